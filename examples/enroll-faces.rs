@@ -0,0 +1,105 @@
+use rust_opencv::{
+    config::Config,
+    detection,
+    error::{Error, Result},
+    recognition::{self, PersonRecognizer, RecognitionSettings},
+};
+use std::{collections::BTreeMap, env, path::PathBuf, process};
+
+///
+#[derive(Debug)]
+struct CliOptions {
+    /// One folder per person, named after the person
+    people_dir: PathBuf,
+    /// `None` means using the default detector and recognition settings
+    config_file: Option<PathBuf>,
+    /// `None` means the `recognition.model_file` of the config
+    model_file: Option<PathBuf>,
+}
+
+/// Supported arguments:
+///
+/// `--people <dir>`  - One folder per person (named after the person) with their photos
+/// `--config <file>` - Use the `detector`, `postprocess`, `alignment` and `recognition` settings
+/// `--model <file>`  - Where to save the model (default: `recognition.model_file`)
+fn parse_cli_options() -> Result<CliOptions> {
+    let mut people_dir = None;
+    let mut options = CliOptions {
+        people_dir: PathBuf::new(),
+        config_file: None,
+        model_file: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--people" => people_dir = args.next().map(PathBuf::from),
+            "--config" => options.config_file = args.next().map(PathBuf::from),
+            "--model" => options.model_file = args.next().map(PathBuf::from),
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+
+    options.people_dir =
+        people_dir.ok_or_else(|| Error::Config(String::from("'--people' is required")))?;
+
+    Ok(options)
+}
+
+///
+fn enroll_faces() -> Result<()> {
+    let options = parse_cli_options()?;
+    let config = match options.config_file.as_ref() {
+        Some(config_file) => Config::load(config_file)?,
+        None => Config::default(),
+    };
+    let recognition_settings = RecognitionSettings {
+        model_file: options
+            .model_file
+            .clone()
+            .unwrap_or_else(|| config.recognition.model_file.clone()),
+        ..config.recognition.clone()
+    };
+
+    let mut face = detection::load_face_classifier()?;
+    let enrollment = recognition::collect_enrollment_samples(
+        &mut face,
+        &options.people_dir,
+        &config.detector,
+        &config.postprocess,
+        &config.alignment,
+    )?;
+
+    for skipped_image in enrollment.skipped.iter() {
+        println!("Skipped (no face found): {}", skipped_image.display());
+    }
+    let mut samples_per_person = BTreeMap::new();
+    for (name, _) in enrollment.samples.iter() {
+        *samples_per_person.entry(name.as_str()).or_insert(0) += 1;
+    }
+    for (name, sample_count) in samples_per_person.iter() {
+        println!("{}: {} face samples", name, sample_count);
+    }
+
+    let recognizer = PersonRecognizer::train(
+        &enrollment.samples,
+        recognition_settings.max_distance,
+        &config.alignment,
+    )?;
+    recognizer.save(&recognition_settings.model_file)?;
+    println!(
+        "Enrolled {} people, model saved: {}",
+        samples_per_person.len(),
+        recognition_settings.model_file.display()
+    );
+
+    Ok(())
+}
+
+///
+fn main() {
+    if let Err(error) = enroll_faces() {
+        println!("Enroll faces abnormally: {}", error);
+        process::exit(error.exit_code());
+    }
+}
@@ -0,0 +1,278 @@
+use rust_opencv::{
+    config::Config,
+    dataset::{self, AnnotationFormat},
+    detection,
+    error::{Error, Result},
+    evaluation::{self, EvaluationReport, DEFAULT_IOU_THRESHOLDS},
+    sweep::{self, SweepGrid, SweepResult},
+};
+use serde::Serialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// How many of the worst images are printed, the report file has all of them
+const PRINTED_IMAGE_ERRORS: usize = 10;
+
+///
+#[derive(Debug)]
+struct CliOptions {
+    images_dir: PathBuf,
+    annotations: PathBuf,
+    format: AnnotationFormat,
+    /// `None` means using the default detector settings
+    config_file: Option<PathBuf>,
+    iou_thresholds: Vec<f64>,
+    /// `None` means only printing the summary
+    report_file: Option<PathBuf>,
+    /// Try every grid point instead of the config settings
+    sweep: bool,
+    /// `None` means using the default grid
+    sweep_grid_file: Option<PathBuf>,
+}
+
+/// Supported arguments:
+///
+/// `--images <dir>`              - The folder the annotated image names are relative to
+/// `--annotations <file|dir>`    - Ground truth file (a folder of XML files for `voc`)
+/// `--format <wider|voc|coco>`   - Ground truth format (default: wider)
+/// `--config <file>`             - Use the `detector` and `postprocess` settings of the config
+/// `--iou <t1,t2,..>`            - IoU thresholds (default: 0.5,0.75)
+/// `--report <file>`             - Write the full report as JSON
+/// `--sweep`                     - Try a grid of detector settings, report the Pareto front
+/// `--sweep-grid <file>`         - JSON grid for `--sweep` (default: the built-in grid)
+fn parse_cli_options() -> Result<CliOptions> {
+    let mut images_dir = None;
+    let mut annotations = None;
+    let mut options = CliOptions {
+        images_dir: PathBuf::new(),
+        annotations: PathBuf::new(),
+        format: AnnotationFormat::WiderFace,
+        config_file: None,
+        iou_thresholds: DEFAULT_IOU_THRESHOLDS.to_vec(),
+        report_file: None,
+        sweep: false,
+        sweep_grid_file: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--images" => images_dir = args.next().map(PathBuf::from),
+            "--annotations" => annotations = args.next().map(PathBuf::from),
+            "--config" => options.config_file = args.next().map(PathBuf::from),
+            "--report" => options.report_file = args.next().map(PathBuf::from),
+            "--sweep" => options.sweep = true,
+            "--sweep-grid" => {
+                options.sweep = true;
+                options.sweep_grid_file = args.next().map(PathBuf::from);
+            }
+            "--format" => {
+                options.format = args
+                    .next()
+                    .and_then(|value| AnnotationFormat::from_name(&value))
+                    .ok_or_else(|| {
+                        Error::Config(String::from("'--format' needs wider, voc or coco"))
+                    })?;
+            }
+            "--iou" => {
+                options.iou_thresholds = args
+                    .next()
+                    .map(|value| {
+                        value
+                            .split(',')
+                            .filter_map(|threshold| threshold.trim().parse::<f64>().ok())
+                            .filter(|threshold| *threshold > 0. && *threshold <= 1.)
+                            .collect::<Vec<f64>>()
+                    })
+                    .filter(|thresholds| !thresholds.is_empty())
+                    .ok_or_else(|| {
+                        Error::Config(String::from(
+                            "'--iou' needs thresholds in (0, 1] like 0.5,0.75",
+                        ))
+                    })?;
+            }
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+
+    options.images_dir =
+        images_dir.ok_or_else(|| Error::Config(String::from("'--images' is required")))?;
+    options.annotations =
+        annotations.ok_or_else(|| Error::Config(String::from("'--annotations' is required")))?;
+
+    Ok(options)
+}
+
+///
+fn print_summary(report: &EvaluationReport) {
+    println!(
+        "Images: {}, faces: {}, detected: {}, detection: {:.1} ms/image",
+        report.image_count,
+        report.ground_truth_faces,
+        report.detected_faces,
+        report.mean_detection_ms
+    );
+
+    println!(
+        "{:<8}{:<8}{:<8}{:<8}{:<12}{:<10}{}",
+        "IoU", "TP", "FP", "FN", "Precision", "Recall", "AP"
+    );
+    for metrics in report.thresholds.iter() {
+        println!(
+            "{:<8}{:<8}{:<8}{:<8}{:<12}{:<10}{:.3}",
+            metrics.iou_threshold,
+            metrics.true_positives,
+            metrics.false_positives,
+            metrics.false_negatives,
+            format!("{:.3}", metrics.precision),
+            format!("{:.3}", metrics.recall),
+            metrics.average_precision
+        );
+    }
+    println!("mAP: {:.3}", report.mean_average_precision);
+
+    if !report.image_errors.is_empty() {
+        println!(
+            "Images with errors: {} (worst {} below)",
+            report.image_errors.len(),
+            PRINTED_IMAGE_ERRORS.min(report.image_errors.len())
+        );
+        for errors in report.image_errors.iter().take(PRINTED_IMAGE_ERRORS) {
+            println!(
+                "  {}: {} faces, {} false positives, {} missed",
+                errors.file_name,
+                errors.ground_truth_faces,
+                errors.false_positives.len(),
+                errors.missed_faces.len()
+            );
+        }
+    }
+}
+
+///
+fn format_settings(result: &SweepResult) -> String {
+    format!(
+        "scale {} / neighbors {} / min size {} / downscale {}",
+        result.settings.scale_factor,
+        result.settings.min_neighbors,
+        result.settings.min_face_size,
+        result.settings.downscale
+    )
+}
+
+/// The Pareto front only, fastest first, the report file has every grid point
+fn print_pareto_front(results: &[SweepResult]) {
+    println!("Pareto front (no other settings are both faster and more accurate):");
+    println!(
+        "{:<12}{:<8}{:<12}{:<10}{}",
+        "ms/image", "AP", "Precision", "Recall", "Settings"
+    );
+    for result in results.iter().filter(|result| result.is_pareto_optimal) {
+        println!(
+            "{:<12}{:<8}{:<12}{:<10}{}",
+            format!("{:.1}", result.mean_detection_ms),
+            format!("{:.3}", result.average_precision),
+            format!("{:.3}", result.precision),
+            format!("{:.3}", result.recall),
+            format_settings(result)
+        );
+    }
+}
+
+///
+fn load_sweep_grid(file_name: &Path) -> Result<SweepGrid> {
+    let content = fs::read_to_string(file_name).map_err(|error| {
+        Error::io(
+            format!("Unable to read sweep grid '{}'", file_name.display()),
+            error,
+        )
+    })?;
+    serde_json::from_str(&content)
+        .map_err(|error| Error::Config(format!("'{}': {}", file_name.display(), error)))
+}
+
+///
+fn save_report<T: Serialize>(report: &T, report_file: &Path) -> Result<()> {
+    let report_json = serde_json::to_string_pretty(report)
+        .map_err(|error| Error::Config(format!("Unable to serialize report: {}", error)))?;
+    fs::write(report_file, report_json).map_err(|error| {
+        Error::io(
+            format!("Unable to write report '{}'", report_file.display()),
+            error,
+        )
+    })?;
+    println!("Report saved: {}", report_file.display());
+    Ok(())
+}
+
+///
+fn evaluate_detection() -> Result<()> {
+    let options = parse_cli_options()?;
+    let config = match options.config_file.as_ref() {
+        Some(config_file) => Config::load(config_file)?,
+        None => Config::default(),
+    };
+
+    let annotations = dataset::load_annotations(options.format, &options.annotations)?;
+    println!(
+        "Loaded {} annotated images from {}",
+        annotations.len(),
+        options.annotations.display()
+    );
+
+    // Read once, the sweep runs the detector on every image many times
+    let images = evaluation::load_dataset(&options.images_dir, &annotations)?;
+    let mut face = detection::load_face_classifier()?;
+
+    if options.sweep {
+        let grid = match options.sweep_grid_file.as_ref() {
+            Some(grid_file) => load_sweep_grid(grid_file)?,
+            None => SweepGrid::default(),
+        };
+        let results = sweep::run_sweep(
+            &mut face,
+            &images,
+            &grid,
+            &config.postprocess,
+            &options.iou_thresholds,
+            |done, total, result| {
+                println!(
+                    "[{}/{}] {}: {:.1} ms/image, AP {:.3}",
+                    done,
+                    total,
+                    format_settings(result),
+                    result.mean_detection_ms,
+                    result.average_precision
+                )
+            },
+        )?;
+        print_pareto_front(&results);
+
+        if let Some(report_file) = options.report_file.as_ref() {
+            save_report(&results, report_file)?;
+        }
+        return Ok(());
+    }
+
+    let detections =
+        evaluation::detect_dataset(&mut face, &images, &config.detector, &config.postprocess)?;
+    let report = evaluation::evaluate(&detections, &options.iou_thresholds);
+    print_summary(&report);
+
+    if let Some(report_file) = options.report_file.as_ref() {
+        save_report(&report, report_file)?;
+    }
+
+    Ok(())
+}
+
+///
+fn main() {
+    if let Err(error) = evaluate_detection() {
+        println!("Evaluate detection abnormally: {}", error);
+        process::exit(error.exit_code());
+    }
+}
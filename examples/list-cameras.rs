@@ -0,0 +1,102 @@
+use rust_opencv::{
+    camera::{self, CameraInfo},
+    error::{Error, Result},
+};
+use std::{env, process};
+
+///
+#[derive(Debug)]
+struct CliOptions {
+    /// Try camera index `0..max_index`
+    max_index: i32,
+    /// Print JSON instead of a table
+    json: bool,
+}
+
+/// Supported arguments:
+///
+/// `--max-index <n>` - Try camera index `0..n` with every backend (default: 5)
+/// `--json`          - Print JSON instead of a table
+fn parse_cli_options() -> Result<CliOptions> {
+    let mut options = CliOptions {
+        max_index: 5,
+        json: false,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-index" => {
+                options.max_index = args
+                    .next()
+                    .and_then(|value| value.parse::<i32>().ok())
+                    .ok_or_else(|| Error::Config(String::from("'--max-index' needs a number")))?;
+            }
+            "--json" => options.json = true,
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+
+    Ok(options)
+}
+
+///
+fn format_resolution(resolution: &(i32, i32)) -> String {
+    format!("{}x{}", resolution.0, resolution.1)
+}
+
+///
+fn print_camera_table(cameras: &[CameraInfo]) {
+    if cameras.is_empty() {
+        println!("No camera found.");
+        return;
+    }
+
+    println!(
+        "{:<6}{:<14}{:<12}{:<8}{:<8}{}",
+        "Index", "Backend", "Default", "FPS", "Format", "Supported resolutions"
+    );
+    for info in cameras {
+        println!(
+            "{:<6}{:<14}{:<12}{:<8}{:<8}{}",
+            info.index,
+            info.backend,
+            format_resolution(&info.default_resolution),
+            format!("{:.1}", info.fps),
+            if info.pixel_format.is_empty() {
+                "-"
+            } else {
+                info.pixel_format.as_str()
+            },
+            info.supported_resolutions
+                .iter()
+                .map(format_resolution)
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+    }
+}
+
+///
+fn list_cameras() -> Result<()> {
+    let options = parse_cli_options()?;
+    let cameras = camera::probe_cameras(options.max_index)?;
+
+    if options.json {
+        let cameras_json = serde_json::to_string_pretty(&cameras)
+            .map_err(|error| Error::Config(format!("Unable to serialize cameras: {}", error)))?;
+        println!("{}", cameras_json);
+    } else {
+        print_camera_table(&cameras);
+    }
+
+    Ok(())
+}
+
+///
+fn main() {
+    if let Err(error) = list_cameras() {
+        println!("List cameras abnormally: {}", error);
+        process::exit(error.exit_code());
+    }
+}
@@ -0,0 +1,399 @@
+use opencv::{core, highgui, prelude::*, videoio};
+use rust_opencv::{
+    capture::{self, CaptureState, ReconnectStatus, ReconnectingCapture},
+    config::Config,
+    detection,
+    display::{self, DisplayBackend},
+    error::{Context, Error, Result},
+    fps::FpsCounter,
+    grid, overlay,
+    scheduler::DetectionScheduler,
+};
+use std::{
+    env,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const WINDOW_NAME: &'static str = "Multi Camera Grid";
+/// The grid is refreshed (and written into `--output`) at this rate
+const GRID_FPS: f64 = 25.;
+
+///
+#[derive(Debug)]
+struct CliOptions {
+    /// Camera indexes or video file names
+    sources: Vec<String>,
+    /// `None` means using the default settings
+    config_file: Option<PathBuf>,
+    /// `None` means as square as possible
+    columns: Option<i32>,
+    tile_size: core::Size,
+    /// `None` means only showing the grid in the window
+    output_file: Option<PathBuf>,
+    display_backend: DisplayBackend,
+}
+
+/// Supported arguments:
+///
+/// `--source <index|file>` - Camera index or video file, repeat it for every source (default: 0)
+/// `--config <file>`       - Camera, reconnect and detector settings for all sources
+/// `--columns <n>`         - Grid columns (default: as square as possible)
+/// `--tile-size <WxH>`     - The size of every tile (default: 640x360)
+/// `--output <file>`       - Write the grid into a video file as well
+/// `--display <name>`      - Preview window: `highgui` (default) or `minifb`
+fn parse_cli_options() -> Result<CliOptions> {
+    let mut options = CliOptions {
+        sources: Vec::new(),
+        config_file: None,
+        columns: None,
+        tile_size: core::Size::new(640, 360),
+        output_file: None,
+        display_backend: DisplayBackend::default(),
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => {
+                if let Some(source) = args.next() {
+                    options.sources.push(source);
+                }
+            }
+            "--config" => options.config_file = args.next().map(PathBuf::from),
+            "--output" => options.output_file = args.next().map(PathBuf::from),
+            "--columns" => {
+                options.columns = Some(
+                    args.next()
+                        .and_then(|value| value.parse::<i32>().ok())
+                        .filter(|columns| *columns > 0)
+                        .ok_or_else(|| {
+                            Error::Config(String::from("'--columns' needs a positive number"))
+                        })?,
+                );
+            }
+            "--display" => {
+                let name = args.next().unwrap_or_default();
+                options.display_backend = DisplayBackend::from_name(&name).ok_or_else(|| {
+                    Error::Config(format!("Unknown display '{}', use highgui or minifb", name))
+                })?;
+            }
+            "--tile-size" => {
+                options.tile_size = args
+                    .next()
+                    .and_then(|value| parse_size(&value))
+                    .ok_or_else(|| {
+                        Error::Config(String::from("'--tile-size' needs a size like 640x360"))
+                    })?;
+            }
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+
+    if options.sources.is_empty() {
+        options.sources.push(String::from("0"));
+    }
+
+    Ok(options)
+}
+
+/// `"640x360"` -> `Size(640, 360)`
+fn parse_size(value: &str) -> Option<core::Size> {
+    let mut parts = value.split('x');
+    let width = parts.next()?.parse::<i32>().ok()?;
+    let height = parts.next()?.parse::<i32>().ok()?;
+    if width > 0 && height > 0 {
+        Some(core::Size::new(width, height))
+    } else {
+        None
+    }
+}
+
+/// The latest state of one source, written by its capture thread, read by the grid
+#[derive(Default)]
+struct TileState {
+    /// The latest good frame with the faces drawn
+    frame: Option<Mat>,
+    frame_width: i32,
+    frame_height: i32,
+    fps: f64,
+    detected_faces: usize,
+    /// `Some` while the source is reconnecting
+    reconnect_status: Option<ReconnectStatus>,
+    /// End of the video file or the source failed, the tile keeps the last frame
+    finished_message: Option<String>,
+}
+
+type SharedTile = Arc<Mutex<TileState>>;
+
+/// Every source gets its own capture thread (and classifier), so a slow or reconnecting
+/// source doesn't hold back the others.
+fn capture_source(
+    source: &str,
+    config: &Config,
+    tile: &SharedTile,
+    is_stopped: &AtomicBool,
+) -> Result<()> {
+    let mut cam =
+        ReconnectingCapture::open(source, config.camera.clone(), config.reconnect.clone())?;
+    let mut face = detection::load_face_classifier()?;
+    let mut fps_counter = FpsCounter::default();
+    // Every camera skips the detection on its own (the `scheduler` config)
+    let mut face_scheduler = DetectionScheduler::new(&config.scheduler);
+    // Cameras deliver frames in real time, video files would play at decode speed
+    let file_frame_duration = match cam.get(videoio::CAP_PROP_FPS)? {
+        fps if cam.is_video_file() && fps > 0. => Some(Duration::from_secs_f64(1. / fps)),
+        _ => None,
+    };
+
+    while !is_stopped.load(Ordering::Relaxed) {
+        let read_started_at = Instant::now();
+        let mut video_frame = core::Mat::default()?;
+        match cam.read(&mut video_frame)? {
+            CaptureState::Frame => {
+                let frame_started_at = Instant::now();
+                let detected_faces = if face_scheduler.should_detect() {
+                    let detected_faces = detection::detect_faces(
+                        &mut face,
+                        &video_frame,
+                        &config.detector,
+                        &config.postprocess,
+                    )?;
+                    face_scheduler.on_detection(
+                        &detected_faces,
+                        frame_started_at.elapsed().as_secs_f64() * 1000.,
+                    );
+                    detected_faces
+                } else {
+                    face_scheduler.predicted_faces(video_frame.size()?)
+                };
+                let face_rects: Vec<core::Rect> = detected_faces
+                    .iter()
+                    .map(|detected_face| detected_face.rect)
+                    .collect();
+                overlay::draw_face_rects(&mut video_frame, &face_rects)?;
+                face_scheduler.on_frame_processed(frame_started_at.elapsed().as_secs_f64() * 1000.);
+
+                let mut tile = tile.lock().unwrap();
+                tile.frame_width = video_frame.cols();
+                tile.frame_height = video_frame.rows();
+                tile.fps = fps_counter.tick();
+                tile.detected_faces = face_rects.len();
+                tile.reconnect_status = None;
+                tile.frame = Some(video_frame);
+                drop(tile);
+
+                if let Some(frame_duration) = file_frame_duration {
+                    let elapsed = read_started_at.elapsed();
+                    if elapsed < frame_duration {
+                        thread::sleep(frame_duration - elapsed);
+                    }
+                }
+            }
+            CaptureState::Reconnecting(status) => {
+                tile.lock().unwrap().reconnect_status = Some(status);
+                thread::sleep(Duration::from_millis(100));
+            }
+            CaptureState::EndOfFile => {
+                tile.lock().unwrap().finished_message = Some(String::from("End of video"));
+                break;
+            }
+        }
+    }
+
+    cam.release()
+}
+
+/// The tile to show for one source: the frame (or the reconnecting frame) resized into the
+/// grid cell, with the info panel on top.
+fn draw_tile(source: &str, tile: &SharedTile, tile_size: core::Size) -> Result<Mat> {
+    let tile = tile.lock().unwrap();
+    let empty_frame = Mat::default()?;
+    let last_frame = tile.frame.as_ref().unwrap_or(&empty_frame);
+
+    let mut tile_frame = match tile.reconnect_status.as_ref() {
+        Some(status) => {
+            let reconnecting_frame = capture::draw_reconnecting_frame(
+                last_frame,
+                status,
+                tile_size.width,
+                tile_size.height,
+            )?;
+            grid::resize_to_tile(&reconnecting_frame, tile_size)?
+        }
+        None if last_frame.size()?.width > 0 => grid::resize_to_tile(last_frame, tile_size)?,
+        None => Mat::new_rows_cols_with_default(
+            tile_size.height,
+            tile_size.width,
+            core::CV_8UC3,
+            core::Scalar::all(0.),
+        )
+        .context("Create empty tile")?,
+    };
+
+    // The panel sits on the tile, the resolution line shows the tile size
+    let mut extra_info = vec![format!(
+        "Source: {} ({}x{})",
+        source, tile.frame_width, tile.frame_height
+    )];
+    if let Some(message) = tile.finished_message.as_ref() {
+        extra_info.push(message.clone());
+    }
+    overlay::draw_info_panel(
+        &mut tile_frame,
+        tile_size.width,
+        tile_size.height,
+        tile.fps.round() as i32,
+        tile.detected_faces as u8,
+        &extra_info,
+    )?;
+
+    Ok(tile_frame)
+}
+
+///
+fn show_multi_camera_grid(options: CliOptions) -> Result<()> {
+    let config = match options.config_file.as_ref() {
+        Some(config_file) => Config::load(config_file)?,
+        None => Config::default(),
+    };
+    let columns = options
+        .columns
+        .unwrap_or_else(|| grid::grid_columns(options.sources.len()));
+
+    let window_flags = highgui::WINDOW_AUTOSIZE
+        | highgui::WINDOW_KEEPRATIO
+        | highgui::WINDOW_OPENGL
+        | highgui::WINDOW_NORMAL;
+    let mut display = display::open_display(options.display_backend, WINDOW_NAME, window_flags)?;
+
+    let is_stopped = Arc::new(AtomicBool::new(false));
+    let mut tiles: Vec<SharedTile> = Vec::new();
+    let mut workers = Vec::new();
+    for source in options.sources.iter() {
+        let tile = SharedTile::default();
+        let (worker_source, worker_config, worker_tile, worker_is_stopped) = (
+            source.clone(),
+            config.clone(),
+            Arc::clone(&tile),
+            Arc::clone(&is_stopped),
+        );
+        workers.push(thread::spawn(move || {
+            // One failed source only ends its own tile
+            if let Err(error) = capture_source(
+                &worker_source,
+                &worker_config,
+                &worker_tile,
+                &worker_is_stopped,
+            ) {
+                println!("Source '{}' stopped abnormally: {}", worker_source, error);
+                worker_tile.lock().unwrap().finished_message = Some(error.to_string());
+            }
+        }));
+        tiles.push(tile);
+    }
+
+    let mut writer: Option<videoio::VideoWriter> = None;
+    loop {
+        let mut tile_frames = Vec::new();
+        for (source, tile) in options.sources.iter().zip(tiles.iter()) {
+            tile_frames.push(draw_tile(source, tile, options.tile_size)?);
+        }
+        let grid_frame = grid::compose_grid(&tile_frames, columns, options.tile_size)?;
+        display.show(&grid_frame)?;
+
+        if let Some(output_file) = options.output_file.as_ref() {
+            if writer.is_none() {
+                let fourcc =
+                    videoio::VideoWriter::fourcc('m' as i8, 'p' as i8, '4' as i8, 'v' as i8)?;
+                let new_writer = videoio::VideoWriter::new(
+                    &output_file.to_string_lossy(),
+                    fourcc,
+                    GRID_FPS,
+                    grid_frame.size()?,
+                    true,
+                )
+                .context("Create grid video writer")?;
+                if !new_writer.is_opened()? {
+                    return Err(Error::Codec(format!(
+                        "Unable to open grid video for writing: {}",
+                        output_file.display()
+                    )));
+                }
+                writer = Some(new_writer);
+            }
+            if let Some(writer) = writer.as_mut() {
+                writer.write(&grid_frame).context("Write grid frame")?;
+            }
+        }
+
+        // All sources are done (e.g. all video files ended)
+        if tiles
+            .iter()
+            .all(|tile| tile.lock().unwrap().finished_message.is_some())
+        {
+            println!("All sources finished");
+            break;
+        }
+
+        // Press any key to stop
+        let key = display.wait_key((1000. / GRID_FPS) as i32)?;
+        if key > 0 && key != 255 {
+            break;
+        }
+    }
+
+    is_stopped.store(true, Ordering::Relaxed);
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    if let Some(mut writer) = writer {
+        writer.release().context("Release grid video writer")?;
+    }
+
+    Ok(())
+}
+
+///
+fn main() {
+    let options = match parse_cli_options() {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Close video capture abnormally: {}", error);
+            process::exit(error.exit_code());
+        }
+    };
+    let display_backend = options.display_backend;
+
+    let capture_result = show_multi_camera_grid(options);
+    let close_capture_successfully = match capture_result.as_ref() {
+        Ok(_) => true,
+        Err(error) => {
+            println!("Close video capture abnormally: {}", error);
+            false
+        }
+    };
+
+    let close_all_window_succesfully = match display::destroy_all_windows(display_backend) {
+        Ok(_) => true,
+        Err(error) => {
+            println!("Close all windows abnormally: {}", error);
+            false
+        }
+    };
+
+    if close_capture_successfully && close_all_window_succesfully {
+        println!("Program exit normally:)");
+    }
+
+    if let Err(error) = capture_result {
+        process::exit(error.exit_code());
+    }
+}
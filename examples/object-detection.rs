@@ -0,0 +1,202 @@
+use opencv::{core, highgui, prelude::*, videoio};
+use rust_opencv::{
+    camera::CameraSettings,
+    capture::{self, CaptureState, ReconnectSettings, ReconnectingCapture},
+    display::{self, DisplayBackend},
+    error::{Error, Result},
+    fps::FpsCounter,
+    object_detection::{ModelKind, ObjectDetector, ObjectDetectorSettings},
+    overlay,
+};
+use std::{env, path::PathBuf, process};
+
+///
+#[derive(Debug)]
+struct CliOptions {
+    /// Camera index or video file
+    source: String,
+    detector_settings: ObjectDetectorSettings,
+    display_backend: DisplayBackend,
+}
+
+/// Supported arguments:
+///
+/// `--source <index|file>`   - Camera index (default: `0`) or video file
+/// `--model <file>`          - `.weights`, `.onnx`, `.caffemodel` or `.pb`
+/// `--model-config <file>`   - `.cfg`, `.prototxt` or `.pbtxt` (not needed for ONNX)
+/// `--classes <file>`        - One class name per line
+/// `--type <yolo|ssd>`       - The model family (default: `yolo`)
+/// `--input-size <pixels>`   - The model input size (default: 416 for YOLO, 300 for SSD)
+/// `--letterbox`             - Pad the frame to the input size instead of stretching it
+/// `--confidence <0.0..1.0>` - Drop the boxes below this confidence (default: 0.5)
+/// `--nms <0.0..1.0>`        - NMS IoU threshold (default: 0.4)
+/// `--display <name>`        - Preview window: `highgui` (default) or `minifb`
+fn parse_cli_options() -> Result<CliOptions> {
+    let mut source = String::from("0");
+    let mut model_file = None;
+    let mut model_config = None;
+    let mut classes_file = None;
+    let mut kind = ModelKind::Yolo;
+    let mut input_size = None;
+    let mut letterbox = false;
+    let mut confidence_threshold = None;
+    let mut nms_threshold = None;
+    let mut display_backend = DisplayBackend::default();
+
+    let parse_number = |flag: &str, value: Option<String>| -> Result<f32> {
+        value
+            .as_ref()
+            .and_then(|value| value.parse::<f32>().ok())
+            .ok_or_else(|| Error::Config(format!("'{}' needs a number", flag)))
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => source = args.next().unwrap_or(source),
+            "--model" => model_file = args.next().map(PathBuf::from),
+            "--model-config" => model_config = args.next().map(PathBuf::from),
+            "--classes" => classes_file = args.next().map(PathBuf::from),
+            "--type" => {
+                let name = args.next().unwrap_or_default();
+                kind = ModelKind::from_name(&name).ok_or_else(|| {
+                    Error::Config(format!("Unknown model type '{}', use yolo or ssd", name))
+                })?;
+            }
+            "--input-size" => input_size = Some(parse_number(&arg, args.next())? as i32),
+            "--letterbox" => letterbox = true,
+            "--confidence" => confidence_threshold = Some(parse_number(&arg, args.next())?),
+            "--nms" => nms_threshold = Some(parse_number(&arg, args.next())?),
+            "--display" => {
+                let name = args.next().unwrap_or_default();
+                display_backend = DisplayBackend::from_name(&name).ok_or_else(|| {
+                    Error::Config(format!("Unknown display '{}', use highgui or minifb", name))
+                })?;
+            }
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+
+    let model_file =
+        model_file.ok_or_else(|| Error::Config(String::from("'--model' is required")))?;
+    let classes_file =
+        classes_file.ok_or_else(|| Error::Config(String::from("'--classes' is required")))?;
+
+    let mut detector_settings = ObjectDetectorSettings::new(kind, model_file, classes_file);
+    detector_settings.model_config = model_config;
+    detector_settings.letterbox = letterbox;
+    if let Some(input_size) = input_size {
+        detector_settings.input_size = input_size;
+    }
+    if let Some(confidence_threshold) = confidence_threshold {
+        detector_settings.confidence_threshold = confidence_threshold;
+    }
+    if let Some(nms_threshold) = nms_threshold {
+        detector_settings.nms_threshold = nms_threshold;
+    }
+
+    Ok(CliOptions {
+        source,
+        detector_settings,
+        display_backend,
+    })
+}
+
+///
+fn run_object_detection(options: CliOptions) -> Result<()> {
+    let mut detector = ObjectDetector::new(options.detector_settings)?;
+
+    let mut display = display::open_display(
+        options.display_backend,
+        "Object Detection Window",
+        highgui::WINDOW_AUTOSIZE,
+    )?;
+
+    let mut cam = ReconnectingCapture::open(
+        &options.source,
+        CameraSettings::default(),
+        ReconnectSettings::default(),
+    )?;
+    let cam_width = cam.get(videoio::CAP_PROP_FRAME_WIDTH)?;
+    let cam_height = cam.get(videoio::CAP_PROP_FRAME_HEIGHT)?;
+
+    let mut fps_counter = FpsCounter::default();
+    let mut last_frame = Mat::default()?;
+
+    loop {
+        let mut frame = core::Mat::default()?;
+        match cam.read(&mut frame)? {
+            CaptureState::Reconnecting(status) => {
+                let reconnecting_frame = capture::draw_reconnecting_frame(
+                    &last_frame,
+                    &status,
+                    cam_width as i32,
+                    cam_height as i32,
+                )?;
+                display.show(&reconnecting_frame)?;
+            }
+            CaptureState::EndOfFile => break,
+            CaptureState::Frame => {
+                let objects = detector.detect(&frame)?;
+                let fps = fps_counter.tick();
+
+                last_frame = frame.clone();
+                overlay::draw_detected_objects(&mut frame, &objects)?;
+                overlay::draw_text_on_image(
+                    &mut frame,
+                    &format!("FPS: {:.0}  Objects: {}", fps, objects.len()),
+                    core::Point::new(10, 30),
+                    0.8,
+                    1,
+                    core::Scalar::new(0f64, 255f64, 0f64, -1f64), // (Blue, Green, Red, Alpha)
+                )?;
+                display.show(&frame)?;
+            }
+        }
+
+        // Press any key to stop
+        let key = display.wait_key(10)?;
+        if key > 0 && key != 255 {
+            break;
+        }
+    }
+
+    cam.release()
+}
+
+///
+fn main() {
+    let options = match parse_cli_options() {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Object detection abnormally: {}", error);
+            process::exit(error.exit_code());
+        }
+    };
+    let display_backend = options.display_backend;
+
+    let capture_result = run_object_detection(options);
+    let close_capture_successfully = match capture_result.as_ref() {
+        Ok(_) => true,
+        Err(error) => {
+            println!("Object detection abnormally: {}", error);
+            false
+        }
+    };
+
+    let close_all_window_succesfully = match display::destroy_all_windows(display_backend) {
+        Ok(_) => true,
+        Err(error) => {
+            println!("Close all windows abnormally: {}", error);
+            false
+        }
+    };
+
+    if close_capture_successfully && close_all_window_succesfully {
+        println!("Program exit normally:)");
+    }
+
+    if let Err(error) = capture_result {
+        process::exit(error.exit_code());
+    }
+}
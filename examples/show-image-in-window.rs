@@ -1,40 +1,41 @@
 use opencv::{core, highgui, imgcodecs, prelude::*};
-use std::env;
+use rust_opencv::error::{Context, Error, Result};
+use std::{env, process};
 
 /// For all available `Matrix`, plz have a look at:
 /// https://docs.rs/opencv/0.46.3/opencv/core/prelude/trait.MatTrait.html
-fn show_image_info(matrix: &core::Mat) {
+fn show_image_info(matrix: &core::Mat) -> Result<()> {
     let mut messages = String::new();
     messages.push_str("\n[ Image Info ]:");
 
-    let size_result = matrix.size();
-    let size = size_result.as_ref().unwrap();
+    let size = matrix.size().context("Get image size")?;
     messages.push_str(format!("\nresulotion: {} x {}", size.width, size.height).as_str());
 
-    let channels_result = matrix.channels();
-    let channels = channels_result.as_ref().unwrap();
-    messages.push_str(&format!("\nIs grayscale: {}", *channels == 1));
+    let channels = matrix.channels().context("Get image channels")?;
+    messages.push_str(&format!("\nIs grayscale: {}", channels == 1));
 
     messages.push_str(&format!("\nDimension: {}", matrix.dims()));
     println!("{}", messages);
+    Ok(())
 }
 
 ///
-fn show_image_from_file(file_name: &str, image_read_mode: i32) {
+fn show_image_from_file(file_name: &str, image_read_mode: i32) -> Result<()> {
     // Setup render window
     let window_name = "Image Preview";
 
     // `imread` (Image Read) return a `n-demensional Matrix` which contains all image pixels
     // and extra data
-    let image = imgcodecs::imread(file_name, image_read_mode).unwrap();
+    let image = imgcodecs::imread(file_name, image_read_mode)
+        .context(format!("Read image '{}'", file_name))?;
     println!("image: {:#?}", &image);
 
-    if image.empty().unwrap() {
-        println!("\nImage load failed: {}\n", file_name);
-        return;
+    // `imread` doesn't fail on a missing or broken file, it returns an empty `Matrix`
+    if image.empty()? {
+        return Err(Error::Codec(format!("Image load failed: {}", file_name)));
     }
 
-    show_image_info(&image);
+    show_image_info(&image)?;
 
     // highgui::named_window(window_name, highgui::WindowFlags::WINDOW_AUTOSIZE as i32).unwrap();
 
@@ -50,18 +51,20 @@ fn show_image_from_file(file_name: &str, image_read_mode: i32) {
         | highgui::WINDOW_KEEPRATIO
         | highgui::WINDOW_OPENGL
         | highgui::WINDOW_GUI_NORMAL;
-    highgui::named_window(window_name, window_flags).unwrap();
+    highgui::named_window(window_name, window_flags).context("Create preview window")?;
 
-    highgui::imshow(window_name, &image).unwrap();
-    highgui::wait_key(10000).unwrap();
+    highgui::imshow(window_name, &image).context("Show image")?;
+    highgui::wait_key(10000)?;
+    Ok(())
 }
 
 ///
 fn main() {
     let image_file_name = env::args().nth(1);
     if let None = image_file_name {
-        println!("\nPlease provide an image file name:)\n");
-        return;
+        let error = Error::Config(String::from("Please provide an image file name:)"));
+        println!("\n{}\n", error);
+        process::exit(error.exit_code());
     }
 
     // imgcodecs::IMREAD_UNCHANGED           - return the loaded image as is (with alpha channel, otherwise it gets cropped). Ignore EXIF orientation.
@@ -80,5 +83,8 @@ fn main() {
     let read_mode = imgcodecs::IMREAD_COLOR;
     // let read_mode = imgcodecs::IMREAD_GRAYSCALE;
 
-    show_image_from_file(image_file_name.unwrap().as_str(), read_mode);
+    if let Err(error) = show_image_from_file(image_file_name.unwrap().as_str(), read_mode) {
+        println!("\n{}\n", error);
+        process::exit(error.exit_code());
+    }
 }
@@ -1,9 +1,16 @@
 use opencv::{core, highgui, imgproc, prelude::*, videoio};
+use rust_opencv::{
+    camera::CameraSettings,
+    capture::{self, CaptureState, ReconnectSettings, ReconnectingCapture},
+    display::{self, DisplayBackend},
+    error::{Context, Error, Result},
+};
+use std::{env, process};
 
 const TIPS: &'static str = "Press 'g' to toggle grayscale mode\nPress any key to exit";
 
 ///
-fn draw_tips_on_frame(frame_image: &mut Mat) {
+fn draw_tips_on_frame(frame_image: &mut Mat) -> Result<()> {
     let text_list = TIPS.split("\n");
     let text_coord = (5, 30); // X, Y
     let mut text_drawing_coord_y = text_coord.1;
@@ -29,7 +36,7 @@ fn draw_tips_on_frame(frame_image: &mut Mat) {
             font_thickness,
             &mut base_line,
         )
-        .unwrap();
+        .context("Get tips text size")?;
 
         // let center_coord = core::Point::new(
         // (frame_image.cols() - tip_size.width) / 2,
@@ -42,7 +49,7 @@ fn draw_tips_on_frame(frame_image: &mut Mat) {
             core::Point::new(text_coord.0, text_drawing_coord_y)
         };
 
-        let _ = imgproc::put_text(
+        imgproc::put_text(
             frame_image,
             temp_text,
             drawing_coord,
@@ -53,57 +60,87 @@ fn draw_tips_on_frame(frame_image: &mut Mat) {
             imgproc::LINE_AA,
             false,
         )
-        .unwrap();
+        .context("Draw tips")?;
     }
+
+    Ok(())
+}
+
+/// Supported arguments:
+///
+/// `--display <name>` - Preview window: `highgui` (default) or `minifb`
+fn parse_display_backend() -> Result<DisplayBackend> {
+    let mut display_backend = DisplayBackend::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--display" => {
+                let name = args.next().unwrap_or_default();
+                display_backend = DisplayBackend::from_name(&name).ok_or_else(|| {
+                    Error::Config(format!("Unknown display '{}', use highgui or minifb", name))
+                })?;
+            }
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+    Ok(display_backend)
 }
 
 ///
-fn capture_from_web_cam() -> opencv::Result<()> {
+fn capture_from_web_cam(display_backend: DisplayBackend) -> Result<()> {
     // Setup render window
-    let window_name = "Web Cam Preview Window";
     let window_flags = highgui::WINDOW_AUTOSIZE
         | highgui::WINDOW_KEEPRATIO
         | highgui::WINDOW_OPENGL
         | highgui::WINDOW_NORMAL;
-    highgui::named_window(window_name, window_flags).unwrap();
+    let mut display =
+        display::open_display(display_backend, "Web Cam Preview Window", window_flags)?;
 
     // Create video capture (camera), `0` means default webcam.
     // You can pass `1` for the second camera, `2` for the third camera.
-    #[cfg(not(feature = "opencv-32"))]
-    let mut cam = videoio::VideoCapture::new(0, videoio::CAP_ANY)?;
-
-    // prelude::VideoCaptureTrait (open, open_file, is_opened, read, etc...)
-    let is_cam_opened = videoio::VideoCapture::is_opened(&cam)?;
-    if !is_cam_opened {
-        panic!("Unable to open default web camera");
-    }
+    // A stalled or disconnected camera is re-opened with backoff, it gives up after 10 attempts.
+    let mut cam =
+        ReconnectingCapture::open("0", CameraSettings::default(), ReconnectSettings::default())?;
+    let cam_width = cam.get(videoio::CAP_PROP_FRAME_WIDTH)?;
+    let cam_height = cam.get(videoio::CAP_PROP_FRAME_HEIGHT)?;
 
     let mut grayscale_mode = false;
+    // Shown (dimmed) while reconnecting
+    let mut last_frame = Mat::default()?;
 
     loop {
         // Render every frame into preview window
         let mut video_frame = core::Mat::default()?;
-        cam.read(&mut video_frame)?;
-        if video_frame.size()?.width > 0 {
-            draw_tips_on_frame(&mut video_frame);
+        let capture_state = cam.read(&mut video_frame)?;
+        if let CaptureState::Reconnecting(status) = capture_state {
+            let reconnecting_frame = capture::draw_reconnecting_frame(
+                &last_frame,
+                &status,
+                cam_width as i32,
+                cam_height as i32,
+            )?;
+            display.show(&reconnecting_frame)?;
+        } else if video_frame.size()?.width > 0 {
+            draw_tips_on_frame(&mut video_frame)?;
 
             if grayscale_mode {
                 let mut grayscale_frame = Mat::default()?;
                 // Do a color conversion from `BRG(blue Red Green 3 channels)` to `Grayscale`(1 channel)
-                let _ = imgproc::cvt_color(
+                imgproc::cvt_color(
                     &video_frame,
                     &mut grayscale_frame,
                     imgproc::COLOR_BGR2GRAY,
                     0,
                 )
-                .unwrap();
-                highgui::imshow(window_name, &grayscale_frame)?;
+                .context("Convert frame to grayscale")?;
+                display.show(&grayscale_frame)?;
             } else {
-                highgui::imshow(window_name, &video_frame)?;
+                display.show(&video_frame)?;
             }
+            last_frame = video_frame;
         }
 
-        let key = highgui::wait_key(10)?;
+        let key = display.wait_key(10)?;
         // if key > 0 {
         // println!("You pressed key: {}", &key);
         // }
@@ -130,7 +167,16 @@ fn capture_from_web_cam() -> opencv::Result<()> {
 
 ///
 fn main() {
-    let close_capture_successfully = match capture_from_web_cam() {
+    let display_backend = match parse_display_backend() {
+        Ok(display_backend) => display_backend,
+        Err(error) => {
+            println!("Close video capture abnormally: {}", error);
+            process::exit(error.exit_code());
+        }
+    };
+
+    let capture_result = capture_from_web_cam(display_backend);
+    let close_capture_successfully = match capture_result.as_ref() {
         Ok(_) => true,
         Err(error) => {
             println!("Close video capture abnormally: {}", error);
@@ -138,7 +184,7 @@ fn main() {
         }
     };
 
-    let close_all_window_succesfully = match highgui::destroy_all_windows() {
+    let close_all_window_succesfully = match display::destroy_all_windows(display_backend) {
         Ok(_) => true,
         Err(error) => {
             println!("Close all windows abnormally: {}", error);
@@ -149,4 +195,8 @@ fn main() {
     if close_capture_successfully && close_all_window_succesfully {
         println!("Program exit normally:)");
     }
+
+    if let Err(error) = capture_result {
+        process::exit(error.exit_code());
+    }
 }
@@ -1,55 +1,125 @@
-use opencv::{core, highgui, imgproc, objdetect, prelude::*, types, videoio};
-use std::{thread, time::Duration};
-
-const TIPS: &'static str = "Press 'g' to toggle grayscale mode\nPress any key to exit";
+use opencv::{core, highgui, imgproc, prelude::*, videoio};
+use rust_opencv::{
+    capture::{self, CaptureState, ReconnectingCapture},
+    compare::{CompareMode, CompareView},
+    config::Config,
+    control::{PipelineControl, PipelineSettings, PipelineStatus},
+    counting::CrossingCounter,
+    detection,
+    display::{self, DisplayBackend},
+    error::{Context, Error, Result},
+    events::FaceEventPublisher,
+    export::DetectionReport,
+    face_features::FaceFeatureDetector,
+    filters::{self, Filter},
+    fps::FpsCounter,
+    overlay::{self, draw_info_panel, draw_text_on_image, get_drawing_text_size},
+    playback::PlaybackController,
+    recognition::{PersonRecognizer, Recognition},
+    recording::{EventRecorder, MotionDetector, RecordingSettings},
+    scheduler::{DetectionScheduler, ScheduleMode},
+    streaming::StreamPublisher,
+    tracking::CentroidTracker,
+    zones::{self, ZoneEditor, ZoneMode},
+};
+use std::{
+    env,
+    path::PathBuf,
+    process,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+const TIPS: &'static str = "Press 'g' to toggle grayscale mode\n\
+                            'f' switch filter, 'v' compare view\n\
+                            Click to draw zone, right click to close\n\
+                            'c' clear zones, 'm' zone mode, 's' save\n\
+                            Press any other key to exit";
+const DEFAULT_CONFIG_FILE: &'static str = "config.json";
 const WINDOW_NAME: &'static str = "Web Cam Preview Window";
+const TRACKBAR_NAME: &'static str = "Position";
 
 ///
-fn get_drawing_text_size<'a>(text: &'a str, font_scale: f64, font_thickness: i32) -> core::Size {
-    let mut base_line = 0;
-    imgproc::get_text_size(
-        text,
-        // FONT_HERSHEY_COMPLEX        - normal size serif font
-        // FONT_HERSHEY_COMPLEX_SMALL  - smaller version of FONT_HERSHEY_COMPLEX
-        // FONT_HERSHEY_DUPLEX         - normal size sans-serif font (more complex than FONT_HERSHEY_SIMPLEX)
-        // FONT_HERSHEY_PLAIN          - small size sans-serif font
-        // FONT_HERSHEY_SCRIPT_COMPLEX - more complex variant of FONT_HERSHEY_SCRIPT_SIMPLEX
-        // FONT_HERSHEY_SCRIPT_SIMPLEX - hand-writing style font
-        // FONT_HERSHEY_SIMPLEX        - normal size sans-serif font
-        // FONT_HERSHEY_TRIPLEX        - normal size serif font (more complex than FONT_HERSHEY_COMPLEX)
-        imgproc::FONT_HERSHEY_DUPLEX,
-        font_scale,
-        font_thickness,
-        &mut base_line,
-    )
-    .unwrap()
+#[derive(Debug, Default)]
+struct CliOptions {
+    /// Camera index or video file name, `0` means default webcam
+    source: String,
+    /// `None` means using the default settings
+    config_file: Option<PathBuf>,
+    /// `None` means event recording is disabled
+    recording: Option<RecordingSettings>,
+    /// `None` means the MJPEG stream server is disabled
+    serve_address: Option<String>,
+    /// `None` means the control API server is disabled
+    control_address: Option<String>,
+    display_backend: DisplayBackend,
 }
 
+/// Supported arguments:
 ///
-fn draw_text_on_image<'a>(
-    image: &mut Mat,
-    text: &'a str,
-    left_top_coord: core::Point,
-    font_scale: f64,
-    font_thickness: i32,
-    text_color: core::Scalar,
-) {
-    let _ = imgproc::put_text(
-        image,
-        text,
-        left_top_coord,
-        imgproc::FONT_HERSHEY_DUPLEX,
-        font_scale,
-        text_color,
-        font_thickness,
-        imgproc::LINE_AA,
-        false,
-    )
-    .unwrap();
+/// `--source <index|file>`  - Camera index or video file (default: 0)
+/// `--config <file>`        - Load settings (e.g. zones) from a JSON config file
+/// `--record <dir>`         - Enable event recording, save clips into `dir`
+/// `--pre-roll <seconds>`   - How many seconds to keep before the event (default: 3)
+/// `--post-roll <seconds>`  - How many seconds to keep after the last event (default: 5)
+/// `--serve <address>`      - Serve the annotated frames as MJPEG stream, e.g. `0.0.0.0:8080`
+/// `--control <address>`    - Start the HTTP/JSON control API, e.g. `127.0.0.1:8081`
+/// `--display <name>`       - Preview window: `highgui` (default) or `minifb`, zone drawing
+///                            and the playback trackbar need `highgui`
+fn parse_cli_options() -> Result<CliOptions> {
+    let mut options = CliOptions {
+        source: String::from("0"),
+        ..CliOptions::default()
+    };
+    let mut recording_settings = RecordingSettings::default();
+    let mut recording_enabled = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--source" => {
+                if let Some(source) = args.next() {
+                    options.source = source;
+                }
+            }
+            "--serve" => options.serve_address = args.next(),
+            "--control" => options.control_address = args.next(),
+            "--config" => options.config_file = args.next().map(PathBuf::from),
+            "--display" => {
+                let name = args.next().unwrap_or_default();
+                options.display_backend = DisplayBackend::from_name(&name).ok_or_else(|| {
+                    Error::Config(format!("Unknown display '{}', use highgui or minifb", name))
+                })?;
+            }
+            "--record" => {
+                recording_enabled = true;
+                if let Some(output_dir) = args.next() {
+                    recording_settings.output_dir = PathBuf::from(output_dir);
+                }
+            }
+            "--pre-roll" | "--post-roll" => {
+                let seconds = args.next().and_then(|value| value.parse::<f64>().ok());
+                match seconds {
+                    Some(seconds) if arg == "--pre-roll" => {
+                        recording_settings.pre_roll_seconds = seconds
+                    }
+                    Some(seconds) => recording_settings.post_roll_seconds = seconds,
+                    None => println!("'{}' needs a number of seconds, ignored.", arg),
+                }
+            }
+            _ => println!("Unknown argument ignored: {}", arg),
+        }
+    }
+
+    if recording_enabled {
+        options.recording = Some(recording_settings);
+    }
+
+    Ok(options)
 }
 
 ///
-fn draw_tips_on_frame(frame_image: &mut Mat) {
+fn draw_tips_on_frame(frame_image: &mut Mat) -> Result<()> {
     let text_list = TIPS.split("\n");
     let text_coord = (5, 30); // X, Y
     let mut text_drawing_coord_y = text_coord.1;
@@ -57,7 +127,7 @@ fn draw_tips_on_frame(frame_image: &mut Mat) {
     let font_thickness = 1;
 
     for (index, temp_text) in text_list.enumerate() {
-        let text_size = get_drawing_text_size(temp_text, font_scale, font_thickness);
+        let text_size = get_drawing_text_size(temp_text, font_scale, font_thickness)?;
         let drawing_coord = if index == 0 {
             core::Point::new(text_coord.0, text_drawing_coord_y)
         } else {
@@ -72,264 +142,485 @@ fn draw_tips_on_frame(frame_image: &mut Mat) {
             font_scale,
             font_thickness,
             core::Scalar::new(0f64, 255f64, 0f64, -1f64), // Border color (Blue, Green, Red, Alpha)
-        );
+        )?;
     }
-}
-
-///
-fn face_detection_on_frame(
-    face: &mut objdetect::CascadeClassifier,
-    frame: &Mat,
-) -> opencv::Result<core::Vector<core::Rect>> {
-    // Convert every frame into gray color
-    let mut gray = Mat::default()?;
-    imgproc::cvt_color(&frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)?;
-
-    // Reduce the image size for fast face detection
-    let mut reduced = Mat::default()?;
-    imgproc::resize(
-        &gray,
-        &mut reduced,
-        core::Size {
-            width: 0,
-            height: 0,
-        },
-        0.25f64,
-        0.25f64,
-        imgproc::INTER_LINEAR,
-    )?;
-
-    // Run face detection
-    let mut detected_faces = types::VectorOfRect::new();
-    face.detect_multi_scale(
-        &reduced,
-        &mut detected_faces,
-        1.1,
-        2,
-        objdetect::CASCADE_SCALE_IMAGE,
-        core::Size {
-            width: 30,
-            height: 30,
-        },
-        core::Size {
-            width: 0,
-            height: 0,
-        },
-    )?;
 
-    Ok(detected_faces)
+    Ok(())
 }
 
 ///
-fn draw_detected_faces_on_frame(frame: &mut Mat, faces: core::Vector<core::Rect>) {
-    for temp_face in faces {
-        // println!("temp_face: {:#?}", temp_face);
-
-        let scaled_face = core::Rect {
-            x: temp_face.x * 4,
-            y: temp_face.y * 4,
-            width: temp_face.width * 4,
-            height: temp_face.height * 4,
-        };
+fn capture_from_web_cam_with_face_detection(options: CliOptions) -> Result<()> {
+    let window_flags = highgui::WINDOW_AUTOSIZE
+        | highgui::WINDOW_KEEPRATIO
+        | highgui::WINDOW_OPENGL
+        | highgui::WINDOW_NORMAL;
+    let mut display = display::open_display(options.display_backend, WINDOW_NAME, window_flags)?;
+    let is_highgui = display.backend() == DisplayBackend::Highgui;
 
-        let _ = imgproc::rectangle(
-            frame,                                        // Dest image
-            scaled_face,                                  // Rectangle to draw
-            core::Scalar::new(0f64, 0f64, 255f64, -1f64), // Border color (Blue, Green, Red, Alpha)
-            4,                                            // Boarder thickness
-            imgproc::LINE_AA,                             // Boarder line type
-            0,
-        )
-        .unwrap();
-    }
+    let mut config = match options.config_file.as_ref() {
+        Some(config_file) => Config::load(config_file)?,
+        None => Config::default(),
+    };
 
-    // Render the frame after merging with drawing faces
-    let _ = highgui::imshow(WINDOW_NAME, frame).unwrap();
-}
+    // Create video capture (camera), `0` means default webcam.
+    // You can pass `1` for the second camera, `2` for the third camera.
+    // Load face detection settings
+    // Or a video file name, e.g. recorded footage.
+    // The `camera` config (resolution, FPS, exposure, etc.) is applied right after opening.
+    // A stalled or disconnected camera is re-opened with backoff (the `reconnect` config).
+    let mut cam = ReconnectingCapture::open(
+        &options.source,
+        config.camera.clone(),
+        config.reconnect.clone(),
+    )?;
+    let is_video_file = cam.is_video_file();
+
+    // Create object detection classifier, plus the eye/smile/landmark detection inside the
+    // faces if the `features` config enables it
+    let mut face = detection::load_face_classifier()?;
+    let mut feature_detector = FaceFeatureDetector::new(&config.features)?;
+    // Label the faces with the people enrolled by `enroll-faces`
+    let mut person_recognizer = if config.recognition.enabled {
+        Some(PersonRecognizer::load(
+            &config.recognition,
+            &config.alignment,
+        )?)
+    } else {
+        None
+    };
 
-///
-fn draw_info_panel(
-    frame: &mut Mat,
-    frame_width: i32,
-    frame_height: i32,
-    fps: i32,
-    detected_face_amount: u8,
-) {
-    let formatted_panel_info = format!(
-        "Resolution: {} x {}\nFPS: {}\nDetected Faces: {}",
-        frame_width, frame_height, fps, detected_face_amount
+    // Settings can be changed by the keyboard shortcuts and the control API
+    let pipeline_control = PipelineControl::new(PipelineSettings {
+        detector: config.detector.clone(),
+        recording: options.recording.is_some(),
+        ..PipelineSettings::default()
+    });
+
+    // Zones can be loaded from config and drawn with the mouse, the mouse callback runs on
+    // another thread, that's why the editor is shared.
+    // In the split compare view, dragging the split line goes to the compare view instead.
+    let mut zone_mode = config.zones.mode;
+    let zone_editor = Arc::new(Mutex::new(ZoneEditor::new(config.zones.zones.clone())));
+    let compare_view = Arc::new(Mutex::new(CompareView::default()));
+    let (zone_editor_for_mouse, compare_view_for_mouse, control_for_mouse) = (
+        Arc::clone(&zone_editor),
+        Arc::clone(&compare_view),
+        pipeline_control.clone(),
     );
-    let text_list = formatted_panel_info.split("\n");
-    let text_vertical_space = 10;
-    let font_scale = 0.7;
-    let font_thickness = 1;
-    let font_color = core::Scalar::new(251., 235., 220., -1.); // (Blue, Green, Red, Alpha)
-    let info_panel_background_color = core::Scalar::new(15., 6., 3., -1.); // (Blue, Green, Red, Alpha)
-    let info_panel_width = 280;
-    let info_panel_height = 88;
-    let info_panel_margin = 2;
-
-    // For getting the better performance, we create a `ROI`(Region Of Interest) from the origin
-    // frame. This won't copy any data, as it's just a mut reference which will be affected if
-    // we modify it!!!
-    let roi = core::Rect {
-        x: frame_width - info_panel_width - info_panel_margin,
-        y: info_panel_margin,
-        width: info_panel_width,
-        height: info_panel_height,
+    // Only `highgui` has mouse callbacks, the zones from the config still work
+    if is_highgui {
+        highgui::set_mouse_callback(
+            WINDOW_NAME,
+            Some(Box::new(move |event, x, y, _flags| {
+                let compare_mode = control_for_mouse.settings().compare;
+                let is_split_dragged =
+                    compare_view_for_mouse
+                        .lock()
+                        .unwrap()
+                        .on_mouse_event(compare_mode, event, x);
+                if !is_split_dragged {
+                    zone_editor_for_mouse
+                        .lock()
+                        .unwrap()
+                        .on_mouse_event(event, x, y);
+                }
+            })),
+        )?;
+    } else {
+        println!("Zone drawing and the split view dragging need '--display highgui'");
+    }
+    if let Some(control_address) = options.control_address.as_ref() {
+        pipeline_control.serve(control_address)?;
+    }
+    let mut fps_counter = FpsCounter::default();
+
+    let cam_width = cam.get(videoio::CAP_PROP_FRAME_WIDTH)?;
+    let cam_height = cam.get(videoio::CAP_PROP_FRAME_HEIGHT)?;
+    let cam_fps = cam.get(videoio::CAP_PROP_FPS)?;
+
+    // Event recording: keep a pre-roll buffer in memory and only write clips to disk when a
+    // face or motion is detected. It can be started/stopped by the control API.
+    let recording_settings = options.recording.clone().unwrap_or_default();
+    let mut event_recorder: Option<EventRecorder> = None;
+    let mut motion_detector = MotionDetector::default();
+
+    // Track faces across frames, then count the ones crossing the counting lines
+    let mut face_tracker = CentroidTracker::default();
+    let mut face_scheduler = DetectionScheduler::new(&config.scheduler);
+    let mut crossing_counter = CrossingCounter::new(&config.counting)?;
+    let mut face_event_publisher = FaceEventPublisher::new(&config.events, &config.alignment)?;
+
+    // Serve the annotated frames and detections over HTTP
+    let stream_publisher = match options.serve_address.as_ref() {
+        Some(serve_address) => Some(StreamPublisher::start(serve_address)?),
+        None => None,
     };
-    let mut panel_roi_ref = core::Mat::roi(frame, roi).unwrap();
-
-    // Create a temp draw area with the same size of `roi`
-    let mut panel_background = core::Mat::new_size_with_default(
-        core::Size {
-            width: panel_roi_ref.cols(),
-            height: panel_roi_ref.rows(),
-        },
-        panel_roi_ref.typ().unwrap(),
-        core::Scalar::new(0., 0., 0., -1.),
-    )
-    .unwrap();
-    let panel_background_area = core::Rect {
-        x: 0,
-        y: 0,
-        width: roi.width,
-        height: roi.height,
+    let mut frame_index: u64 = 0;
+    // Shown (dimmed) while reconnecting
+    let mut last_frame = Mat::default()?;
+    // The last frame read from a video file, processed again while paused
+    let mut last_read_frame = Mat::default()?;
+
+    // Playback controls for video files: pause, step, seek, speed and the position trackbar.
+    // OpenCV keeps a pointer to the trackbar value, so it has to live until the window is gone.
+    let mut trackbar_value = Box::new(0);
+    let playback = if is_video_file {
+        let playback = Arc::new(Mutex::new(PlaybackController::new(
+            cam.get(videoio::CAP_PROP_FRAME_COUNT)? as i32,
+            cam_fps,
+        )));
+        let playback_for_trackbar = Arc::clone(&playback);
+        let frame_count = playback.lock().unwrap().frame_count();
+        // Without `highgui` there is no trackbar, the playback keys still work
+        if is_highgui {
+            highgui::create_trackbar(
+                TRACKBAR_NAME,
+                WINDOW_NAME,
+                &mut *trackbar_value,
+                frame_count - 1,
+                Some(Box::new(move |position| {
+                    playback_for_trackbar.lock().unwrap().on_trackbar(position);
+                })),
+            )
+            .context("Create playback trackbar")?;
+        }
+        Some(playback)
+    } else {
+        None
     };
-    // println!("roi: {:#?}", roi);
-    // println!("panel_roi_ref: {:#?}", panel_roi_ref);
-    // println!("panel_background_area: {:#?}", panel_background_area);
-    // println!("panel_background: {:#?}", panel_background);
-
-    // Fill the color
-    let _ = imgproc::rectangle(
-        &mut panel_background,       // Dest image
-        panel_background_area,       // Rectangle to draw
-        info_panel_background_color, // Boarder color
-        imgproc::FILLED,             // Boarder thickness: Fill the entire area
-        imgproc::LINE_AA,            // Boarder line type
-        0,
-    );
 
-    // Merge `panel_roi_ref` and `panel_background` together with the particular alpha(transparent)
-    // settings. So, we finished drawing a transparent background on top of the original frame:)
-    //
-    // `src image alpha` + `copy image alpha` should equal `1.0`. Just like a transparent percentage.
-    let _ = core::add_weighted(
-        &panel_roi_ref.clone(), // Src image
-        0.3,                    // Src image alpha
-        &panel_background,      // Copy image
-        0.7,                    // Copy image alpha
-        0.,                     // Gamma
-        &mut panel_roi_ref,     // The merge dest image
-        -1,
-    );
-
-    // Draw all split text
-    let text_coord = (roi.x + 6, roi.y + 25); // X, Y
-    let mut text_drawing_coord_y = text_coord.1;
-    for (index, temp_text) in text_list.enumerate() {
-        let text_size = get_drawing_text_size(temp_text, font_scale, font_thickness);
+    loop {
+        let frame_started_at = Instant::now();
+
+        // Paused: process the last frame again instead of reading one, so the info panel shows
+        // `PAUSED` and the other shortcuts keep working
+        let is_paused = match playback.as_ref() {
+            Some(playback) => {
+                let mut playback = playback.lock().unwrap();
+                let should_read_frame = playback.should_read_frame();
+                if should_read_frame {
+                    playback.apply_pending_seek(&mut cam)?;
+                }
+                !should_read_frame && last_read_frame.cols() > 0
+            }
+            None => false,
+        };
 
-        let drawing_coord = if index == 0 {
-            core::Point::new(text_coord.0, text_drawing_coord_y)
+        // Read every frame
+        let mut video_frame = core::Mat::default()?;
+        if is_paused {
+            video_frame = last_read_frame.clone();
         } else {
-            text_drawing_coord_y += text_size.height + text_vertical_space;
-            core::Point::new(text_coord.0, text_drawing_coord_y)
-        };
+            match cam.read(&mut video_frame)? {
+                CaptureState::Frame => {
+                    if let Some(playback) = playback.as_ref() {
+                        // Don't hold the lock, moving the trackbar calls `on_trackbar`
+                        let current_frame = {
+                            let mut playback = playback.lock().unwrap();
+                            playback.update_position(&cam)?;
+                            playback.current_frame()
+                        };
+                        if is_highgui {
+                            highgui::set_trackbar_pos(TRACKBAR_NAME, WINDOW_NAME, current_frame)
+                                .context("Move playback trackbar")?;
+                        }
+                    }
+                }
+                CaptureState::EndOfFile => {
+                    println!("End of video file: {}", options.source);
+                    break;
+                }
+                CaptureState::Reconnecting(status) => {
+                    let reconnecting_frame = capture::draw_reconnecting_frame(
+                        &last_frame,
+                        &status,
+                        cam_width as i32,
+                        cam_height as i32,
+                    )?;
+                    display.show(&reconnecting_frame)?;
+
+                    // Press any key to stop waiting
+                    let key = display.wait_key(10)?;
+                    if key > 0 && key != 255 {
+                        break;
+                    }
+                    continue;
+                }
+            }
+            if playback.is_some() {
+                last_read_frame = video_frame.clone();
+            }
+        }
 
-        draw_text_on_image(
-            frame,
-            temp_text,
-            drawing_coord,
-            font_scale,
-            font_thickness,
-            font_color,
-        );
-    }
-}
+        let settings = pipeline_control.settings();
+        let measured_fps = fps_counter.tick();
 
-///
-fn capture_from_web_cam_with_face_detection() -> opencv::Result<()> {
-    let window_flags = highgui::WINDOW_AUTOSIZE
-        | highgui::WINDOW_KEEPRATIO
-        | highgui::WINDOW_OPENGL
-        | highgui::WINDOW_NORMAL;
-    highgui::named_window(WINDOW_NAME, window_flags).unwrap();
+        // Start/stop event recording
+        if settings.recording && event_recorder.is_none() {
+            event_recorder = Some(EventRecorder::new(recording_settings.clone(), cam_fps)?);
+        } else if !settings.recording {
+            if let Some(mut recorder) = event_recorder.take() {
+                recorder.finish_clip()?;
+            }
+        }
 
-    // Create video capture (camera), `0` means default webcam.
-    // You can pass `1` for the second camera, `2` for the third camera.
-    // Load face detection settings
-    #[cfg(not(feature = "opencv-32"))]
-    let (xml, mut cam) = {
-        (
-            core::find_file("haarcascades/haarcascade_frontalface_alt.xml", true, false)?,
-            videoio::VideoCapture::new(0, videoio::CAP_ANY)?,
-        )
-    };
+        // Keep a clean copy for recording (and the compare view) before drawing anything on
+        // top of it
+        let raw_frame = if event_recorder.is_some() || settings.compare != CompareMode::Off {
+            Some(video_frame.clone())
+        } else {
+            None
+        };
 
-    let is_cam_opened = videoio::VideoCapture::is_opened(&cam)?;
-    if !is_cam_opened {
-        panic!("Unable to open default web camera");
-    }
+        // Detection is skipped on some frames (the `scheduler` config), the faces are moved
+        // along their last motion in between
+        let detected_faces = if face_scheduler.should_detect() {
+            let detection_started_at = Instant::now();
+            // Do face detection, only inside the zones or report the faces inside the zones.
+            // The faces are in the frame coordinates, overlapping and implausible ones are
+            // cleaned up by the `postprocess` config.
+            let active_zones = zone_editor.lock().unwrap().zones.clone();
+            let detected_faces = if active_zones.is_empty() {
+                detection::detect_faces(
+                    &mut face,
+                    &video_frame,
+                    &settings.detector,
+                    &config.postprocess,
+                )?
+            } else if zone_mode == ZoneMode::DetectInside {
+                let masked_frame = zones::mask_frame_outside_zones(&video_frame, &active_zones)?;
+                detection::detect_faces(
+                    &mut face,
+                    &masked_frame,
+                    &settings.detector,
+                    &config.postprocess,
+                )?
+            } else {
+                let mut faces_inside_zones = Vec::new();
+                for temp_face in detection::detect_faces(
+                    &mut face,
+                    &video_frame,
+                    &settings.detector,
+                    &config.postprocess,
+                )? {
+                    let face_center = core::Point::new(
+                        temp_face.rect.x + temp_face.rect.width / 2,
+                        temp_face.rect.y + temp_face.rect.height / 2,
+                    );
+                    if zones::is_point_inside_zones(face_center, &active_zones)? {
+                        faces_inside_zones.push(temp_face);
+                    }
+                }
+                faces_inside_zones
+            };
+            face_scheduler.on_detection(
+                &detected_faces,
+                detection_started_at.elapsed().as_secs_f64() * 1000.,
+            );
+            detected_faces
+        } else {
+            face_scheduler.predicted_faces(video_frame.size()?)
+        };
 
-    // Create object detection classifier
-    let mut face = objdetect::CascadeClassifier::new(&xml)?;
+        // Record the clean frame, face or motion triggers the event. A paused video adds nothing.
+        if let (Some(recorder), Some(raw_frame)) = (event_recorder.as_mut(), raw_frame.as_ref()) {
+            if !is_paused {
+                let motion_detected = motion_detector.detect(raw_frame)?;
+                recorder.push_frame(raw_frame, detected_faces.len() > 0 || motion_detected)?;
+            }
+        }
 
-    let mut grayscale_mode = false;
+        // Track faces across frames
+        let face_rects: Vec<core::Rect> = detected_faces
+            .iter()
+            .map(|temp_face| temp_face.rect)
+            .collect();
+        let track_update = face_tracker.update(&face_rects);
+        let face_features = feature_detector.detect(&video_frame, &face_rects)?;
+        let recognitions = match person_recognizer.as_mut() {
+            Some(recognizer) => recognizer.recognize(&video_frame, &face_rects)?,
+            None => Vec::new(),
+        };
+        let face_labels: Vec<String> = recognitions.iter().map(Recognition::label).collect();
 
-    let cam_width = cam.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap();
-    let cam_height = cam.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap();
-    let cam_fps = cam.get(videoio::CAP_PROP_FPS).unwrap();
+        // Publish `face_appeared`/`face_left` events, snapshots are taken from the clean frame
+        if !face_event_publisher.is_empty() {
+            face_event_publisher.on_track_update(
+                &track_update,
+                face_tracker.objects(),
+                &video_frame,
+            )?;
+        }
 
-    loop {
-        // Read every frame
-        let mut video_frame = core::Mat::default()?;
-        cam.read(&mut video_frame)?;
-        if video_frame.size()?.width == 0 {
-            thread::sleep(Duration::from_secs(5));
-            continue;
+        // Apply the filter after the detection, then draw everything on top of it
+        if settings.filter != Filter::None {
+            video_frame = filters::apply_filter(&video_frame, settings.filter)?;
         }
 
         // Draw tips
-        draw_tips_on_frame(&mut video_frame);
+        draw_tips_on_frame(&mut video_frame)?;
 
-        // Do face detection
-        let detected_faces = face_detection_on_frame(&mut face, &mut video_frame).unwrap();
+        if !is_paused {
+            frame_index += 1;
+        }
+        let video_position_ms = if is_video_file {
+            cam.get(videoio::CAP_PROP_POS_MSEC)?
+        } else {
+            0.
+        };
+        if !crossing_counter.is_empty() {
+            crossing_counter.update(face_tracker.objects(), video_position_ms);
+            crossing_counter.draw_lines_on_frame(&mut video_frame)?;
+        }
 
-        // Draw info panel
+        // Draw zones (and the one in progress)
+        zone_editor
+            .lock()
+            .unwrap()
+            .draw_on_frame(&mut video_frame)?;
+
+        // Draw info panel, with the camera properties the device didn't accept
+        let mut extra_info = crossing_counter.summary();
+        extra_info.extend(
+            cam.mismatches()
+                .iter()
+                .map(|mismatch| format!("! {}", mismatch)),
+        );
+        if let Some(playback) = playback.as_ref() {
+            extra_info.extend(playback.lock().unwrap().summary());
+        }
+        if config.scheduler.mode != ScheduleMode::EveryFrame {
+            extra_info.push(format!("Detect every {} frames", face_scheduler.interval()));
+        }
         draw_info_panel(
             &mut video_frame,
             cam_width as i32,
             cam_height as i32,
-            cam_fps as i32,
+            measured_fps.round() as i32,
             detected_faces.len() as u8,
-        );
+            &extra_info,
+        )?;
+
+        pipeline_control.set_status(PipelineStatus {
+            frame_width: cam_width as i32,
+            frame_height: cam_height as i32,
+            measured_fps,
+            detected_faces: detected_faces.len(),
+            frame_index,
+            recording_clip: event_recorder
+                .as_ref()
+                .map(|recorder| recorder.is_recording())
+                .unwrap_or(false),
+        });
 
         // println!("Detected face amount: {}", faces.len());
 
         // Draw a rectangle for each face result on top of the particular (frame) image
-        if grayscale_mode {
+        if settings.grayscale {
             let mut grayscale_frame = Mat::default()?;
             // Do a color conversion from `BRG(blue Red Green 3 channels)` to `Grayscale`(1 channel)
-            let _ = imgproc::cvt_color(
+            imgproc::cvt_color(
                 &video_frame,
                 &mut grayscale_frame,
                 imgproc::COLOR_BGR2GRAY,
                 0,
             )
-            .unwrap();
-            draw_detected_faces_on_frame(&mut grayscale_frame, detected_faces);
+            .context("Convert frame to grayscale")?;
+            overlay::draw_face_rects(&mut grayscale_frame, &face_rects)?;
+            overlay::draw_face_features(&mut grayscale_frame, &face_features)?;
+            overlay::draw_face_labels(&mut grayscale_frame, &face_rects, &face_labels)?;
+            video_frame = grayscale_frame;
         } else {
-            draw_detected_faces_on_frame(&mut video_frame, detected_faces);
+            overlay::draw_face_rects(&mut video_frame, &face_rects)?;
+            overlay::draw_face_features(&mut video_frame, &face_features)?;
+            overlay::draw_face_labels(&mut video_frame, &face_rects, &face_labels)?;
         }
 
-        let key = highgui::wait_key(10)?;
+        // Show the original next to the processed frame
+        if let Some(raw_frame) = raw_frame.as_ref() {
+            if settings.compare != CompareMode::Off {
+                video_frame = compare_view.lock().unwrap().compose(
+                    settings.compare,
+                    raw_frame,
+                    &video_frame,
+                )?;
+            }
+        }
+        display.show(&video_frame)?;
+
+        // Publish exactly what's shown in the preview window
+        if let Some(publisher) = stream_publisher.as_ref() {
+            let mut detection_report = DetectionReport::from_tracked_objects(
+                frame_index,
+                video_position_ms,
+                face_tracker.objects(),
+            );
+            detection_report.attach_features(&face_rects, &face_features);
+            detection_report.attach_recognitions(&face_rects, &recognitions);
+            publisher.publish(&video_frame, &detection_report)?;
+        }
+        last_frame = video_frame;
+        face_scheduler.on_frame_processed(frame_started_at.elapsed().as_secs_f64() * 1000.);
+
+        // Video files play at the (adjustable) file FPS
+        let key_delay_ms = match playback.as_ref() {
+            Some(_) if is_paused => 30,
+            Some(playback) => playback
+                .lock()
+                .unwrap()
+                .frame_delay_ms(frame_started_at.elapsed().as_secs_f64() * 1000.),
+            None => 10,
+        };
+        let key = display.wait_key(key_delay_ms)?;
+        let is_playback_key = match playback.as_ref() {
+            Some(playback) => playback.lock().unwrap().on_key(key),
+            None => false,
+        };
 
+        if is_playback_key {
+            // Handled by the playback controls
+        }
         // Press `g` key to toggle `grayscale_mode`
-        if key == 103 {
-            grayscale_mode = !grayscale_mode;
-            println!("Grayscale mode enabled: {}", grayscale_mode);
+        else if key == 103 {
+            pipeline_control.update_settings(|settings| settings.grayscale = !settings.grayscale);
+            println!(
+                "Grayscale mode enabled: {}",
+                pipeline_control.settings().grayscale
+            );
+        }
+        // Press `v` key to switch the compare view
+        else if key == 118 {
+            pipeline_control.update_settings(|settings| settings.compare = settings.compare.next());
+            println!("Compare view: {:?}", pipeline_control.settings().compare);
+        }
+        // Press `f` key to switch to the next filter
+        else if key == 102 {
+            pipeline_control.update_settings(|settings| settings.filter = settings.filter.next());
+            println!("Filter: {:?}", pipeline_control.settings().filter);
+        }
+        // Press `c` key to clear all zones
+        else if key == 99 {
+            zone_editor.lock().unwrap().clear();
+            println!("All zones cleared");
+        }
+        // Press `m` key to toggle zone mode
+        else if key == 109 {
+            zone_mode = match zone_mode {
+                ZoneMode::DetectInside => ZoneMode::ReportInside,
+                ZoneMode::ReportInside => ZoneMode::DetectInside,
+            };
+            println!("Zone mode: {:?}", zone_mode);
+        }
+        // Press `s` key to save zones into the config file
+        else if key == 115 {
+            let config_file = options
+                .config_file
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+            config.zones.mode = zone_mode;
+            config.zones.zones = zone_editor.lock().unwrap().zones.clone();
+            config.save(&config_file)?;
+            println!("Zones saved into: {}", config_file.display());
         }
         // Press any key to stop
         else if key > 0 && key != 255 {
@@ -337,6 +628,18 @@ fn capture_from_web_cam_with_face_detection() -> opencv::Result<()> {
         }
     }
 
+    if let Some(publisher) = stream_publisher.as_ref() {
+        publisher.close();
+    }
+
+    // Give the event sinks a chance to deliver the queued events
+    face_event_publisher.shutdown();
+
+    // Make sure the clip in progress is closed properly, otherwise it can't be played.
+    if let Some(recorder) = event_recorder.as_mut() {
+        recorder.finish_clip()?;
+    }
+
     // Closes video file or capturing device.
     //
     // The method is automatically called by subsequent `VideoCapture::open` and by
@@ -348,7 +651,17 @@ fn capture_from_web_cam_with_face_detection() -> opencv::Result<()> {
 
 ///
 fn main() {
-    let close_capture_successfully = match capture_from_web_cam_with_face_detection() {
+    let options = match parse_cli_options() {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Close video capture abnormally: {}", error);
+            process::exit(error.exit_code());
+        }
+    };
+    let display_backend = options.display_backend;
+
+    let capture_result = capture_from_web_cam_with_face_detection(options);
+    let close_capture_successfully = match capture_result.as_ref() {
         Ok(_) => true,
         Err(error) => {
             println!("Close video capture abnormally: {}", error);
@@ -356,7 +669,7 @@ fn main() {
         }
     };
 
-    let close_all_window_succesfully = match highgui::destroy_all_windows() {
+    let close_all_window_succesfully = match display::destroy_all_windows(display_backend) {
         Ok(_) => true,
         Err(error) => {
             println!("Close all windows abnormally: {}", error);
@@ -367,4 +680,8 @@ fn main() {
     if close_capture_successfully && close_all_window_succesfully {
         println!("Program exit normally:)");
     }
+
+    if let Err(error) = capture_result {
+        process::exit(error.exit_code());
+    }
 }
@@ -0,0 +1,194 @@
+use crate::{
+    detection::{clamp_to_frame, load_cascade},
+    error::{Context, Error, Result},
+    face_features::{detect_in_area, EYE_CASCADE_FILE},
+};
+use opencv::{core, imgproc, objdetect, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// The canonical face: the eyes level, at the same place and distance in every aligned face
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlignmentSettings {
+    /// The aligned face is a square of this size (in pixels)
+    pub face_size: i32,
+    /// Where the eyes are vertically, `0.0` (top) to `1.0` (bottom)
+    pub eye_height: f64,
+    /// The distance between the eyes, relative to `face_size`
+    pub eye_distance: f64,
+    /// Histogram equalization, makes the faces less dependent on the lighting
+    pub equalize: bool,
+}
+
+impl Default for AlignmentSettings {
+    fn default() -> Self {
+        AlignmentSettings {
+            face_size: 100,
+            eye_height: 0.38,
+            eye_distance: 0.42,
+            equalize: true,
+        }
+    }
+}
+
+impl AlignmentSettings {
+    ///
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.face_size <= 0 {
+            return Err(format!(
+                "face_size must be positive, got: {}",
+                self.face_size
+            ));
+        }
+        if self.eye_height <= 0. || self.eye_height >= 1. {
+            return Err(format!(
+                "eye_height must be in (0, 1), got: {}",
+                self.eye_height
+            ));
+        }
+        if self.eye_distance <= 0. || self.eye_distance >= 1. {
+            return Err(format!(
+                "eye_distance must be in (0, 1), got: {}",
+                self.eye_distance
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The eye centers (left and right in the image) from the detected eye rects: the 2 biggest
+/// ones, `None` if there are less than 2
+pub fn eye_centers(eyes: &[core::Rect]) -> Option<(core::Point2f, core::Point2f)> {
+    let mut eyes = eyes.to_vec();
+    eyes.sort_by_key(|eye| std::cmp::Reverse(eye.area()));
+    eyes.truncate(2);
+    if eyes.len() < 2 {
+        return None;
+    }
+    eyes.sort_by_key(|eye| eye.x);
+
+    let center = |eye: &core::Rect| {
+        core::Point2f::new(
+            eye.x as f32 + eye.width as f32 / 2.,
+            eye.y as f32 + eye.height as f32 / 2.,
+        )
+    };
+    Some((center(&eyes[0]), center(&eyes[1])))
+}
+
+/// The frame converted to grayscale, `None` if it already is (use the frame itself then)
+pub fn convert_to_gray(frame: &Mat) -> Result<Option<Mat>> {
+    if frame.channels()? == 1 {
+        return Ok(None);
+    }
+    let mut gray = Mat::default()?;
+    imgproc::cvt_color(frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)
+        .context("Convert frame to grayscale for alignment")?;
+    Ok(Some(gray))
+}
+
+/// Rotate, scale and crop the face into the canonical pose, `face` and the eye centers are in
+/// the frame coordinates. Without eye centers, the face rect is only cropped and resized.
+/// The result is grayscale, `face_size` x `face_size`.
+pub fn align_face(
+    frame: &Mat,
+    face: core::Rect,
+    eye_centers: Option<(core::Point2f, core::Point2f)>,
+    settings: &AlignmentSettings,
+) -> Result<Mat> {
+    let converted = convert_to_gray(frame)?;
+    let gray = converted.as_ref().unwrap_or(frame);
+    let face = clamp_to_frame(face, gray.size()?);
+    if face.width <= 0 || face.height <= 0 {
+        return Err(Error::EmptyFrame(String::from("Face outside of the frame")));
+    }
+    let face_size = core::Size::new(settings.face_size, settings.face_size);
+
+    let mut aligned = Mat::default()?;
+    match eye_centers {
+        Some((left_eye, right_eye)) => {
+            let (dx, dy) = (
+                (right_eye.x - left_eye.x) as f64,
+                (right_eye.y - left_eye.y) as f64,
+            );
+            // Rotating by the angle of the eye line around the eyes center levels the eyes
+            let angle = dy.atan2(dx).to_degrees();
+            let scale = settings.eye_distance * settings.face_size as f64
+                / (dx * dx + dy * dy).sqrt().max(1.);
+            let eyes_center = core::Point2f::new(
+                (left_eye.x + right_eye.x) / 2.,
+                (left_eye.y + right_eye.y) / 2.,
+            );
+            let mut transform = imgproc::get_rotation_matrix_2d(eyes_center, angle, scale)
+                .context("Create alignment transform")?;
+
+            // Then move the eyes center to its place in the aligned face
+            *transform.at_2d_mut::<f64>(0, 2)? +=
+                settings.face_size as f64 * 0.5 - eyes_center.x as f64;
+            *transform.at_2d_mut::<f64>(1, 2)? +=
+                settings.face_size as f64 * settings.eye_height - eyes_center.y as f64;
+
+            imgproc::warp_affine(
+                gray,
+                &mut aligned,
+                &transform,
+                face_size,
+                imgproc::INTER_LINEAR,
+                core::BORDER_REPLICATE,
+                core::Scalar::default(),
+            )
+            .context("Align face")?;
+        }
+        None => {
+            let face_image = core::Mat::roi(gray, face).context("Create face ROI")?;
+            imgproc::resize(
+                &face_image,
+                &mut aligned,
+                face_size,
+                0.,
+                0.,
+                imgproc::INTER_AREA,
+            )
+            .context("Resize face")?;
+        }
+    }
+
+    if settings.equalize {
+        let mut equalized = Mat::default()?;
+        imgproc::equalize_hist(&aligned, &mut equalized).context("Equalize aligned face")?;
+        aligned = equalized;
+    }
+    Ok(aligned)
+}
+
+/// `align_face` with its own eye detection, for the callers which only have the face rects
+pub struct FaceAligner {
+    eye: objdetect::CascadeClassifier,
+    settings: AlignmentSettings,
+}
+
+impl FaceAligner {
+    ///
+    pub fn new(settings: &AlignmentSettings) -> Result<Self> {
+        settings.validate().map_err(Error::Config)?;
+        Ok(FaceAligner {
+            eye: load_cascade(EYE_CASCADE_FILE)?,
+            settings: settings.clone(),
+        })
+    }
+
+    /// Detect the eyes in the upper half of `face` (frame coordinates), then align it. `frame`
+    /// is BGR or grayscale, convert it once with `convert_to_gray` when aligning many faces.
+    pub fn align(&mut self, frame: &Mat, face: core::Rect) -> Result<Mat> {
+        let converted = convert_to_gray(frame)?;
+        let gray = converted.as_ref().unwrap_or(frame);
+        let face = clamp_to_frame(face, gray.size()?);
+        let upper_half = core::Rect {
+            height: face.height / 2,
+            ..face
+        };
+        let eyes = detect_in_area(&mut self.eye, gray, upper_half, 1.1, 3, face.width / 8)?;
+
+        align_face(gray, face, eye_centers(&eyes), &self.settings)
+    }
+}
@@ -0,0 +1,325 @@
+use crate::error::{Context, Result};
+use opencv::{prelude::*, videoio};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The resolutions we try when probing a camera, most webcams support a few of them
+pub const COMMON_RESOLUTIONS: &[(i32, i32)] = &[
+    (320, 240),
+    (640, 480),
+    (800, 600),
+    (1280, 720),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+/// What a camera reports about itself with a particular backend
+#[derive(Debug, Clone, Serialize)]
+pub struct CameraInfo {
+    pub index: i32,
+    pub backend: String,
+    /// The resolution right after opening
+    pub default_resolution: (i32, i32),
+    /// `0` means the device doesn't report it
+    pub fps: f64,
+    /// FourCC, e.g. `MJPG` or `YUYV`, empty if the device doesn't report it
+    pub pixel_format: String,
+    /// The ones in `COMMON_RESOLUTIONS` which the device accepted
+    pub supported_resolutions: Vec<(i32, i32)>,
+}
+
+/// The `camera` section of the config file, only the provided properties are set, e.g.:
+///
+/// ```json
+/// "camera": {
+///     "width": 1280,
+///     "height": 720,
+///     "fps": 30,
+///     "fourcc": "MJPG",
+///     "exposure": -6,
+///     "gain": 0,
+///     "white_balance": 4500,
+///     "autofocus": false
+/// }
+/// ```
+///
+/// The values of `exposure` and `gain` are backend specific, some backends ignore `exposure`
+/// until auto exposure is turned off in the driver.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub fps: Option<f64>,
+    /// Pixel format, e.g. `MJPG` (most webcams only reach full FPS in HD with it)
+    pub fourcc: Option<String>,
+    pub exposure: Option<f64>,
+    pub gain: Option<f64>,
+    /// Color temperature in Kelvin, auto white balance is turned off when provided
+    pub white_balance: Option<f64>,
+    pub autofocus: Option<bool>,
+}
+
+/// A property the device didn't accept as requested
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyMismatch {
+    pub name: &'static str,
+    pub requested: f64,
+    /// `None` means the backend doesn't support setting it at all
+    pub actual: Option<f64>,
+}
+
+impl fmt::Display for PropertyMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Show `MJPG` rather than the number
+        let format_value = |value: f64| {
+            if self.name == "fourcc" {
+                fourcc_to_string(value)
+            } else {
+                value.to_string()
+            }
+        };
+        match self.actual {
+            Some(actual) => write!(
+                f,
+                "{} {} -> {}",
+                self.name,
+                format_value(self.requested),
+                format_value(actual)
+            ),
+            None => write!(
+                f,
+                "{} {} unsupported",
+                self.name,
+                format_value(self.requested)
+            ),
+        }
+    }
+}
+
+/// `"MJPG"` -> the number for `CAP_PROP_FOURCC`
+pub fn string_to_fourcc(fourcc: &str) -> Option<f64> {
+    let chars: Vec<char> = fourcc.chars().collect();
+    if chars.len() != 4 {
+        return None;
+    }
+    videoio::VideoWriter::fourcc(
+        chars[0] as i8,
+        chars[1] as i8,
+        chars[2] as i8,
+        chars[3] as i8,
+    )
+    .ok()
+    .map(|code| code as f64)
+}
+
+/// `CAP_PROP_FOURCC` is 4 chars packed into a number, e.g. `MJPG`
+pub fn fourcc_to_string(fourcc: f64) -> String {
+    let code = fourcc as u32;
+    if code == 0 {
+        return String::new();
+    }
+    code.to_le_bytes()
+        .iter()
+        .map(|&byte| byte as char)
+        .collect::<String>()
+        .trim_end_matches(char::from(0))
+        .to_string()
+}
+
+/// `VideoCapture` for the camera `index` with the given backend (e.g. `videoio::CAP_ANY`)
+#[cfg(not(feature = "opencv-32"))]
+pub fn open_camera(index: i32, backend: i32) -> opencv::Result<videoio::VideoCapture> {
+    videoio::VideoCapture::new(index, backend)
+}
+
+/// `VideoCapture` for the camera `index` with the given backend (e.g. `videoio::CAP_ANY`).
+/// OpenCV 3.2 has no backend parameter, the backend is added to the index instead.
+#[cfg(feature = "opencv-32")]
+pub fn open_camera(index: i32, backend: i32) -> opencv::Result<videoio::VideoCapture> {
+    videoio::VideoCapture::new(backend + index)
+}
+
+/// `videoio::get_backend_name` (the videoio registry) is only in OpenCV 4
+#[cfg(feature = "opencv-4")]
+fn backend_name(cam: &videoio::VideoCapture, backend: videoio::VideoCaptureAPIs) -> String {
+    videoio::get_backend_name(backend)
+        .or_else(|_| cam.get_backend_name())
+        .unwrap_or_else(|_| format!("{:?}", backend))
+}
+
+/// OpenCV 3.2 and the older 3.4 builds can't name the backends
+#[cfg(not(feature = "opencv-4"))]
+fn backend_name(_cam: &videoio::VideoCapture, backend: videoio::VideoCaptureAPIs) -> String {
+    format!("{:?}", backend)
+}
+
+/// Every camera backend OpenCV was built with
+#[cfg(feature = "opencv-4")]
+fn camera_backends() -> Result<Vec<videoio::VideoCaptureAPIs>> {
+    Ok(videoio::get_camera_backends()
+        .context("List camera backends")?
+        .iter()
+        .collect())
+}
+
+/// OpenCV 3.2 and the older 3.4 builds can't list the backends, only the default one is probed
+#[cfg(not(feature = "opencv-4"))]
+fn camera_backends() -> Result<Vec<videoio::VideoCaptureAPIs>> {
+    Ok(vec![videoio::VideoCaptureAPIs::CAP_ANY])
+}
+
+/// Open the camera with the given backend, `None` if it can't be opened
+pub fn probe_camera(index: i32, backend: videoio::VideoCaptureAPIs) -> Result<Option<CameraInfo>> {
+    let mut cam = match open_camera(index, backend as i32) {
+        Ok(cam) => cam,
+        // Some backends throw rather than return a closed capture
+        Err(_) => return Ok(None),
+    };
+    if !videoio::VideoCapture::is_opened(&cam)? {
+        return Ok(None);
+    }
+
+    let backend = backend_name(&cam, backend);
+    let default_resolution = (
+        cam.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32,
+        cam.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32,
+    );
+    let fps = cam.get(videoio::CAP_PROP_FPS)?;
+    let pixel_format = fourcc_to_string(cam.get(videoio::CAP_PROP_FOURCC)?);
+
+    // There is no API to list the modes, so ask for every common resolution and read back
+    // what the device actually switched to.
+    let mut supported_resolutions = Vec::new();
+    for &(width, height) in COMMON_RESOLUTIONS {
+        cam.set(videoio::CAP_PROP_FRAME_WIDTH, width as f64)?;
+        cam.set(videoio::CAP_PROP_FRAME_HEIGHT, height as f64)?;
+        let accepted = (
+            cam.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32,
+            cam.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32,
+        );
+        if accepted == (width, height) {
+            supported_resolutions.push(accepted);
+        }
+    }
+
+    cam.release().context("Release probed camera")?;
+
+    Ok(Some(CameraInfo {
+        index,
+        backend,
+        default_resolution,
+        fps,
+        pixel_format,
+        supported_resolutions,
+    }))
+}
+
+/// Try camera index `0..max_index` with every camera backend OpenCV was built with
+pub fn probe_cameras(max_index: i32) -> Result<Vec<CameraInfo>> {
+    let mut cameras = Vec::new();
+    for backend in camera_backends()? {
+        for index in 0..max_index {
+            if let Some(info) = probe_camera(index, backend)? {
+                cameras.push(info);
+            }
+        }
+    }
+
+    Ok(cameras)
+}
+
+/// Set one property and read back what the device actually accepted
+fn set_property(
+    cam: &mut videoio::VideoCapture,
+    name: &'static str,
+    property_id: i32,
+    requested: f64,
+    tolerance: f64,
+) -> Result<Option<PropertyMismatch>> {
+    let actual = if cam.set(property_id, requested)? {
+        Some(cam.get(property_id)?)
+    } else {
+        None
+    };
+
+    match actual {
+        Some(actual) if (actual - requested).abs() <= tolerance => Ok(None),
+        _ => Ok(Some(PropertyMismatch {
+            name,
+            requested,
+            actual,
+        })),
+    }
+}
+
+/// Apply the provided properties, return the ones the device didn't accept (they are logged
+/// as well). The pixel format goes first, as it limits the available resolutions and FPS.
+pub fn apply_camera_settings(
+    cam: &mut videoio::VideoCapture,
+    settings: &CameraSettings,
+) -> Result<Vec<PropertyMismatch>> {
+    let mut requested_properties: Vec<(&'static str, i32, f64, f64)> = Vec::new();
+    let mut mismatches = Vec::new();
+    if let Some(fourcc) = settings.fourcc.as_ref() {
+        match string_to_fourcc(fourcc) {
+            Some(code) => requested_properties.push(("fourcc", videoio::CAP_PROP_FOURCC, code, 0.)),
+            None => println!("Invalid camera fourcc ignored: '{}'", fourcc),
+        }
+    }
+    if let Some(width) = settings.width {
+        requested_properties.push(("width", videoio::CAP_PROP_FRAME_WIDTH, width as f64, 0.));
+    }
+    if let Some(height) = settings.height {
+        requested_properties.push(("height", videoio::CAP_PROP_FRAME_HEIGHT, height as f64, 0.));
+    }
+    if let Some(fps) = settings.fps {
+        // Devices report things like `29.97` for `30`
+        requested_properties.push(("fps", videoio::CAP_PROP_FPS, fps, 0.5));
+    }
+    if let Some(exposure) = settings.exposure {
+        requested_properties.push(("exposure", videoio::CAP_PROP_EXPOSURE, exposure, 0.01));
+    }
+    if let Some(gain) = settings.gain {
+        requested_properties.push(("gain", videoio::CAP_PROP_GAIN, gain, 0.01));
+    }
+    #[cfg(not(feature = "opencv-32"))]
+    {
+        if let Some(white_balance) = settings.white_balance {
+            requested_properties.push(("auto_white_balance", videoio::CAP_PROP_AUTO_WB, 0., 0.));
+            requested_properties.push((
+                "white_balance",
+                videoio::CAP_PROP_WB_TEMPERATURE,
+                white_balance,
+                1.,
+            ));
+        }
+    }
+    // The white balance properties are only in OpenCV 3.4 and newer
+    #[cfg(feature = "opencv-32")]
+    {
+        if let Some(white_balance) = settings.white_balance {
+            let mismatch = PropertyMismatch {
+                name: "white_balance",
+                requested: white_balance,
+                actual: None,
+            };
+            println!("Camera property not applied, {}", mismatch);
+            mismatches.push(mismatch);
+        }
+    }
+    if let Some(autofocus) = settings.autofocus {
+        let value = if autofocus { 1. } else { 0. };
+        requested_properties.push(("autofocus", videoio::CAP_PROP_AUTOFOCUS, value, 0.));
+    }
+
+    for (name, property_id, requested, tolerance) in requested_properties {
+        if let Some(mismatch) = set_property(cam, name, property_id, requested, tolerance)? {
+            println!("Camera property not applied, {}", mismatch);
+            mismatches.push(mismatch);
+        }
+    }
+
+    Ok(mismatches)
+}
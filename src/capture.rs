@@ -0,0 +1,352 @@
+use crate::{
+    camera::{self, CameraSettings, PropertyMismatch},
+    error::{Context, Error, Result},
+};
+use opencv::{core, imgproc, prelude::*, videoio};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// The `reconnect` section of the config file, e.g.:
+///
+/// ```json
+/// "reconnect": {
+///     "initial_backoff_ms": 500,
+///     "max_backoff_ms": 10000,
+///     "max_attempts": 10,
+///     "stall_timeout_ms": 5000
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectSettings {
+    /// The first retry delay, it doubles after every failed attempt
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Give up after this many failed attempts in a row, `0` means retry forever
+    pub max_attempts: u32,
+    /// A read slower than this counts as a stall (frozen IP camera feed), only for live sources
+    pub stall_timeout_ms: u64,
+}
+
+impl Default for ReconnectSettings {
+    fn default() -> Self {
+        ReconnectSettings {
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            max_attempts: 10,
+            stall_timeout_ms: 5_000,
+        }
+    }
+}
+
+///
+#[derive(Debug, Clone)]
+pub struct ReconnectStatus {
+    /// The failed attempts so far
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// How long until the next attempt
+    pub retry_in: Duration,
+}
+
+impl ReconnectStatus {
+    /// The text for the overlay, e.g. `Reconnecting... (attempt 2/10, retry in 1.0s)`
+    pub fn message(&self) -> String {
+        let attempts = if self.max_attempts == 0 {
+            format!("{}", self.attempt)
+        } else {
+            format!("{}/{}", self.attempt, self.max_attempts)
+        };
+        format!(
+            "Reconnecting... (attempt {}, retry in {:.1}s)",
+            attempts,
+            self.retry_in.as_secs_f64()
+        )
+    }
+}
+
+/// The result of `ReconnectingCapture::read`
+#[derive(Debug, Clone)]
+pub enum CaptureState {
+    /// A new frame is ready
+    Frame,
+    /// The source is stalled or disconnected, no frame this time, keep calling `read`
+    Reconnecting(ReconnectStatus),
+    /// The video file has no more frames
+    EndOfFile,
+}
+
+/// `VideoCapture` for a camera index, a stream URL (e.g. `rtsp://` IP camera) or a video file
+/// which re-opens the source with exponential backoff when it stalls or disconnects.
+///
+/// `read` never blocks for the backoff delay, so the caller can keep the window (and the
+/// keyboard shortcuts) responsive while reconnecting.
+///
+/// A stall is only detected once the backend's `read` returns. Some backends never return on a
+/// dead network stream, then `read` blocks as well. For the FFmpeg backend set a socket
+/// timeout, e.g. `OPENCV_FFMPEG_CAPTURE_OPTIONS="rtsp_transport;tcp|stimeout;5000000"`.
+pub struct ReconnectingCapture {
+    source: String,
+    camera_settings: CameraSettings,
+    settings: ReconnectSettings,
+    cam: Option<videoio::VideoCapture>,
+    /// The camera properties the device didn't accept on the last open
+    mismatches: Vec<PropertyMismatch>,
+    /// The failed attempts in a row, reset by the next good frame
+    failed_attempts: u32,
+    backoff: Duration,
+    next_attempt_at: Instant,
+}
+
+/// `rtsp://`, `http://`, `udp://` etc., anything with a scheme except `file://`
+fn is_stream_url(source: &str) -> bool {
+    match source.find("://") {
+        Some(scheme_end) => {
+            let scheme = &source[..scheme_end];
+            let is_scheme = scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '+');
+            is_scheme && scheme != "file"
+        }
+        None => false,
+    }
+}
+
+/// Camera index (e.g. `0`), stream URL or video file name, the camera settings only apply to
+/// cameras
+fn open_source(
+    source: &str,
+    camera_settings: &CameraSettings,
+) -> Result<Option<(videoio::VideoCapture, Vec<PropertyMismatch>)>> {
+    let (cam, is_camera) = match source.parse::<i32>() {
+        Ok(camera_index) => (camera::open_camera(camera_index, videoio::CAP_ANY), true),
+        Err(_) => (
+            videoio::VideoCapture::from_file(source, videoio::CAP_ANY),
+            false,
+        ),
+    };
+    let mut cam = cam.context("Create video capture")?;
+
+    if !videoio::VideoCapture::is_opened(&cam)? {
+        return Ok(None);
+    }
+
+    let mismatches = if is_camera {
+        camera::apply_camera_settings(&mut cam, camera_settings)?
+    } else {
+        Vec::new()
+    };
+    Ok(Some((cam, mismatches)))
+}
+
+impl ReconnectingCapture {
+    /// The first open must succeed, otherwise it's most likely a wrong source rather than
+    /// a flaky connection. The camera settings are applied again after every reconnect.
+    pub fn open(
+        source: &str,
+        camera_settings: CameraSettings,
+        settings: ReconnectSettings,
+    ) -> Result<Self> {
+        let (cam, mismatches) = open_source(source, &camera_settings)?
+            .ok_or_else(|| Error::SourceOpen(source.to_string()))?;
+        let backoff = Duration::from_millis(settings.initial_backoff_ms);
+
+        Ok(ReconnectingCapture {
+            source: source.to_string(),
+            camera_settings,
+            settings,
+            cam: Some(cam),
+            mismatches,
+            failed_attempts: 0,
+            backoff,
+            next_attempt_at: Instant::now(),
+        })
+    }
+
+    /// `false` for cameras and stream URLs, those are live: they reconnect instead of ending
+    /// and can't be paused or seeked
+    pub fn is_video_file(&self) -> bool {
+        self.source.parse::<i32>().is_err() && !is_stream_url(&self.source)
+    }
+
+    /// The camera properties the device didn't accept, e.g. for the info panel
+    pub fn mismatches(&self) -> &[PropertyMismatch] {
+        &self.mismatches
+    }
+
+    /// `CAP_PROP_*` value of the current capture, `0` while reconnecting
+    pub fn get(&self, property_id: i32) -> Result<f64> {
+        match self.cam.as_ref() {
+            Some(cam) => Ok(cam.get(property_id)?),
+            None => Ok(0.),
+        }
+    }
+
+    /// Set a `CAP_PROP_*` value (e.g. seek in a video file), `false` if it's not supported or
+    /// reconnecting
+    pub fn set(&mut self, property_id: i32, value: f64) -> Result<bool> {
+        match self.cam.as_mut() {
+            Some(cam) => Ok(cam.set(property_id, value)?),
+            None => Ok(false),
+        }
+    }
+
+    /// Read the next frame into `frame`, only `CaptureState::Frame` means `frame` is valid
+    pub fn read(&mut self, frame: &mut Mat) -> Result<CaptureState> {
+        if self.cam.is_none() {
+            return self.reconnect();
+        }
+
+        let cam = self.cam.as_mut().unwrap();
+        let read_started_at = Instant::now();
+        // Some backends report a disconnected camera as an error rather than an empty frame
+        let is_read = match cam.read(frame) {
+            Ok(is_read) => is_read,
+            Err(error) => {
+                println!("Read frame from '{}' failed: {}", self.source, error);
+                false
+            }
+        };
+        // A slow read of a video file (slow disk, heavy decode) is still a good frame, re-opening
+        // the file would start it over from the first frame
+        let is_stalled = !self.is_video_file()
+            && read_started_at.elapsed() > Duration::from_millis(self.settings.stall_timeout_ms);
+
+        if is_read && frame.size()?.width > 0 && !is_stalled {
+            self.failed_attempts = 0;
+            self.backoff = Duration::from_millis(self.settings.initial_backoff_ms);
+            return Ok(CaptureState::Frame);
+        }
+
+        if !is_stalled && self.is_video_file() {
+            return Ok(CaptureState::EndOfFile);
+        }
+
+        println!(
+            "Video source '{}' {}, reconnecting...",
+            self.source,
+            if is_stalled {
+                "stalled"
+            } else {
+                "disconnected"
+            }
+        );
+        self.release()?;
+        self.schedule_next_attempt();
+        Ok(CaptureState::Reconnecting(self.status()))
+    }
+
+    /// Try to re-open the source if the backoff delay is over
+    fn reconnect(&mut self) -> Result<CaptureState> {
+        let now = Instant::now();
+        if now < self.next_attempt_at {
+            return Ok(CaptureState::Reconnecting(self.status()));
+        }
+
+        if self.settings.max_attempts > 0 && self.failed_attempts >= self.settings.max_attempts {
+            return Err(Error::SourceOpen(format!(
+                "{} (gave up after {} reconnect attempts)",
+                self.source, self.failed_attempts
+            )));
+        }
+
+        // Only a good frame resets the counter, a re-opened camera may fail again right away
+        self.failed_attempts += 1;
+
+        // A failed open is just another failed attempt, not an error
+        match open_source(&self.source, &self.camera_settings) {
+            Ok(Some((cam, mismatches))) => {
+                println!("Video source '{}' re-opened", self.source);
+                self.cam = Some(cam);
+                self.mismatches = mismatches;
+            }
+            Ok(None) => self.schedule_next_attempt(),
+            Err(error) => {
+                println!("Re-open '{}' failed: {}", self.source, error);
+                self.schedule_next_attempt();
+            }
+        }
+
+        Ok(CaptureState::Reconnecting(self.status()))
+    }
+
+    ///
+    fn schedule_next_attempt(&mut self) {
+        self.next_attempt_at = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(Duration::from_millis(self.settings.max_backoff_ms));
+    }
+
+    ///
+    fn status(&self) -> ReconnectStatus {
+        ReconnectStatus {
+            attempt: self.failed_attempts,
+            max_attempts: self.settings.max_attempts,
+            retry_in: self
+                .next_attempt_at
+                .saturating_duration_since(Instant::now()),
+        }
+    }
+
+    /// Closes the current capture, it's safe to call it more than once
+    pub fn release(&mut self) -> Result<()> {
+        if let Some(mut cam) = self.cam.take() {
+            cam.release().context("Release video capture")?;
+        }
+        Ok(())
+    }
+}
+
+/// The frame to show while reconnecting: the last frame dimmed (or a black frame if there is
+/// none) with the reconnect status in the middle.
+pub fn draw_reconnecting_frame(
+    last_frame: &Mat,
+    status: &ReconnectStatus,
+    frame_width: i32,
+    frame_height: i32,
+) -> Result<Mat> {
+    let mut frame = Mat::default()?;
+    if last_frame.size()?.width > 0 {
+        last_frame
+            .convert_to(&mut frame, -1, 0.4, 0.)
+            .context("Dim the last frame")?;
+    } else {
+        frame = Mat::new_rows_cols_with_default(
+            frame_height.max(480),
+            frame_width.max(640),
+            core::CV_8UC3,
+            core::Scalar::all(0.),
+        )
+        .context("Create reconnecting frame")?;
+    }
+
+    let message = status.message();
+    let font_scale = 0.8;
+    let font_thickness = 2;
+    let mut base_line = 0;
+    let text_size = imgproc::get_text_size(
+        &message,
+        imgproc::FONT_HERSHEY_DUPLEX,
+        font_scale,
+        font_thickness,
+        &mut base_line,
+    )
+    .context("Get reconnecting text size")?;
+
+    imgproc::put_text(
+        &mut frame,
+        &message,
+        core::Point::new(
+            (frame.cols() - text_size.width) / 2,
+            (frame.rows() + text_size.height) / 2,
+        ),
+        imgproc::FONT_HERSHEY_DUPLEX,
+        font_scale,
+        core::Scalar::new(0., 165., 255., -1.), // (Blue, Green, Red, Alpha)
+        font_thickness,
+        imgproc::LINE_AA,
+        false,
+    )
+    .context("Draw reconnecting status")?;
+
+    Ok(frame)
+}
@@ -0,0 +1,213 @@
+use crate::error::{Context, Result};
+use opencv::{core, highgui, imgproc, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// How far (in pixels) from the split line a click still grabs it
+const SPLIT_GRAB_DISTANCE: i32 = 12;
+
+/// Show the original frame next to the processed one (filter, grayscale, overlays)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareMode {
+    Off,
+    /// Original on the left of the split line, processed on the right
+    Split,
+    /// Processed frame with a small original in the bottom right corner
+    PictureInPicture,
+}
+
+impl Default for CompareMode {
+    fn default() -> Self {
+        CompareMode::Off
+    }
+}
+
+impl CompareMode {
+    /// The next mode for the keyboard shortcut
+    pub fn next(self) -> Self {
+        match self {
+            CompareMode::Off => CompareMode::Split,
+            CompareMode::Split => CompareMode::PictureInPicture,
+            CompareMode::PictureInPicture => CompareMode::Off,
+        }
+    }
+}
+
+/// Keeps the split line position, which can be dragged with the mouse
+pub struct CompareView {
+    /// `0.0` (left edge) to `1.0` (right edge)
+    split_ratio: f64,
+    is_dragging: bool,
+    /// The width of the last composed frame, to map the mouse position to the ratio
+    frame_width: i32,
+}
+
+impl Default for CompareView {
+    fn default() -> Self {
+        CompareView {
+            split_ratio: 0.5,
+            is_dragging: false,
+            frame_width: 0,
+        }
+    }
+}
+
+/// The processed frame can be grayscale (1 channel), the original is always BGR
+fn to_bgr(frame: &Mat) -> Result<Mat> {
+    if frame.channels()? == 1 {
+        let mut color_frame = Mat::default()?;
+        imgproc::cvt_color(frame, &mut color_frame, imgproc::COLOR_GRAY2BGR, 0)
+            .context("Convert frame to BGR")?;
+        Ok(color_frame)
+    } else {
+        Ok(frame.clone())
+    }
+}
+
+///
+fn draw_label(frame: &mut Mat, text: &str, point: core::Point) -> Result<()> {
+    imgproc::put_text(
+        frame,
+        text,
+        point,
+        imgproc::FONT_HERSHEY_DUPLEX,
+        0.7,
+        core::Scalar::new(0., 255., 255., -1.), // (Blue, Green, Red, Alpha)
+        1,
+        imgproc::LINE_AA,
+        false,
+    )
+    .context("Draw compare label")
+}
+
+impl CompareView {
+    /// Pass the `highgui` mouse events in, return `true` if the event was used to drag the
+    /// split line, so it shouldn't go to anything else (e.g. the zone editor).
+    pub fn on_mouse_event(&mut self, mode: CompareMode, event: i32, x: i32) -> bool {
+        if mode != CompareMode::Split || self.frame_width <= 0 {
+            self.is_dragging = false;
+            return false;
+        }
+
+        let split_x = (self.frame_width as f64 * self.split_ratio) as i32;
+        match event {
+            highgui::EVENT_LBUTTONDOWN if (x - split_x).abs() <= SPLIT_GRAB_DISTANCE => {
+                self.is_dragging = true;
+                true
+            }
+            highgui::EVENT_MOUSEMOVE if self.is_dragging => {
+                self.split_ratio = (x as f64 / self.frame_width as f64).max(0.).min(1.);
+                true
+            }
+            highgui::EVENT_LBUTTONUP if self.is_dragging => {
+                self.is_dragging = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The frame to show for the mode, `original` and `processed` must be the same size
+    pub fn compose(&mut self, mode: CompareMode, original: &Mat, processed: &Mat) -> Result<Mat> {
+        let mut frame = to_bgr(processed)?;
+        let (frame_width, frame_height) = (frame.cols(), frame.rows());
+        self.frame_width = frame_width;
+
+        match mode {
+            CompareMode::Off => {}
+            CompareMode::Split => {
+                let split_x = (frame_width as f64 * self.split_ratio) as i32;
+                if split_x > 0 {
+                    let left_area = core::Rect {
+                        x: 0,
+                        y: 0,
+                        width: split_x,
+                        height: frame_height,
+                    };
+                    let original_left =
+                        core::Mat::roi(original, left_area).context("Create original split ROI")?;
+                    let mut frame_left_ref =
+                        core::Mat::roi(&frame, left_area).context("Create split ROI")?;
+                    original_left
+                        .copy_to(&mut frame_left_ref)
+                        .context("Copy original into split")?;
+                }
+
+                imgproc::line(
+                    &mut frame,
+                    core::Point::new(split_x, 0),
+                    core::Point::new(split_x, frame_height),
+                    core::Scalar::new(255., 255., 255., -1.),
+                    2,
+                    imgproc::LINE_AA,
+                    0,
+                )
+                .context("Draw split line")?;
+                draw_label(
+                    &mut frame,
+                    "Original",
+                    core::Point::new(10, frame_height - 12),
+                )?;
+                draw_label(
+                    &mut frame,
+                    "Processed",
+                    core::Point::new(frame_width - 130, frame_height - 12),
+                )?;
+            }
+            CompareMode::PictureInPicture => {
+                let inset_width = frame_width / 4;
+                let inset_height = frame_height / 4;
+                let margin = 10;
+                let inset_area = core::Rect {
+                    x: frame_width - inset_width - margin,
+                    y: frame_height - inset_height - margin,
+                    width: inset_width,
+                    height: inset_height,
+                };
+
+                let mut inset = Mat::default()?;
+                imgproc::resize(
+                    original,
+                    &mut inset,
+                    inset_area.size(),
+                    0.,
+                    0.,
+                    imgproc::INTER_AREA,
+                )
+                .context("Resize picture in picture")?;
+
+                // Same as the info panel: blend into the ROI, the processed frame stays
+                // slightly visible behind the inset
+                let mut inset_roi_ref =
+                    core::Mat::roi(&frame, inset_area).context("Create picture in picture ROI")?;
+                core::add_weighted(
+                    &inset_roi_ref.clone(), // Src image
+                    0.15,                   // Src image alpha
+                    &inset,                 // Copy image
+                    0.85,                   // Copy image alpha
+                    0.,                     // Gamma
+                    &mut inset_roi_ref,     // The merge dest image
+                    -1,
+                )
+                .context("Blend picture in picture")?;
+
+                imgproc::rectangle(
+                    &mut frame,
+                    inset_area,
+                    core::Scalar::new(255., 255., 255., -1.),
+                    1,
+                    imgproc::LINE_AA,
+                    0,
+                )
+                .context("Draw picture in picture border")?;
+                draw_label(
+                    &mut frame,
+                    "Original",
+                    core::Point::new(inset_area.x + 6, inset_area.y + 22),
+                )?;
+            }
+        }
+
+        Ok(frame)
+    }
+}
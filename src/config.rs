@@ -0,0 +1,67 @@
+use crate::{
+    alignment::AlignmentSettings,
+    camera::CameraSettings,
+    capture::ReconnectSettings,
+    counting::CountingSettings,
+    detection::DetectorSettings,
+    error::{Error, Result},
+    events::EventSettings,
+    face_features::FaceFeatureSettings,
+    postprocess::PostProcessSettings,
+    recognition::RecognitionSettings,
+    scheduler::SchedulerSettings,
+    zones::ZoneSettings,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Settings loaded from a JSON config file (`--config <file>`), every section is optional
+/// and falls back to its default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub zones: ZoneSettings,
+    pub counting: CountingSettings,
+    pub detector: DetectorSettings,
+    pub postprocess: PostProcessSettings,
+    pub scheduler: SchedulerSettings,
+    pub events: EventSettings,
+    pub reconnect: ReconnectSettings,
+    pub camera: CameraSettings,
+    pub features: FaceFeatureSettings,
+    pub recognition: RecognitionSettings,
+    pub alignment: AlignmentSettings,
+}
+
+impl Config {
+    ///
+    pub fn load(file_name: &Path) -> Result<Config> {
+        let content = fs::read_to_string(file_name).map_err(|error| {
+            Error::io(
+                format!("Unable to read config '{}'", file_name.display()),
+                error,
+            )
+        })?;
+
+        let config: Config = serde_json::from_str(&content)
+            .map_err(|error| Error::Config(format!("'{}': {}", file_name.display(), error)))?;
+        config.detector.validate().map_err(Error::Config)?;
+        config.postprocess.validate().map_err(Error::Config)?;
+        config.scheduler.validate().map_err(Error::Config)?;
+        config.alignment.validate().map_err(Error::Config)?;
+        Ok(config)
+    }
+
+    ///
+    pub fn save(&self, file_name: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|error| Error::Config(format!("Unable to serialize config: {}", error)))?;
+
+        fs::write(file_name, content).map_err(|error| {
+            Error::io(
+                format!("Unable to write config '{}'", file_name.display()),
+                error,
+            )
+        })
+    }
+}
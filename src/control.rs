@@ -0,0 +1,197 @@
+use crate::{
+    compare::CompareMode,
+    detection::DetectorSettings,
+    error::{Error, Result},
+    filters::Filter,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Everything which can be changed while the pipeline is running
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineSettings {
+    pub grayscale: bool,
+    pub filter: Filter,
+    /// Show the original frame next to the processed one
+    pub compare: CompareMode,
+    pub detector: DetectorSettings,
+    /// Event recording enabled or not
+    pub recording: bool,
+}
+
+/// The same data the info panel shows
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipelineStatus {
+    pub frame_width: i32,
+    pub frame_height: i32,
+    pub measured_fps: f64,
+    pub detected_faces: usize,
+    pub frame_index: u64,
+    /// A clip is being written right now
+    pub recording_clip: bool,
+}
+
+#[derive(Default)]
+struct ControlState {
+    settings: PipelineSettings,
+    status: PipelineStatus,
+}
+
+/// Shared between the capture loop (read settings, write status) and the control API server
+/// (the other way around). The keyboard shortcuts go through it as well, so both ways always
+/// see the same settings.
+#[derive(Clone)]
+pub struct PipelineControl {
+    shared: Arc<Mutex<ControlState>>,
+}
+
+impl PipelineControl {
+    ///
+    pub fn new(settings: PipelineSettings) -> Self {
+        PipelineControl {
+            shared: Arc::new(Mutex::new(ControlState {
+                settings,
+                status: PipelineStatus::default(),
+            })),
+        }
+    }
+
+    ///
+    pub fn settings(&self) -> PipelineSettings {
+        self.shared.lock().unwrap().settings.clone()
+    }
+
+    ///
+    pub fn update_settings<F: FnOnce(&mut PipelineSettings)>(&self, update: F) {
+        update(&mut self.shared.lock().unwrap().settings);
+    }
+
+    ///
+    pub fn status(&self) -> PipelineStatus {
+        self.shared.lock().unwrap().status.clone()
+    }
+
+    ///
+    pub fn set_status(&self, status: PipelineStatus) {
+        self.shared.lock().unwrap().status = status;
+    }
+
+    /// Start the control API server in the background, e.g. `serve("127.0.0.1:8081")`.
+    ///
+    /// Endpoints:
+    ///
+    /// - `GET  /status`           : resolution, measured FPS, face count, etc.
+    /// - `GET  /settings`         : the current settings
+    /// - `POST /settings`         : change settings, only the provided fields are changed,
+    ///                              e.g. `{"filter": "edges", "detector": {"min_neighbors": 4}}`
+    /// - `POST /grayscale/toggle` : same as pressing `g`
+    /// - `POST /recording/start`  : start event recording
+    /// - `POST /recording/stop`   : stop event recording
+    pub fn serve(&self, address: &str) -> Result<()> {
+        let server = Server::http(address).map_err(|error| {
+            Error::Server(format!(
+                "Unable to start control server on '{}': {}",
+                address, error
+            ))
+        })?;
+        println!("Control API server listening on: http://{}", address);
+
+        let control = self.clone();
+        thread::spawn(move || {
+            // Requests are tiny and fast, no need for a thread per request
+            for request in server.incoming_requests() {
+                control.handle_request(request);
+            }
+        });
+
+        Ok(())
+    }
+
+    ///
+    fn handle_request(&self, mut request: Request) {
+        let path = request.url().split('?').next().unwrap_or("").to_string();
+        let method = request.method().clone();
+
+        let (status_code, body) = match (&method, path.as_str()) {
+            (Method::Get, "/status") => (200, to_json(&self.status())),
+            (Method::Get, "/settings") => (200, to_json(&self.settings())),
+            (Method::Post, "/settings") | (Method::Put, "/settings") => {
+                let mut request_body = String::new();
+                match request.as_reader().read_to_string(&mut request_body) {
+                    Ok(_) => match self.merge_settings(&request_body) {
+                        Ok(settings) => (200, to_json(&settings)),
+                        Err(error) => (400, error_json(&error)),
+                    },
+                    Err(error) => (400, error_json(&error.to_string())),
+                }
+            }
+            (Method::Post, "/grayscale/toggle") => {
+                self.update_settings(|settings| settings.grayscale = !settings.grayscale);
+                (200, to_json(&self.settings()))
+            }
+            (Method::Post, "/recording/start") | (Method::Post, "/recording/stop") => {
+                let recording = path == "/recording/start";
+                self.update_settings(|settings| settings.recording = recording);
+                (200, to_json(&self.settings()))
+            }
+            _ => (404, error_json("Not found")),
+        };
+
+        let response = Response::from_string(body)
+            .with_status_code(status_code)
+            .with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+            );
+        if let Err(error) = request.respond(response) {
+            println!("Control API client '{}' disconnected: {}", path, error);
+        }
+    }
+
+    /// Merge the partial JSON into the current settings, the settings stay the same if the
+    /// result is invalid.
+    fn merge_settings(&self, partial_json: &str) -> std::result::Result<PipelineSettings, String> {
+        let patch: Value = serde_json::from_str(partial_json)
+            .map_err(|error| format!("Invalid JSON: {}", error))?;
+
+        let mut state = self.shared.lock().unwrap();
+        let mut merged =
+            serde_json::to_value(&state.settings).map_err(|error| error.to_string())?;
+        merge_json(&mut merged, patch);
+
+        let new_settings: PipelineSettings = serde_json::from_value(merged)
+            .map_err(|error| format!("Invalid settings: {}", error))?;
+        new_settings.detector.validate()?;
+
+        state.settings = new_settings.clone();
+        Ok(new_settings)
+    }
+}
+
+/// Recursively merge `patch` into `target`, only the keys in `patch` are replaced
+fn merge_json(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target_map), Value::Object(patch_map)) => {
+            for (key, patch_value) in patch_map {
+                merge_json(target_map.entry(key).or_insert(Value::Null), patch_value);
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+///
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+///
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
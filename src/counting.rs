@@ -0,0 +1,248 @@
+use crate::{
+    error::{Error, Result},
+    tracking::TrackedObject,
+};
+use opencv::{core, imgproc, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+/// A counting line in the frame coordinates.
+///
+/// Looking from `start` to `end`, crossing from the left side to the right side counts as `in`,
+/// the opposite counts as `out`. Swap `start` and `end` to swap the direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountingLine {
+    pub name: String,
+    pub start: (i32, i32),
+    pub end: (i32, i32),
+}
+
+/// The `counting` section of the config file, e.g.:
+///
+/// ```json
+/// "counting": {
+///     "log_file": "crossings.csv",
+///     "lines": [
+///         { "name": "entrance", "start": [0, 360], "end": [1280, 360] }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CountingSettings {
+    /// Every crossing will be appended into this CSV file if provided
+    pub log_file: Option<PathBuf>,
+    pub lines: Vec<CountingLine>,
+}
+
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrossingDirection {
+    In,
+    Out,
+}
+
+///
+#[derive(Debug)]
+pub struct LineCounter {
+    pub line: CountingLine,
+    pub in_count: u32,
+    pub out_count: u32,
+}
+
+/// Count the tracked objects whose centroid crosses the counting lines
+pub struct CrossingCounter {
+    counters: Vec<LineCounter>,
+    log_file: Option<File>,
+    /// The last known side (-1 or 1) and centroid for every (track ID, line index)
+    last_sides: HashMap<(u32, usize), (i64, core::Point)>,
+}
+
+/// Which side of the line the point is on: `< 0` left, `> 0` right, `0` on the line.
+/// (The frame Y axis points down, that's why the sign looks "reversed")
+fn side_of_line(start: core::Point, end: core::Point, point: core::Point) -> i64 {
+    let cross = (end.x - start.x) as i64 * (point.y - start.y) as i64
+        - (end.y - start.y) as i64 * (point.x - start.x) as i64;
+    cross.signum()
+}
+
+/// Only crossing the line segment counts, passing by the (infinite) extension doesn't
+fn is_segment_crossed(line: &CountingLine, from: core::Point, to: core::Point) -> bool {
+    let start = core::Point::new(line.start.0, line.start.1);
+    let end = core::Point::new(line.end.0, line.end.1);
+    let start_side = side_of_line(from, to, start);
+    let end_side = side_of_line(from, to, end);
+    start_side == 0 || end_side == 0 || start_side != end_side
+}
+
+impl CrossingCounter {
+    ///
+    pub fn new(settings: &CountingSettings) -> Result<Self> {
+        let log_file = match settings.log_file.as_ref() {
+            Some(log_file_name) => {
+                let is_new_file = !log_file_name.exists();
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(log_file_name)
+                    .map_err(|error| {
+                        Error::io(
+                            format!("Unable to open counting log '{}'", log_file_name.display()),
+                            error,
+                        )
+                    })?;
+                if is_new_file {
+                    let _ = writeln!(file, "timestamp,video_position_ms,line,direction,track_id");
+                }
+                Some(file)
+            }
+            None => None,
+        };
+
+        Ok(CrossingCounter {
+            counters: settings
+                .lines
+                .iter()
+                .map(|line| LineCounter {
+                    line: line.clone(),
+                    in_count: 0,
+                    out_count: 0,
+                })
+                .collect(),
+            log_file,
+            last_sides: HashMap::new(),
+        })
+    }
+
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    ///
+    pub fn counters(&self) -> &[LineCounter] {
+        &self.counters
+    }
+
+    /// Check all tracked objects against all lines, `video_position_ms` is the position of
+    /// the current frame in the video file (`0` for a live camera) for logging.
+    pub fn update(&mut self, objects: &[TrackedObject], video_position_ms: f64) {
+        for object in objects.iter().filter(|object| object.missing_frames == 0) {
+            for (line_index, counter) in self.counters.iter_mut().enumerate() {
+                let line = &counter.line;
+                let side = side_of_line(
+                    core::Point::new(line.start.0, line.start.1),
+                    core::Point::new(line.end.0, line.end.1),
+                    object.centroid,
+                );
+                // On the line: wait until it reaches one side
+                if side == 0 {
+                    continue;
+                }
+
+                let key = (object.id, line_index);
+                if let Some(&(last_side, last_centroid)) = self.last_sides.get(&key) {
+                    if last_side != side && is_segment_crossed(line, last_centroid, object.centroid)
+                    {
+                        let direction = if last_side < 0 {
+                            counter.in_count += 1;
+                            CrossingDirection::In
+                        } else {
+                            counter.out_count += 1;
+                            CrossingDirection::Out
+                        };
+
+                        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+                        println!(
+                            "[{}] Track {} crossed '{}': {:?}",
+                            timestamp, object.id, line.name, direction
+                        );
+                        if let Some(log_file) = self.log_file.as_mut() {
+                            let _ = writeln!(
+                                log_file,
+                                "{},{:.0},{},{},{}",
+                                timestamp,
+                                video_position_ms,
+                                line.name,
+                                if direction == CrossingDirection::In {
+                                    "in"
+                                } else {
+                                    "out"
+                                },
+                                object.id
+                            );
+                        }
+                    }
+                }
+                self.last_sides.insert(key, (side, object.centroid));
+            }
+        }
+
+        // Forget the objects which are not tracked anymore
+        self.last_sides
+            .retain(|(track_id, _), _| objects.iter().any(|object| object.id == *track_id));
+    }
+
+    /// One line per counting line for the info panel, e.g. `entrance: in 3 / out 1`
+    pub fn summary(&self) -> Vec<String> {
+        self.counters
+            .iter()
+            .map(|counter| {
+                format!(
+                    "{}: in {} / out {}",
+                    counter.line.name, counter.in_count, counter.out_count
+                )
+            })
+            .collect()
+    }
+
+    /// Draw the counting lines with an arrow pointing to the `in` side
+    pub fn draw_lines_on_frame(&self, frame: &mut Mat) -> Result<()> {
+        let line_color = core::Scalar::new(0., 215., 255., -1.); // (Blue, Green, Red, Alpha)
+
+        for counter in self.counters.iter() {
+            let line = &counter.line;
+            let start = core::Point::new(line.start.0, line.start.1);
+            let end = core::Point::new(line.end.0, line.end.1);
+            imgproc::line(frame, start, end, line_color, 2, imgproc::LINE_AA, 0)?;
+
+            // The right side normal of `start -> end` (Y axis points down)
+            let middle = core::Point::new((start.x + end.x) / 2, (start.y + end.y) / 2);
+            let (dx, dy) = ((end.x - start.x) as f64, (end.y - start.y) as f64);
+            let length = (dx * dx + dy * dy).sqrt().max(1.);
+            let arrow_end = core::Point::new(
+                middle.x - (dy / length * 30.) as i32,
+                middle.y + (dx / length * 30.) as i32,
+            );
+            imgproc::arrowed_line(
+                frame,
+                middle,
+                arrow_end,
+                line_color,
+                2,
+                imgproc::LINE_AA,
+                0,
+                0.3,
+            )?;
+
+            imgproc::put_text(
+                frame,
+                &line.name,
+                core::Point::new(start.x + 4, start.y - 8),
+                imgproc::FONT_HERSHEY_DUPLEX,
+                0.6,
+                line_color,
+                1,
+                imgproc::LINE_AA,
+                false,
+            )?;
+        }
+
+        Ok(())
+    }
+}
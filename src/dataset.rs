@@ -0,0 +1,417 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fs, path::Path};
+
+/// A ground-truth or detected box in the image coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl BoundingBox {
+    ///
+    pub fn intersection_over_union(&self, other: &BoundingBox) -> f64 {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        let intersection = (right - left).max(0.) * (bottom - top).max(0.);
+        let union = self.width * self.height + other.width * other.height - intersection;
+        if union > 0. {
+            intersection / union
+        } else {
+            0.
+        }
+    }
+}
+
+/// The faces of one image, `file_name` is relative to the images folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageAnnotation {
+    pub file_name: String,
+    pub faces: Vec<BoundingBox>,
+}
+
+/// Supported ground-truth formats
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationFormat {
+    /// `wider_face_*_bbx_gt.txt`
+    WiderFace,
+    /// A folder of Pascal VOC XML files, one per image
+    PascalVoc,
+    /// COCO JSON (`images` + `annotations`)
+    Coco,
+}
+
+impl AnnotationFormat {
+    /// `"wider"`, `"voc"` or `"coco"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wider" | "wider_face" => Some(AnnotationFormat::WiderFace),
+            "voc" | "pascal_voc" => Some(AnnotationFormat::PascalVoc),
+            "coco" => Some(AnnotationFormat::Coco),
+            _ => None,
+        }
+    }
+}
+
+///
+fn invalid_annotation(path: &Path, message: &str) -> Error {
+    Error::Config(format!(
+        "Invalid annotation '{}': {}",
+        path.display(),
+        message
+    ))
+}
+
+///
+fn read_annotation_file(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|error| {
+        Error::io(
+            format!("Unable to read annotation '{}'", path.display()),
+            error,
+        )
+    })
+}
+
+/// Load the ground truth, `path` is a file for WIDER FACE and COCO, a folder for Pascal VOC
+pub fn load_annotations(format: AnnotationFormat, path: &Path) -> Result<Vec<ImageAnnotation>> {
+    match format {
+        AnnotationFormat::WiderFace => parse_wider_face(path, &read_annotation_file(path)?),
+        AnnotationFormat::PascalVoc => {
+            let mut xml_files: Vec<_> = fs::read_dir(path)
+                .map_err(|error| {
+                    Error::io(
+                        format!("Unable to read annotation folder '{}'", path.display()),
+                        error,
+                    )
+                })?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().map(|ext| ext == "xml").unwrap_or(false))
+                .collect();
+            xml_files.sort();
+
+            xml_files
+                .iter()
+                .map(|xml_file| parse_pascal_voc(xml_file, &read_annotation_file(xml_file)?))
+                .collect()
+        }
+        AnnotationFormat::Coco => parse_coco(path, &read_annotation_file(path)?),
+    }
+}
+
+/// Every image is: the file name, the face count, then one `x y w h ...` line per face (an
+/// image without faces still has one line of zeros). The faces marked `invalid` are skipped.
+fn parse_wider_face(path: &Path, content: &str) -> Result<Vec<ImageAnnotation>> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    let mut annotations = Vec::new();
+
+    while let Some(file_name) = lines.next() {
+        let face_count = lines
+            .next()
+            .and_then(|line| line.parse::<usize>().ok())
+            .ok_or_else(|| invalid_annotation(path, &format!("no face count for {}", file_name)))?;
+
+        let mut faces = Vec::new();
+        for _ in 0..face_count.max(1) {
+            let values: Vec<f64> = lines
+                .next()
+                .ok_or_else(|| {
+                    invalid_annotation(path, &format!("missing faces of {}", file_name))
+                })?
+                .split_whitespace()
+                .filter_map(|value| value.parse::<f64>().ok())
+                .collect();
+            // x, y, w, h, blur, expression, illumination, invalid, occlusion, pose
+            let is_invalid = values.get(7).map(|value| *value > 0.).unwrap_or(false);
+            if face_count > 0
+                && values.len() >= 4
+                && !is_invalid
+                && values[2] > 0.
+                && values[3] > 0.
+            {
+                faces.push(BoundingBox {
+                    x: values[0],
+                    y: values[1],
+                    width: values[2],
+                    height: values[3],
+                });
+            }
+        }
+
+        annotations.push(ImageAnnotation {
+            file_name: file_name.to_string(),
+            faces,
+        });
+    }
+
+    Ok(annotations)
+}
+
+/// The text between `<tag>` and `</tag>` for every occurrence
+fn xml_elements<'a>(content: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let mut elements = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(&open) {
+        let after_open = &rest[start + open.len()..];
+        match after_open.find(&close) {
+            Some(end) => {
+                elements.push(after_open[..end].trim());
+                rest = &after_open[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    elements
+}
+
+/// Only the few tags we need: `filename` and `object/bndbox`. Objects named anything else
+/// than `face` are skipped, an object without `name` counts as a face.
+fn parse_pascal_voc(path: &Path, content: &str) -> Result<ImageAnnotation> {
+    let file_name = xml_elements(content, "filename")
+        .first()
+        .map(|file_name| file_name.to_string())
+        .ok_or_else(|| invalid_annotation(path, "no <filename>"))?;
+
+    let mut faces = Vec::new();
+    for object in xml_elements(content, "object") {
+        let name = xml_elements(object, "name")
+            .first()
+            .map(|name| name.to_lowercase());
+        if name.map(|name| name != "face").unwrap_or(false) {
+            continue;
+        }
+
+        let bndbox = xml_elements(object, "bndbox")
+            .first()
+            .cloned()
+            .ok_or_else(|| invalid_annotation(path, "<object> without <bndbox>"))?;
+        let value = |tag: &str| -> Result<f64> {
+            xml_elements(bndbox, tag)
+                .first()
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| invalid_annotation(path, &format!("invalid <{}>", tag)))
+        };
+        let (xmin, ymin, xmax, ymax) = (
+            value("xmin")?,
+            value("ymin")?,
+            value("xmax")?,
+            value("ymax")?,
+        );
+        faces.push(BoundingBox {
+            x: xmin,
+            y: ymin,
+            width: xmax - xmin,
+            height: ymax - ymin,
+        });
+    }
+
+    Ok(ImageAnnotation { file_name, faces })
+}
+
+/// `bbox` is `[x, y, width, height]`. If there is a category named `face`, only that one
+/// counts, otherwise every annotation is a face.
+fn parse_coco(path: &Path, content: &str) -> Result<Vec<ImageAnnotation>> {
+    let coco: Value = serde_json::from_str(content)
+        .map_err(|error| invalid_annotation(path, &error.to_string()))?;
+
+    let face_category = coco["categories"].as_array().and_then(|categories| {
+        categories
+            .iter()
+            .find(|category| category["name"].as_str() == Some("face"))
+            .and_then(|category| category["id"].as_i64())
+    });
+
+    let mut annotations = Vec::new();
+    let mut image_indexes = HashMap::new();
+    for image in coco["images"]
+        .as_array()
+        .ok_or_else(|| invalid_annotation(path, "no images"))?
+    {
+        let (id, file_name) = match (image["id"].as_i64(), image["file_name"].as_str()) {
+            (Some(id), Some(file_name)) => (id, file_name),
+            _ => return Err(invalid_annotation(path, "image without id or file_name")),
+        };
+        image_indexes.insert(id, annotations.len());
+        annotations.push(ImageAnnotation {
+            file_name: file_name.to_string(),
+            faces: Vec::new(),
+        });
+    }
+
+    for annotation in coco["annotations"].as_array().unwrap_or(&Vec::new()) {
+        if let Some(face_category) = face_category {
+            if annotation["category_id"].as_i64() != Some(face_category) {
+                continue;
+            }
+        }
+        let bbox: Vec<f64> = annotation["bbox"]
+            .as_array()
+            .map(|bbox| bbox.iter().filter_map(Value::as_f64).collect())
+            .unwrap_or_default();
+        let image_index = annotation["image_id"]
+            .as_i64()
+            .and_then(|image_id| image_indexes.get(&image_id));
+        match (image_index, bbox.len()) {
+            (Some(&image_index), 4) => annotations[image_index].faces.push(BoundingBox {
+                x: bbox[0],
+                y: bbox[1],
+                width: bbox[2],
+                height: bbox[3],
+            }),
+            _ => return Err(invalid_annotation(path, "annotation without image or bbox")),
+        }
+    }
+
+    Ok(annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x: f64, y: f64, width: f64, height: f64) -> BoundingBox {
+        BoundingBox {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn intersection_over_union_of_overlapping_boxes() {
+        let a = bbox(0., 0., 10., 10.);
+        assert_eq!(a.intersection_over_union(&a), 1.);
+        // 5x10 overlap, 150 union
+        assert!((a.intersection_over_union(&bbox(5., 0., 10., 10.)) - 50. / 150.).abs() < 1e-9);
+        assert_eq!(a.intersection_over_union(&bbox(20., 20., 10., 10.)), 0.);
+    }
+
+    #[test]
+    fn wider_face_skips_invalid_and_empty_faces() {
+        let content = "\
+            0--Parade/a.jpg\n\
+            2\n\
+            10 20 30 40 0 0 0 0 0 0\n\
+            50 60 0 0 0 0 0 0 0 0\n\
+            0--Parade/b.jpg\n\
+            0\n\
+            0 0 0 0 0 0 0 0 0 0\n\
+            0--Parade/c.jpg\n\
+            1\n\
+            1 2 3 4 0 0 0 1 0 0\n";
+        let annotations = parse_wider_face(Path::new("gt.txt"), content).unwrap();
+
+        assert_eq!(annotations.len(), 3);
+        assert_eq!(annotations[0].file_name, "0--Parade/a.jpg");
+        assert_eq!(annotations[0].faces, vec![bbox(10., 20., 30., 40.)]);
+        // No faces still has its line of zeros
+        assert_eq!(annotations[1].file_name, "0--Parade/b.jpg");
+        assert!(annotations[1].faces.is_empty());
+        // Marked invalid
+        assert!(annotations[2].faces.is_empty());
+    }
+
+    #[test]
+    fn wider_face_without_face_count_is_an_error() {
+        let content = "0--Parade/a.jpg\n10 20 30 40 0 0 0 0 0 0\n";
+        assert!(parse_wider_face(Path::new("gt.txt"), content).is_err());
+        assert!(parse_wider_face(Path::new("gt.txt"), "a.jpg\n2\n1 2 3 4\n").is_err());
+    }
+
+    #[test]
+    fn pascal_voc_keeps_only_faces() {
+        let content = "<annotation>\
+            <filename>a.jpg</filename>\
+            <object><name>Face</name>\
+                <bndbox><xmin>10</xmin><ymin>20</ymin><xmax>50</xmax><ymax>80</ymax></bndbox>\
+            </object>\
+            <object><name>person</name>\
+                <bndbox><xmin>0</xmin><ymin>0</ymin><xmax>100</xmax><ymax>200</ymax></bndbox>\
+            </object>\
+            <object>\
+                <bndbox><xmin>1</xmin><ymin>2</ymin><xmax>4</xmax><ymax>6</ymax></bndbox>\
+            </object>\
+            </annotation>";
+        let annotation = parse_pascal_voc(Path::new("a.xml"), content).unwrap();
+
+        assert_eq!(annotation.file_name, "a.jpg");
+        assert_eq!(
+            annotation.faces,
+            vec![bbox(10., 20., 40., 60.), bbox(1., 2., 3., 4.)]
+        );
+    }
+
+    #[test]
+    fn pascal_voc_errors() {
+        let path = Path::new("a.xml");
+        assert!(parse_pascal_voc(path, "<annotation></annotation>").is_err());
+        assert!(parse_pascal_voc(
+            path,
+            "<filename>a.jpg</filename><object><name>face</name></object>"
+        )
+        .is_err());
+        assert!(parse_pascal_voc(
+            path,
+            "<filename>a.jpg</filename><object><bndbox><xmin>x</xmin></bndbox></object>"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn coco_uses_the_face_category() {
+        let content = r#"{
+            "images": [{ "id": 1, "file_name": "a.jpg" }, { "id": 2, "file_name": "b.jpg" }],
+            "categories": [{ "id": 1, "name": "face" }, { "id": 2, "name": "person" }],
+            "annotations": [
+                { "image_id": 1, "category_id": 1, "bbox": [1, 2, 3, 4] },
+                { "image_id": 1, "category_id": 2, "bbox": [0, 0, 100, 200] },
+                { "image_id": 2, "category_id": 1, "bbox": [5.5, 6, 7, 8] }
+            ]
+        }"#;
+        let annotations = parse_coco(Path::new("coco.json"), content).unwrap();
+
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].file_name, "a.jpg");
+        assert_eq!(annotations[0].faces, vec![bbox(1., 2., 3., 4.)]);
+        assert_eq!(annotations[1].faces, vec![bbox(5.5, 6., 7., 8.)]);
+    }
+
+    #[test]
+    fn coco_without_face_category_counts_everything() {
+        let content = r#"{
+            "images": [{ "id": 7, "file_name": "a.jpg" }, { "id": 8, "file_name": "b.jpg" }],
+            "annotations": [
+                { "image_id": 7, "category_id": 3, "bbox": [1, 2, 3, 4] },
+                { "image_id": 7, "category_id": 4, "bbox": [5, 6, 7, 8] }
+            ]
+        }"#;
+        let annotations = parse_coco(Path::new("coco.json"), content).unwrap();
+
+        assert_eq!(annotations[0].faces.len(), 2);
+        assert!(annotations[1].faces.is_empty());
+    }
+
+    #[test]
+    fn coco_errors() {
+        let path = Path::new("coco.json");
+        assert!(parse_coco(path, "not json").is_err());
+        assert!(parse_coco(path, "{}").is_err());
+        // Unknown image
+        assert!(parse_coco(
+            path,
+            r#"{ "images": [], "annotations": [{ "image_id": 1, "bbox": [1, 2, 3, 4] }] }"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    error::{Context, Error, Result},
+    postprocess::{PostProcessSettings, ScoredRect},
+    transform::CoordinateTransform,
+};
+use opencv::{core, imgproc, objdetect, prelude::*, types};
+use serde::{Deserialize, Serialize};
+
+/// The face cascade shipped with OpenCV
+pub const FACE_CASCADE_FILE: &'static str = "haarcascades/haarcascade_frontalface_alt.xml";
+
+/// Parameters passed to `CascadeClassifier::detect_multi_scale`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DetectorSettings {
+    /// How much the image size is reduced at each image scale, must be greater than `1.0`
+    pub scale_factor: f64,
+    /// How many neighbors each candidate rectangle should have to retain it
+    pub min_neighbors: i32,
+    /// Minimum possible face size (in the reduced detection image), smaller faces are ignored
+    pub min_face_size: i32,
+    /// The frame is resized by this factor before detection, smaller is faster but misses
+    /// small faces. `1.0` detects on the full frame.
+    pub downscale: f64,
+}
+
+impl Default for DetectorSettings {
+    fn default() -> Self {
+        DetectorSettings {
+            scale_factor: 1.1,
+            min_neighbors: 2,
+            min_face_size: 30,
+            downscale: 0.25,
+        }
+    }
+}
+
+impl DetectorSettings {
+    /// `detect_multi_scale` throws on invalid values, better to catch them before that
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.scale_factor <= 1.0 {
+            return Err(format!(
+                "scale_factor must be greater than 1.0, got: {}",
+                self.scale_factor
+            ));
+        }
+        if self.min_neighbors < 0 {
+            return Err(format!(
+                "min_neighbors can't be negative, got: {}",
+                self.min_neighbors
+            ));
+        }
+        if self.min_face_size < 0 {
+            return Err(format!(
+                "min_face_size can't be negative, got: {}",
+                self.min_face_size
+            ));
+        }
+        if self.downscale <= 0. || self.downscale > 1. {
+            return Err(format!(
+                "downscale must be in (0, 1], got: {}",
+                self.downscale
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The faces found in the detection image and how that image was made from the frame
+#[derive(Debug, Clone)]
+pub struct FaceDetections {
+    /// In the detection image coordinates, the score is the neighbor count (`0` if the
+    /// detector didn't report it)
+    pub faces: Vec<ScoredRect>,
+    /// Maps the faces back to the frame coordinates
+    pub transform: CoordinateTransform,
+}
+
+impl FaceDetections {
+    /// The faces in the frame coordinates, with their scores
+    pub fn frame_faces(&self) -> Vec<ScoredRect> {
+        self.faces
+            .iter()
+            .map(|face| ScoredRect {
+                rect: self.transform.to_frame_rect(face.rect),
+                score: face.score,
+            })
+            .collect()
+    }
+
+    /// The faces in the frame coordinates
+    pub fn frame_rects(&self) -> Vec<core::Rect> {
+        self.faces
+            .iter()
+            .map(|face| self.transform.to_frame_rect(face.rect))
+            .collect()
+    }
+
+    ///
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+}
+
+/// Keep a face (frame coordinates) inside the frame, the scaled up detection can go over the
+/// edge, and a ROI outside of the frame throws. A face entirely outside of the frame ends up
+/// empty (zero width or height).
+pub fn clamp_to_frame(rect: core::Rect, frame_size: core::Size) -> core::Rect {
+    let x = rect.x.max(0).min(frame_size.width);
+    let y = rect.y.max(0).min(frame_size.height);
+    core::Rect {
+        x,
+        y,
+        width: ((rect.x + rect.width).min(frame_size.width) - x).max(0),
+        height: ((rect.y + rect.height).min(frame_size.height) - y).max(0),
+    }
+}
+
+/// Find a cascade file (e.g. `FACE_CASCADE_FILE`) in the OpenCV data folder and load it
+pub fn load_cascade(file_name: &str) -> Result<objdetect::CascadeClassifier> {
+    let xml = core::find_file(file_name, true, false)
+        .map_err(|error| Error::CascadeLoad(error.to_string()))?;
+    let cascade = objdetect::CascadeClassifier::new(&xml)
+        .map_err(|error| Error::CascadeLoad(format!("{}: {}", xml, error)))?;
+    if cascade.empty()? {
+        return Err(Error::CascadeLoad(xml));
+    }
+    Ok(cascade)
+}
+
+/// Find the face cascade file in the OpenCV data folder and load it
+pub fn load_face_classifier() -> Result<objdetect::CascadeClassifier> {
+    load_cascade(FACE_CASCADE_FILE)
+}
+
+/// Grayscale and reduced frame, what the classifier runs on, and how it maps to the frame. An
+/// already grayscale frame (e.g. a dataset image converted once for many runs) is used as is.
+fn reduce_for_detection(frame: &Mat, downscale: f64) -> Result<(Mat, CoordinateTransform)> {
+    // Convert every frame into gray color
+    let gray = if frame.channels()? == 1 {
+        frame.clone()
+    } else {
+        let mut gray = Mat::default()?;
+        imgproc::cvt_color(&frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)
+            .context("Convert frame to grayscale for detection")?;
+        gray
+    };
+
+    // Reduce the image size for fast face detection
+    let mut reduced = Mat::default()?;
+    imgproc::resize(
+        &gray,
+        &mut reduced,
+        core::Size {
+            width: 0,
+            height: 0,
+        },
+        downscale,
+        downscale,
+        imgproc::INTER_LINEAR,
+    )
+    .context("Reduce frame for detection")?;
+
+    // From the real sizes, `resize` rounds them
+    let transform = CoordinateTransform::new(gray.size()?).resize(reduced.size()?);
+    Ok((reduced, transform))
+}
+
+/// Detect faces on the reduced grayscale frame (see `DetectorSettings::downscale`), the
+/// returned faces are in the reduced image coordinates, `FaceDetections::transform` maps them
+/// back
+pub fn face_detection_on_frame(
+    face: &mut objdetect::CascadeClassifier,
+    frame: &Mat,
+    detector_settings: &DetectorSettings,
+) -> Result<FaceDetections> {
+    let (reduced, transform) = reduce_for_detection(frame, detector_settings.downscale)?;
+
+    // Run face detection
+    let mut detected_faces = types::VectorOfRect::new();
+    face.detect_multi_scale(
+        &reduced,
+        &mut detected_faces,
+        detector_settings.scale_factor,
+        detector_settings.min_neighbors,
+        objdetect::CASCADE_SCALE_IMAGE,
+        core::Size {
+            width: detector_settings.min_face_size,
+            height: detector_settings.min_face_size,
+        },
+        core::Size {
+            width: 0,
+            height: 0,
+        },
+    )
+    .context("Detect faces")?;
+
+    Ok(FaceDetections {
+        faces: detected_faces
+            .iter()
+            .map(|rect| ScoredRect { rect, score: 0. })
+            .collect(),
+        transform,
+    })
+}
+
+/// Same as `face_detection_on_frame`, plus how many neighbors every face has. More neighbors
+/// means a more confident detection, that's the score used to rank faces in the evaluation.
+pub fn face_detection_with_scores(
+    face: &mut objdetect::CascadeClassifier,
+    frame: &Mat,
+    detector_settings: &DetectorSettings,
+) -> Result<FaceDetections> {
+    let (reduced, transform) = reduce_for_detection(frame, detector_settings.downscale)?;
+
+    let mut detected_faces = types::VectorOfRect::new();
+    let mut neighbors = types::VectorOfi32::new();
+    face.detect_multi_scale2(
+        &reduced,
+        &mut detected_faces,
+        &mut neighbors,
+        detector_settings.scale_factor,
+        detector_settings.min_neighbors,
+        objdetect::CASCADE_SCALE_IMAGE,
+        core::Size {
+            width: detector_settings.min_face_size,
+            height: detector_settings.min_face_size,
+        },
+        core::Size {
+            width: 0,
+            height: 0,
+        },
+    )
+    .context("Detect faces with scores")?;
+
+    Ok(FaceDetections {
+        faces: detected_faces
+            .iter()
+            .zip(neighbors.iter())
+            .map(|(rect, neighbors)| ScoredRect {
+                rect,
+                score: neighbors as f64,
+            })
+            .collect(),
+        transform,
+    })
+}
+
+/// `face_detection_with_scores` in the frame coordinates, with the overlapping and
+/// implausible faces cleaned up by `postprocess`. That's what should be drawn and exported.
+pub fn detect_faces(
+    face: &mut objdetect::CascadeClassifier,
+    frame: &Mat,
+    detector_settings: &DetectorSettings,
+    postprocess: &PostProcessSettings,
+) -> Result<Vec<ScoredRect>> {
+    let detections = face_detection_with_scores(face, frame, detector_settings)?;
+    Ok(postprocess.apply(detections.frame_faces()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_SIZE: core::Size = core::Size {
+        width: 640,
+        height: 480,
+    };
+
+    #[test]
+    fn clamp_to_frame_cuts_the_part_outside_of_the_frame() {
+        assert_eq!(
+            clamp_to_frame(core::Rect::new(10, 20, 30, 40), FRAME_SIZE),
+            core::Rect::new(10, 20, 30, 40)
+        );
+        assert_eq!(
+            clamp_to_frame(core::Rect::new(-10, -20, 30, 40), FRAME_SIZE),
+            core::Rect::new(0, 0, 20, 20)
+        );
+        assert_eq!(
+            clamp_to_frame(core::Rect::new(620, 460, 30, 40), FRAME_SIZE),
+            core::Rect::new(620, 460, 20, 20)
+        );
+    }
+
+    #[test]
+    fn clamp_to_frame_of_a_rect_outside_of_the_frame_is_empty() {
+        let left = clamp_to_frame(core::Rect::new(-100, 10, 30, 40), FRAME_SIZE);
+        assert_eq!((left.width, left.height), (0, 40));
+        let above = clamp_to_frame(core::Rect::new(10, -100, 30, 40), FRAME_SIZE);
+        assert_eq!((above.width, above.height), (30, 0));
+        let right = clamp_to_frame(core::Rect::new(700, 10, 30, 40), FRAME_SIZE);
+        assert_eq!((right.width, right.height), (0, 40));
+    }
+}
@@ -0,0 +1,339 @@
+use crate::error::{Context, Error, Result};
+use opencv::{core, highgui, imgproc, prelude::*};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Which window implementation shows the frames, picked on the command line (`--display`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayBackend {
+    /// OpenCV `highgui`, needs OpenCV built with GTK/Qt/Cocoa/Win32 support. The only one with
+    /// mouse callbacks and trackbars.
+    Highgui,
+    /// Pure Rust window (`minifb`), works with a headless OpenCV build
+    Minifb,
+}
+
+impl Default for DisplayBackend {
+    fn default() -> Self {
+        DisplayBackend::Highgui
+    }
+}
+
+impl DisplayBackend {
+    /// `"highgui"` or `"minifb"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "highgui" => Some(DisplayBackend::Highgui),
+            "minifb" => Some(DisplayBackend::Minifb),
+            _ => None,
+        }
+    }
+}
+
+/// A preview window
+pub trait Display {
+    /// Show a BGR (or grayscale) frame
+    fn show(&mut self, frame: &Mat) -> Result<()>;
+
+    /// Wait up to `delay_ms` for a key, like `highgui::wait_key`: the key code (ASCII for
+    /// letters, digits, space, enter and `Esc` = 27) or `-1`
+    fn wait_key(&mut self, delay_ms: i32) -> Result<i32>;
+
+    /// Which implementation this is, only `highgui` has mouse callbacks and trackbars
+    fn backend(&self) -> DisplayBackend;
+}
+
+/// Open a window named `window_name`, `highgui_flags` (`highgui::WINDOW_*`) only apply to
+/// `highgui`
+pub fn open_display(
+    backend: DisplayBackend,
+    window_name: &str,
+    highgui_flags: i32,
+) -> Result<Box<dyn Display>> {
+    match backend {
+        DisplayBackend::Highgui => Ok(Box::new(HighguiDisplay::new(window_name, highgui_flags)?)),
+        DisplayBackend::Minifb => Ok(Box::new(MinifbDisplay::new(window_name))),
+    }
+}
+
+/// `highgui::destroy_all_windows` for `highgui`, the `minifb` windows close when dropped (a
+/// headless OpenCV throws on any `highgui` call)
+pub fn destroy_all_windows(backend: DisplayBackend) -> Result<()> {
+    match backend {
+        DisplayBackend::Highgui => {
+            highgui::destroy_all_windows().context("Close all windows")?;
+            Ok(())
+        }
+        DisplayBackend::Minifb => Ok(()),
+    }
+}
+
+/// `highgui::imshow` and `highgui::wait_key`
+pub struct HighguiDisplay {
+    window_name: String,
+}
+
+impl HighguiDisplay {
+    /// `WINDOW_OPENGL` fails on the OpenCV builds without OpenGL support, then the window is
+    /// created without it
+    pub fn new(window_name: &str, flags: i32) -> Result<Self> {
+        if highgui::named_window(window_name, flags).is_err() {
+            highgui::named_window(window_name, flags & !highgui::WINDOW_OPENGL)
+                .context("Create preview window")?;
+        }
+        Ok(HighguiDisplay {
+            window_name: window_name.to_string(),
+        })
+    }
+}
+
+impl Display for HighguiDisplay {
+    fn show(&mut self, frame: &Mat) -> Result<()> {
+        highgui::imshow(&self.window_name, frame).context("Show frame")
+    }
+
+    fn wait_key(&mut self, delay_ms: i32) -> Result<i32> {
+        highgui::wait_key(delay_ms).context("Wait for key")
+    }
+
+    fn backend(&self) -> DisplayBackend {
+        DisplayBackend::Highgui
+    }
+}
+
+/// The frame as `0RGB` pixels (what `minifb` wants), `frame` is BGR, BGRA or grayscale 8 bit.
+/// Returns `(pixels, width, height)`.
+pub fn bgr_to_rgb_buffer(frame: &Mat) -> Result<(Vec<u32>, usize, usize)> {
+    let bgr = match frame.channels()? {
+        3 => frame.clone(),
+        channels => {
+            let code = if channels == 1 {
+                imgproc::COLOR_GRAY2BGR
+            } else {
+                imgproc::COLOR_BGRA2BGR
+            };
+            let mut bgr = Mat::default()?;
+            imgproc::cvt_color(frame, &mut bgr, code, 0).context("Convert frame to BGR")?;
+            bgr
+        }
+    };
+    // `data_typed` needs one continuous block, a ROI isn't
+    let bgr = if bgr.is_continuous()? {
+        bgr
+    } else {
+        bgr.clone()
+    };
+
+    let pixels = bgr
+        .data_typed::<core::Vec3b>()?
+        .iter()
+        .map(|pixel| {
+            let [blue, green, red] = pixel.0;
+            (red as u32) << 16 | (green as u32) << 8 | blue as u32
+        })
+        .collect();
+    Ok((pixels, bgr.cols() as usize, bgr.rows() as usize))
+}
+
+/// `minifb` window, created (again) with the size of the frame
+pub struct MinifbDisplay {
+    window_name: String,
+    window: Option<minifb::Window>,
+    /// `(width, height)` of the window
+    size: (usize, usize),
+}
+
+impl MinifbDisplay {
+    /// The window opens with the first frame
+    pub fn new(window_name: &str) -> Self {
+        MinifbDisplay {
+            window_name: window_name.to_string(),
+            window: None,
+            size: (0, 0),
+        }
+    }
+
+    /// `minifb` key to the `highgui::wait_key` code
+    fn key_code(key: minifb::Key) -> Option<i32> {
+        use minifb::Key;
+
+        let code = key as i32;
+        match key {
+            // `Key::Key0` .. `Key::Key9` come first, then `Key::A` .. `Key::Z`
+            _ if code <= Key::Key9 as i32 => Some('0' as i32 + code),
+            _ if code >= Key::A as i32 && code <= Key::Z as i32 => {
+                Some('a' as i32 + code - Key::A as i32)
+            }
+            Key::Space => Some(32),
+            Key::Enter | Key::NumPadEnter => Some(13),
+            Key::Escape => Some(27),
+            // The playback shortcuts
+            Key::Period => Some('.' as i32),
+            Key::Comma => Some(',' as i32),
+            Key::LeftBracket => Some('[' as i32),
+            Key::RightBracket => Some(']' as i32),
+            Key::Minus | Key::NumPadMinus => Some('-' as i32),
+            // `+` is `Shift` + `=` on most layouts
+            Key::Equal | Key::NumPadPlus => Some('+' as i32),
+            _ => None,
+        }
+    }
+}
+
+impl Display for MinifbDisplay {
+    fn show(&mut self, frame: &Mat) -> Result<()> {
+        let (pixels, width, height) = bgr_to_rgb_buffer(frame)?;
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+
+        if self.window.is_none() || self.size != (width, height) {
+            let mut window = minifb::Window::new(
+                &self.window_name,
+                width,
+                height,
+                minifb::WindowOptions::default(),
+            )
+            .map_err(|error| Error::Display(format!("{}: {}", self.window_name, error)))?;
+            // `wait_key` does the waiting
+            window.limit_update_rate(None);
+            self.window = Some(window);
+            self.size = (width, height);
+        }
+
+        if let Some(window) = self.window.as_mut() {
+            window
+                .update_with_buffer(&pixels, width, height)
+                .map_err(|error| Error::Display(format!("{}: {}", self.window_name, error)))?;
+        }
+        Ok(())
+    }
+
+    fn wait_key(&mut self, delay_ms: i32) -> Result<i32> {
+        let window = match self.window.as_mut() {
+            Some(window) => window,
+            None => {
+                thread::sleep(Duration::from_millis(delay_ms.max(1) as u64));
+                return Ok(-1);
+            }
+        };
+
+        // `highgui::wait_key(0)` waits forever
+        let deadline = if delay_ms > 0 {
+            Some(Instant::now() + Duration::from_millis(delay_ms as u64))
+        } else {
+            None
+        };
+        loop {
+            window.update();
+            // Closing the window is the same as `Esc`
+            if !window.is_open() {
+                return Ok(27);
+            }
+            let key_code = window
+                .get_keys_pressed(minifb::KeyRepeat::No)
+                .unwrap_or_default()
+                .into_iter()
+                .find_map(Self::key_code);
+            if let Some(key_code) = key_code {
+                return Ok(key_code);
+            }
+
+            if deadline
+                .map(|deadline| Instant::now() >= deadline)
+                .unwrap_or(false)
+            {
+                return Ok(-1);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn backend(&self) -> DisplayBackend {
+        DisplayBackend::Minifb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minifb::Key;
+
+    fn frame(typ: i32, color: core::Scalar) -> Mat {
+        Mat::new_rows_cols_with_default(2, 3, typ, color).unwrap()
+    }
+
+    #[test]
+    fn key_code_maps_digits_and_letters_to_ascii() {
+        assert_eq!(MinifbDisplay::key_code(Key::Key0), Some('0' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::Key9), Some('9' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::A), Some('a' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::Q), Some('q' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::Z), Some('z' as i32));
+    }
+
+    #[test]
+    fn key_code_maps_the_shortcut_keys() {
+        assert_eq!(MinifbDisplay::key_code(Key::Escape), Some(27));
+        assert_eq!(MinifbDisplay::key_code(Key::Space), Some(32));
+        assert_eq!(MinifbDisplay::key_code(Key::Enter), Some(13));
+        assert_eq!(MinifbDisplay::key_code(Key::NumPadEnter), Some(13));
+        assert_eq!(MinifbDisplay::key_code(Key::Period), Some('.' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::Comma), Some(',' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::LeftBracket), Some('[' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::RightBracket), Some(']' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::Minus), Some('-' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::Equal), Some('+' as i32));
+        assert_eq!(MinifbDisplay::key_code(Key::NumPadPlus), Some('+' as i32));
+    }
+
+    #[test]
+    fn key_code_ignores_the_other_keys() {
+        assert_eq!(MinifbDisplay::key_code(Key::F1), None);
+        assert_eq!(MinifbDisplay::key_code(Key::LeftShift), None);
+        assert_eq!(MinifbDisplay::key_code(Key::Up), None);
+    }
+
+    #[test]
+    fn bgr_to_rgb_buffer_swaps_the_channels() {
+        // (Blue, Green, Red, Alpha)
+        let mut bgr = frame(core::CV_8UC3, core::Scalar::new(1., 2., 3., 0.));
+        bgr.at_2d_mut::<core::Vec3b>(1, 2).unwrap().0 = [10, 20, 30];
+
+        let (pixels, width, height) = bgr_to_rgb_buffer(&bgr).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(pixels.len(), 6);
+        assert_eq!(pixels[0], 0x03_02_01);
+        assert_eq!(pixels[5], 0x1e_14_0a);
+    }
+
+    #[test]
+    fn bgr_to_rgb_buffer_of_grayscale_is_gray() {
+        let gray = frame(core::CV_8UC1, core::Scalar::all(7.));
+
+        let (pixels, width, height) = bgr_to_rgb_buffer(&gray).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(pixels, vec![0x07_07_07; 6]);
+    }
+
+    #[test]
+    fn bgr_to_rgb_buffer_drops_the_alpha() {
+        let bgra = frame(core::CV_8UC4, core::Scalar::new(1., 2., 3., 255.));
+
+        let (pixels, width, height) = bgr_to_rgb_buffer(&bgra).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(pixels, vec![0x03_02_01; 6]);
+    }
+
+    #[test]
+    fn bgr_to_rgb_buffer_of_a_roi() {
+        let bgr = frame(core::CV_8UC3, core::Scalar::new(1., 2., 3., 0.));
+        let roi = core::Mat::roi(&bgr, core::Rect::new(1, 0, 2, 2)).unwrap();
+
+        let (pixels, width, height) = bgr_to_rgb_buffer(&roi).unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(pixels, vec![0x03_02_01; 4]);
+    }
+}
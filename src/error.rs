@@ -0,0 +1,119 @@
+use std::{fmt, io};
+
+/// All errors in this crate, every variant maps to its own process exit code, so the scripts
+/// running the examples can tell what went wrong.
+#[derive(Debug)]
+pub enum Error {
+    /// Camera or video file can't be opened
+    SourceOpen(String),
+    /// The cascade classifier (or any other model) file can't be loaded
+    CascadeLoad(String),
+    /// Image/video encoding or decoding failed
+    Codec(String),
+    /// The source returned an empty frame (e.g. camera disconnected)
+    EmptyFrame(String),
+    /// Any other OpenCV call failed, `context` tells what we were doing
+    OpenCv {
+        context: String,
+        source: opencv::Error,
+    },
+    /// File system or network error
+    Io { context: String, source: io::Error },
+    /// Invalid config or command line arguments
+    Config(String),
+    /// HTTP server or event sink can't be started
+    Server(String),
+    /// The preview window can't be created or updated
+    Display(String),
+    /// A DNN model (e.g. the object detection YOLO/SSD) can't be loaded
+    ModelLoad(String),
+}
+
+/// Shortcut for `std::result::Result<T, rust_opencv::error::Error>`
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Exit code for the examples, `0` is success, `1` is reserved for panics.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::SourceOpen(_) => 2,
+            Error::CascadeLoad(_) => 3,
+            Error::Codec(_) => 4,
+            Error::EmptyFrame(_) => 5,
+            Error::OpenCv { .. } => 6,
+            Error::Io { .. } => 7,
+            Error::Config(_) => 8,
+            Error::Server(_) => 9,
+            Error::Display(_) => 10,
+            Error::ModelLoad(_) => 11,
+        }
+    }
+
+    ///
+    pub fn io<C: Into<String>>(context: C, source: io::Error) -> Self {
+        Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::SourceOpen(message) => write!(f, "Unable to open video source: {}", message),
+            Error::CascadeLoad(message) => write!(f, "Unable to load classifier: {}", message),
+            Error::Codec(message) => write!(f, "Codec error: {}", message),
+            Error::EmptyFrame(message) => write!(f, "Empty frame: {}", message),
+            Error::OpenCv { context, source } if context.is_empty() => {
+                write!(f, "OpenCV error: {}", source)
+            }
+            Error::OpenCv { context, source } => write!(f, "{}: {}", context, source),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+            Error::Config(message) => write!(f, "Invalid config or argument: {}", message),
+            Error::Server(message) => write!(f, "Server error: {}", message),
+            Error::Display(message) => write!(f, "Display error: {}", message),
+            Error::ModelLoad(message) => write!(f, "Unable to load model: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::OpenCv { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Plain `?` on an OpenCV call, use `.context()` if there is something useful to say
+impl From<opencv::Error> for Error {
+    fn from(source: opencv::Error) -> Self {
+        Error::OpenCv {
+            context: String::new(),
+            source,
+        }
+    }
+}
+
+/// Attach what we were doing to an OpenCV error, e.g.:
+///
+/// ```ignore
+/// imgproc::cvt_color(&frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)
+///     .context("Convert frame to grayscale")?;
+/// ```
+pub trait Context<T> {
+    ///
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+}
+
+impl<T> Context<T> for opencv::Result<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|source| Error::OpenCv {
+            context: context.into(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,474 @@
+use crate::{
+    dataset::{BoundingBox, ImageAnnotation},
+    detection::{detect_faces, DetectorSettings},
+    error::{Context, Error, Result},
+    postprocess::PostProcessSettings,
+};
+use opencv::{imgcodecs, objdetect, prelude::*};
+use serde::Serialize;
+use std::{path::Path, time::Instant};
+
+/// `0.5` is the PASCAL VOC threshold, `0.75` shows how tight the boxes are
+pub const DEFAULT_IOU_THRESHOLDS: &[f64] = &[0.5, 0.75];
+
+/// A detected face with its confidence
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ScoredBox {
+    #[serde(flatten)]
+    pub bbox: BoundingBox,
+    pub score: f64,
+}
+
+/// An annotated image, read and converted to grayscale once for any number of detector runs
+pub struct DatasetImage {
+    pub file_name: String,
+    pub ground_truth: Vec<BoundingBox>,
+    pub gray: Mat,
+}
+
+/// The detector output for one annotated image
+#[derive(Debug, Clone)]
+pub struct ImageDetections {
+    pub file_name: String,
+    pub ground_truth: Vec<BoundingBox>,
+    pub detections: Vec<ScoredBox>,
+    pub detection_ms: f64,
+}
+
+/// Read every annotated image, `images_dir` is the folder the annotation file names are
+/// relative to
+pub fn load_dataset(
+    images_dir: &Path,
+    annotations: &[ImageAnnotation],
+) -> Result<Vec<DatasetImage>> {
+    let mut images = Vec::with_capacity(annotations.len());
+    for annotation in annotations {
+        let image_path = images_dir.join(&annotation.file_name);
+        let image = imgcodecs::imread(&image_path.to_string_lossy(), imgcodecs::IMREAD_GRAYSCALE)
+            .context("Read dataset image")?;
+        if image.cols() <= 0 {
+            return Err(Error::Codec(format!(
+                "Unable to read dataset image: {}",
+                image_path.display()
+            )));
+        }
+
+        images.push(DatasetImage {
+            file_name: annotation.file_name.clone(),
+            ground_truth: annotation.faces.clone(),
+            gray: image,
+        });
+    }
+    Ok(images)
+}
+
+/// Run the detector with the post-processing on every image, the same `detect_faces` the
+/// capture loop runs. `detection_ms` doesn't include reading the image.
+pub fn detect_dataset(
+    face: &mut objdetect::CascadeClassifier,
+    images: &[DatasetImage],
+    detector_settings: &DetectorSettings,
+    postprocess: &PostProcessSettings,
+) -> Result<Vec<ImageDetections>> {
+    let mut results = Vec::with_capacity(images.len());
+    for image in images {
+        let started_at = Instant::now();
+        let detected_faces = detect_faces(face, &image.gray, detector_settings, postprocess)?;
+        let detection_ms = started_at.elapsed().as_secs_f64() * 1000.;
+
+        let detections = detected_faces
+            .into_iter()
+            .map(|detected_face| ScoredBox {
+                bbox: BoundingBox {
+                    x: detected_face.rect.x as f64,
+                    y: detected_face.rect.y as f64,
+                    width: detected_face.rect.width as f64,
+                    height: detected_face.rect.height as f64,
+                },
+                score: detected_face.score,
+            })
+            .collect();
+
+        results.push(ImageDetections {
+            file_name: image.file_name.clone(),
+            ground_truth: image.ground_truth.clone(),
+            detections,
+            detection_ms,
+        });
+    }
+    Ok(results)
+}
+
+/// Which detections are true positives and which ground-truth faces are found
+struct ImageMatch {
+    /// Same order as `ImageDetections::detections`
+    is_true_positive: Vec<bool>,
+    /// Same order as `ImageDetections::ground_truth`
+    is_found: Vec<bool>,
+}
+
+/// Greedy matching like PASCAL VOC: the most confident detection first takes the unmatched
+/// ground-truth face it overlaps most, if the IoU reaches the threshold
+fn match_image(image: &ImageDetections, iou_threshold: f64) -> ImageMatch {
+    let mut order: Vec<usize> = (0..image.detections.len()).collect();
+    order.sort_by(|a, b| {
+        image.detections[*b]
+            .score
+            .partial_cmp(&image.detections[*a].score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut image_match = ImageMatch {
+        is_true_positive: vec![false; image.detections.len()],
+        is_found: vec![false; image.ground_truth.len()],
+    };
+    for detection_index in order {
+        let detection = &image.detections[detection_index].bbox;
+        let best_match = image
+            .ground_truth
+            .iter()
+            .enumerate()
+            .filter(|(face_index, _)| !image_match.is_found[*face_index])
+            .map(|(face_index, face)| (face_index, face.intersection_over_union(detection)))
+            .filter(|(_, iou)| *iou >= iou_threshold)
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some((face_index, _)) = best_match {
+            image_match.is_found[face_index] = true;
+            image_match.is_true_positive[detection_index] = true;
+        }
+    }
+    image_match
+}
+
+/// The metrics at one IoU threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct ThresholdMetrics {
+    pub iou_threshold: f64,
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+    pub precision: f64,
+    pub recall: f64,
+    /// Area under the precision/recall curve (all points interpolated), detections ranked by
+    /// score
+    pub average_precision: f64,
+}
+
+/// An image with missed faces or false detections (at the first IoU threshold)
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageErrors {
+    pub file_name: String,
+    pub ground_truth_faces: usize,
+    pub detected_faces: usize,
+    pub false_positives: Vec<ScoredBox>,
+    pub missed_faces: Vec<BoundingBox>,
+}
+
+/// What the evaluation command writes out as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct EvaluationReport {
+    pub image_count: usize,
+    pub ground_truth_faces: usize,
+    pub detected_faces: usize,
+    pub mean_detection_ms: f64,
+    pub thresholds: Vec<ThresholdMetrics>,
+    /// The mean of `average_precision` over all thresholds
+    pub mean_average_precision: f64,
+    /// Only the images with errors, the worst first
+    pub image_errors: Vec<ImageErrors>,
+}
+
+///
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator > 0 {
+        numerator as f64 / denominator as f64
+    } else {
+        0.
+    }
+}
+
+/// `ranked` is `(score, is_true_positive)` for every detection of the dataset
+fn average_precision(mut ranked: Vec<(f64, bool)>, ground_truth_faces: usize) -> f64 {
+    if ground_truth_faces == 0 {
+        return 0.;
+    }
+    ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut curve = Vec::with_capacity(ranked.len());
+    let mut true_positives = 0;
+    for (rank, (_, is_true_positive)) in ranked.iter().enumerate() {
+        if *is_true_positive {
+            true_positives += 1;
+        }
+        curve.push((
+            ratio(true_positives, ground_truth_faces),
+            ratio(true_positives, rank + 1),
+        ));
+    }
+
+    // Make the precision monotonic from the right, then sum the area of every recall step
+    for index in (0..curve.len().saturating_sub(1)).rev() {
+        curve[index].1 = curve[index].1.max(curve[index + 1].1);
+    }
+    let mut area = 0.;
+    let mut previous_recall = 0.;
+    for (recall, precision) in curve {
+        area += (recall - previous_recall) * precision;
+        previous_recall = recall;
+    }
+    area
+}
+
+/// Precision, recall and AP at every IoU threshold, plus the per-image errors at the first
+/// threshold
+pub fn evaluate(images: &[ImageDetections], iou_thresholds: &[f64]) -> EvaluationReport {
+    let ground_truth_faces: usize = images.iter().map(|image| image.ground_truth.len()).sum();
+    let detected_faces: usize = images.iter().map(|image| image.detections.len()).sum();
+
+    let mut thresholds = Vec::with_capacity(iou_thresholds.len());
+    let mut image_errors = Vec::new();
+    for (threshold_index, iou_threshold) in iou_thresholds.iter().enumerate() {
+        let mut ranked = Vec::with_capacity(detected_faces);
+        for image in images {
+            let image_match = match_image(image, *iou_threshold);
+            for (detection, is_true_positive) in image
+                .detections
+                .iter()
+                .zip(image_match.is_true_positive.iter())
+            {
+                ranked.push((detection.score, *is_true_positive));
+            }
+
+            if threshold_index == 0 {
+                let false_positives: Vec<ScoredBox> = image
+                    .detections
+                    .iter()
+                    .zip(image_match.is_true_positive.iter())
+                    .filter(|(_, is_true_positive)| !**is_true_positive)
+                    .map(|(detection, _)| *detection)
+                    .collect();
+                let missed_faces: Vec<BoundingBox> = image
+                    .ground_truth
+                    .iter()
+                    .zip(image_match.is_found.iter())
+                    .filter(|(_, is_found)| !**is_found)
+                    .map(|(face, _)| *face)
+                    .collect();
+                if !false_positives.is_empty() || !missed_faces.is_empty() {
+                    image_errors.push(ImageErrors {
+                        file_name: image.file_name.clone(),
+                        ground_truth_faces: image.ground_truth.len(),
+                        detected_faces: image.detections.len(),
+                        false_positives,
+                        missed_faces,
+                    });
+                }
+            }
+        }
+
+        let true_positives = ranked
+            .iter()
+            .filter(|(_, is_true_positive)| *is_true_positive)
+            .count();
+        thresholds.push(ThresholdMetrics {
+            iou_threshold: *iou_threshold,
+            true_positives,
+            false_positives: detected_faces - true_positives,
+            false_negatives: ground_truth_faces - true_positives,
+            precision: ratio(true_positives, detected_faces),
+            recall: ratio(true_positives, ground_truth_faces),
+            average_precision: average_precision(ranked, ground_truth_faces),
+        });
+    }
+
+    image_errors.sort_by_key(|errors| {
+        std::cmp::Reverse(errors.false_positives.len() + errors.missed_faces.len())
+    });
+
+    let mean_average_precision = if thresholds.is_empty() {
+        0.
+    } else {
+        thresholds
+            .iter()
+            .map(|metrics| metrics.average_precision)
+            .sum::<f64>()
+            / thresholds.len() as f64
+    };
+
+    EvaluationReport {
+        image_count: images.len(),
+        ground_truth_faces,
+        detected_faces,
+        mean_detection_ms: if images.is_empty() {
+            0.
+        } else {
+            images.iter().map(|image| image.detection_ms).sum::<f64>() / images.len() as f64
+        },
+        thresholds,
+        mean_average_precision,
+        image_errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(x: f64, y: f64, width: f64, height: f64) -> BoundingBox {
+        BoundingBox {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn scored(x: f64, y: f64, size: f64, score: f64) -> ScoredBox {
+        ScoredBox {
+            bbox: bbox(x, y, size, size),
+            score,
+        }
+    }
+
+    fn image(
+        file_name: &str,
+        ground_truth: Vec<BoundingBox>,
+        detections: Vec<ScoredBox>,
+        detection_ms: f64,
+    ) -> ImageDetections {
+        ImageDetections {
+            file_name: file_name.to_string(),
+            ground_truth,
+            detections,
+            detection_ms,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn average_precision_of_a_ranked_list() {
+        // Recall/precision: (1/3, 1), (1/3, 1/2), (2/3, 2/3). The precision made monotonic is
+        // 1, 2/3, 2/3, so the area is 1/3 * 1 + 1/3 * 2/3 = 5/9.
+        let ranked = vec![(0.7, true), (0.9, true), (0.8, false)];
+        assert_close(average_precision(ranked, 3), 5. / 9.);
+
+        assert_close(average_precision(vec![(0.9, true), (0.8, true)], 2), 1.);
+        assert_close(average_precision(vec![(0.9, false)], 1), 0.);
+        assert_close(average_precision(Vec::new(), 2), 0.);
+    }
+
+    #[test]
+    fn average_precision_without_ground_truth_is_zero() {
+        assert_close(average_precision(vec![(0.9, false), (0.5, false)], 0), 0.);
+    }
+
+    #[test]
+    fn duplicate_detections_only_match_once() {
+        let image = image(
+            "a.jpg",
+            vec![bbox(0., 0., 10., 10.)],
+            // The less confident one comes first, the most confident one must win
+            vec![scored(1., 1., 10., 0.8), scored(0., 0., 10., 0.9)],
+            0.,
+        );
+        let image_match = match_image(&image, 0.5);
+
+        assert_eq!(image_match.is_true_positive, vec![false, true]);
+        assert_eq!(image_match.is_found, vec![true]);
+    }
+
+    #[test]
+    fn detection_below_the_iou_threshold_is_false_positive() {
+        // IoU of the two boxes is 81 / 119 = 0.68
+        let image = image(
+            "a.jpg",
+            vec![bbox(0., 0., 10., 10.)],
+            vec![scored(1., 1., 10., 0.9)],
+            0.,
+        );
+
+        assert_eq!(match_image(&image, 0.5).is_true_positive, vec![true]);
+        let image_match = match_image(&image, 0.75);
+        assert_eq!(image_match.is_true_positive, vec![false]);
+        assert_eq!(image_match.is_found, vec![false]);
+    }
+
+    #[test]
+    fn evaluate_counts_duplicates_misses_and_images_without_faces() {
+        let images = vec![
+            // One face found twice
+            image(
+                "a.jpg",
+                vec![bbox(0., 0., 10., 10.)],
+                vec![scored(0., 0., 10., 0.9), scored(1., 1., 10., 0.8)],
+                10.,
+            ),
+            // No ground truth, one false detection
+            image("b.jpg", Vec::new(), vec![scored(50., 50., 10., 0.7)], 20.),
+            // One missed face
+            image("c.jpg", vec![bbox(20., 20., 10., 10.)], Vec::new(), 30.),
+        ];
+        let report = evaluate(&images, &[0.5]);
+
+        assert_eq!(report.image_count, 3);
+        assert_eq!(report.ground_truth_faces, 2);
+        assert_eq!(report.detected_faces, 3);
+        assert_close(report.mean_detection_ms, 20.);
+
+        let metrics = &report.thresholds[0];
+        assert_eq!(metrics.true_positives, 1);
+        assert_eq!(metrics.false_positives, 2);
+        assert_eq!(metrics.false_negatives, 1);
+        assert_close(metrics.precision, 1. / 3.);
+        assert_close(metrics.recall, 0.5);
+        // Only the first ranked detection is a hit: recall 1/2 at precision 1
+        assert_close(metrics.average_precision, 0.5);
+        assert_close(report.mean_average_precision, 0.5);
+
+        let error_files: Vec<&str> = report
+            .image_errors
+            .iter()
+            .map(|errors| errors.file_name.as_str())
+            .collect();
+        assert_eq!(error_files, vec!["a.jpg", "b.jpg", "c.jpg"]);
+        assert_eq!(report.image_errors[0].false_positives.len(), 1);
+        assert_eq!(
+            report.image_errors[2].missed_faces,
+            vec![bbox(20., 20., 10., 10.)]
+        );
+    }
+
+    #[test]
+    fn evaluate_averages_the_thresholds() {
+        let images = vec![image(
+            "a.jpg",
+            vec![bbox(0., 0., 10., 10.)],
+            vec![scored(1., 1., 10., 0.9)],
+            0.,
+        )];
+        let report = evaluate(&images, &[0.5, 0.75]);
+
+        assert_close(report.thresholds[0].average_precision, 1.);
+        assert_close(report.thresholds[1].average_precision, 0.);
+        assert_close(report.mean_average_precision, 0.5);
+    }
+
+    #[test]
+    fn evaluate_empty_dataset() {
+        let report = evaluate(&[], DEFAULT_IOU_THRESHOLDS);
+
+        assert_eq!(report.image_count, 0);
+        assert_close(report.mean_detection_ms, 0.);
+        assert_close(report.thresholds[0].precision, 0.);
+        assert_close(report.thresholds[0].recall, 0.);
+        assert!(report.image_errors.is_empty());
+    }
+}
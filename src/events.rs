@@ -0,0 +1,473 @@
+use crate::{
+    alignment::{AlignmentSettings, FaceAligner},
+    detection::clamp_to_frame,
+    error::{Context, Error, Result},
+    tracking::{TrackUpdate, TrackedObject},
+};
+use opencv::{core, imgcodecs, prelude::*, types};
+use rumqttc::{Client, Event, MqttOptions, Outgoing, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Where the events go
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkSettings {
+    /// Publish JSON payload to an MQTT broker with QoS 1 (at least once)
+    Mqtt {
+        host: String,
+        port: u16,
+        topic: String,
+        #[serde(default = "default_mqtt_client_id")]
+        client_id: String,
+    },
+    /// `POST` JSON payload to the URL, any 2xx response means delivered
+    Webhook { url: String },
+}
+
+///
+fn default_mqtt_client_id() -> String {
+    String::from("rust-opencv-face-detection")
+}
+
+/// The `events` section of the config file, e.g.:
+///
+/// ```json
+/// "events": {
+///     "snapshot_dir": "snapshots",
+///     "align_snapshots": true,
+///     "sinks": [
+///         { "type": "mqtt", "host": "localhost", "port": 1883, "topic": "faces/events" },
+///         { "type": "webhook", "url": "http://localhost:9000/face-events" }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventSettings {
+    /// Save a face snapshot when a face appears, the path goes into the event
+    pub snapshot_dir: Option<PathBuf>,
+    /// Save the aligned face (see the `alignment` config) instead of the detected rect
+    pub align_snapshots: bool,
+    pub sinks: Vec<SinkSettings>,
+    /// First retry waits this long, then doubles until `max_backoff_ms`
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Max undelivered events kept in memory per sink, the oldest ones are dropped first
+    pub max_queue_size: usize,
+}
+
+impl Default for EventSettings {
+    fn default() -> Self {
+        EventSettings {
+            snapshot_dir: None,
+            align_snapshots: false,
+            sinks: Vec::new(),
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            max_queue_size: 1000,
+        }
+    }
+}
+
+///
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaceEventKind {
+    FaceAppeared,
+    FaceLeft,
+}
+
+/// The JSON payload sent to the sinks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaceEvent {
+    pub event: FaceEventKind,
+    pub track_id: u32,
+    /// The last known rect in the frame coordinates
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub snapshot_path: Option<PathBuf>,
+    pub timestamp: String,
+}
+
+impl FaceEvent {
+    ///
+    fn new(event: FaceEventKind, object: &TrackedObject, snapshot_path: Option<PathBuf>) -> Self {
+        FaceEvent {
+            event,
+            track_id: object.id,
+            x: object.rect.x,
+            y: object.rect.y,
+            width: object.rect.width,
+            height: object.rect.height,
+            snapshot_path,
+            timestamp: chrono::Local::now()
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string(),
+        }
+    }
+}
+
+/// Deliver one event, `Err` means it should be retried later
+pub trait EventSink: Send {
+    ///
+    fn name(&self) -> String;
+
+    ///
+    fn publish(&mut self, event: &FaceEvent) -> std::result::Result<(), String>;
+}
+
+/// QoS 1 publisher, one event in flight at a time
+pub struct MqttSink {
+    topic: String,
+    host: String,
+    port: u16,
+    client_id: String,
+    client: Client,
+    connection: rumqttc::Connection,
+}
+
+impl MqttSink {
+    ///
+    pub fn new(host: &str, port: u16, topic: &str, client_id: &str) -> Self {
+        let (client, connection) = Self::connect(host, port, client_id);
+
+        MqttSink {
+            topic: topic.to_string(),
+            host: host.to_string(),
+            port,
+            client_id: client_id.to_string(),
+            client,
+            connection,
+        }
+    }
+
+    ///
+    fn connect(host: &str, port: u16, client_id: &str) -> (Client, rumqttc::Connection) {
+        let mut mqtt_options = MqttOptions::new(client_id, host, port);
+        mqtt_options.set_keep_alive(10);
+        Client::new(mqtt_options, 10)
+    }
+
+    /// Queue the event and wait for the `PubAck` of exactly this publish
+    fn publish_and_wait(&mut self, payload: Vec<u8>) -> std::result::Result<(), String> {
+        self.client
+            .publish(self.topic.as_str(), QoS::AtLeastOnce, false, payload)
+            .map_err(|error| error.to_string())?;
+
+        // The connection only makes progress while being iterated, it also (re)connects to the
+        // broker. The packet id is assigned when the publish goes out, delivered means the
+        // broker sent back the `PubAck` with that id.
+        let mut pkid = None;
+        for notification in self.connection.iter() {
+            match notification {
+                Ok(Event::Outgoing(Outgoing::Publish(outgoing_pkid))) if pkid.is_none() => {
+                    pkid = Some(outgoing_pkid)
+                }
+                Ok(Event::Incoming(Packet::PubAck(ack))) if Some(ack.pkid) == pkid => return Ok(()),
+                Ok(_) => continue,
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+
+        Err(String::from("MQTT connection closed"))
+    }
+}
+
+impl EventSink for MqttSink {
+    fn name(&self) -> String {
+        format!("mqtt://{}:{}/{}", self.host, self.port, self.topic)
+    }
+
+    fn publish(&mut self, event: &FaceEvent) -> std::result::Result<(), String> {
+        let payload = serde_json::to_vec(event).map_err(|error| error.to_string())?;
+        let result = self.publish_and_wait(payload);
+        if result.is_err() {
+            // The failed publish may still sit in the request channel (or its ack may come
+            // late), start over with a new client so the retry is the only one in flight
+            let (client, connection) = Self::connect(&self.host, self.port, &self.client_id);
+            self.client = client;
+            self.connection = connection;
+        }
+        result
+    }
+}
+
+///
+pub struct WebhookSink {
+    url: String,
+}
+
+impl WebhookSink {
+    ///
+    pub fn new(url: &str) -> Self {
+        WebhookSink {
+            url: url.to_string(),
+        }
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn publish(&mut self, event: &FaceEvent) -> std::result::Result<(), String> {
+        let payload = serde_json::to_string(event).map_err(|error| error.to_string())?;
+        let response = ureq::post(&self.url)
+            .set("Content-Type", "application/json")
+            .timeout_connect(5_000)
+            .timeout(Duration::from_secs(10))
+            .send_string(&payload);
+
+        if response.ok() {
+            Ok(())
+        } else if let Some(error) = response.synthetic_error() {
+            Err(error.to_string())
+        } else {
+            Err(format!(
+                "HTTP {} {}",
+                response.status(),
+                response.status_text()
+            ))
+        }
+    }
+}
+
+/// Every sink has its own worker thread and offline queue, so a dead broker never blocks the
+/// capture loop or the other sinks. Failed events stay in the queue and will be retried with
+/// exponential backoff.
+fn run_sink_worker(
+    mut sink: Box<dyn EventSink>,
+    receiver: Receiver<FaceEvent>,
+    settings: EventSettings,
+) {
+    let mut queue: VecDeque<FaceEvent> = VecDeque::new();
+    let initial_backoff = Duration::from_millis(settings.initial_backoff_ms);
+    let max_backoff = Duration::from_millis(settings.max_backoff_ms);
+    let mut backoff = initial_backoff;
+    let mut capture_stopped = false;
+
+    let enqueue = |queue: &mut VecDeque<FaceEvent>, event: FaceEvent| {
+        queue.push_back(event);
+        if queue.len() > settings.max_queue_size {
+            queue.pop_front();
+            println!("Event queue full, the oldest event dropped");
+        }
+    };
+
+    loop {
+        // Nothing to send: wait for the next event
+        if queue.is_empty() {
+            if capture_stopped {
+                break;
+            }
+            match receiver.recv() {
+                Ok(event) => enqueue(&mut queue, event),
+                Err(_) => break,
+            }
+        }
+        while let Ok(event) = receiver.try_recv() {
+            enqueue(&mut queue, event);
+        }
+
+        let event = queue.front().unwrap();
+        match sink.publish(event) {
+            Ok(_) => {
+                queue.pop_front();
+                backoff = initial_backoff;
+            }
+            Err(error) => {
+                if capture_stopped {
+                    println!(
+                        "Event sink '{}' still failing ({}), {} events dropped",
+                        sink.name(),
+                        error,
+                        queue.len()
+                    );
+                    break;
+                }
+
+                println!(
+                    "Event sink '{}' failed: {}, retry in {:?} ({} events queued)",
+                    sink.name(),
+                    error,
+                    backoff,
+                    queue.len()
+                );
+
+                // Keep collecting new events while waiting
+                match receiver.recv_timeout(backoff) {
+                    Ok(event) => enqueue(&mut queue, event),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    // Give it one more try without waiting, then give up
+                    Err(RecvTimeoutError::Disconnected) => capture_stopped = true,
+                }
+                backoff = (backoff * 2).min(max_backoff);
+            }
+        }
+    }
+}
+
+/// Turn the tracker updates into `face_appeared`/`face_left` events and send them to all sinks
+pub struct FaceEventPublisher {
+    senders: Vec<Sender<FaceEvent>>,
+    workers: Vec<JoinHandle<()>>,
+    snapshot_dir: Option<PathBuf>,
+    /// The snapshot taken when the face appeared, reused in the `face_left` event
+    snapshot_paths: HashMap<u32, PathBuf>,
+    /// `Some` if the snapshots are aligned
+    aligner: Option<FaceAligner>,
+}
+
+impl FaceEventPublisher {
+    ///
+    pub fn new(settings: &EventSettings, alignment_settings: &AlignmentSettings) -> Result<Self> {
+        if let Some(snapshot_dir) = settings.snapshot_dir.as_ref() {
+            std::fs::create_dir_all(snapshot_dir).map_err(|error| {
+                Error::io(
+                    format!(
+                        "Unable to create snapshot folder '{}'",
+                        snapshot_dir.display()
+                    ),
+                    error,
+                )
+            })?;
+        }
+
+        let mut senders = Vec::new();
+        let mut workers = Vec::new();
+        for sink_settings in settings.sinks.iter() {
+            let sink: Box<dyn EventSink> = match sink_settings {
+                SinkSettings::Mqtt {
+                    host,
+                    port,
+                    topic,
+                    client_id,
+                } => Box::new(MqttSink::new(host, *port, topic, client_id)),
+                SinkSettings::Webhook { url } => Box::new(WebhookSink::new(url)),
+            };
+            println!("Face events will be published to: {}", sink.name());
+
+            let (sender, receiver) = mpsc::channel();
+            let worker_settings = settings.clone();
+            senders.push(sender);
+            workers.push(thread::spawn(move || {
+                run_sink_worker(sink, receiver, worker_settings)
+            }));
+        }
+
+        Ok(FaceEventPublisher {
+            senders,
+            workers,
+            snapshot_dir: settings.snapshot_dir.clone(),
+            snapshot_paths: HashMap::new(),
+            aligner: if settings.snapshot_dir.is_some() && settings.align_snapshots {
+                Some(FaceAligner::new(alignment_settings)?)
+            } else {
+                None
+            },
+        })
+    }
+
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    /// `frame` should be the clean frame (no overlays) for the snapshots
+    pub fn on_track_update(
+        &mut self,
+        update: &TrackUpdate,
+        objects: &[TrackedObject],
+        frame: &Mat,
+    ) -> Result<()> {
+        for track_id in update.appeared.iter() {
+            let object = match objects.iter().find(|object| object.id == *track_id) {
+                Some(object) => object,
+                None => continue,
+            };
+            let snapshot_path = self.save_snapshot(object, frame)?;
+            if let Some(snapshot_path) = snapshot_path.as_ref() {
+                self.snapshot_paths.insert(object.id, snapshot_path.clone());
+            }
+            self.send(FaceEvent::new(
+                FaceEventKind::FaceAppeared,
+                object,
+                snapshot_path,
+            ));
+        }
+
+        for object in update.left.iter() {
+            let snapshot_path = self.snapshot_paths.remove(&object.id);
+            self.send(FaceEvent::new(
+                FaceEventKind::FaceLeft,
+                object,
+                snapshot_path,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Stop accepting events, wait for the workers to deliver (or give up on) the queued ones
+    pub fn shutdown(self) {
+        drop(self.senders);
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+
+    ///
+    fn send(&self, event: FaceEvent) {
+        for sender in self.senders.iter() {
+            let _ = sender.send(event.clone());
+        }
+    }
+
+    /// Save the face area into `snapshot_dir`, e.g. `2020-11-08_21-30-05_track-3.jpg`
+    fn save_snapshot(&mut self, object: &TrackedObject, frame: &Mat) -> Result<Option<PathBuf>> {
+        let snapshot_dir = match self.snapshot_dir.as_ref() {
+            Some(snapshot_dir) => snapshot_dir,
+            None => return Ok(None),
+        };
+
+        // Make sure the rect is inside the frame, otherwise `Mat::roi` fails
+        let face_area = clamp_to_frame(object.rect, frame.size()?);
+        if face_area.width <= 0 || face_area.height <= 0 {
+            return Ok(None);
+        }
+
+        let snapshot_path = snapshot_dir.join(format!(
+            "{}_track-{}.jpg",
+            chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"),
+            object.id
+        ));
+        let face_image = match self.aligner.as_mut() {
+            Some(aligner) => aligner.align(frame, face_area)?,
+            None => core::Mat::roi(frame, face_area).context("Crop face snapshot")?,
+        };
+        let saved = imgcodecs::imwrite(
+            &snapshot_path.to_string_lossy(),
+            &face_image,
+            &types::VectorOfi32::new(),
+        )
+        .context("Save face snapshot")?;
+        if !saved {
+            return Err(Error::Codec(format!(
+                "Unable to save snapshot: {}",
+                snapshot_path.display()
+            )));
+        }
+
+        Ok(Some(snapshot_path))
+    }
+}
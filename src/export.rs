@@ -0,0 +1,99 @@
+use crate::{face_features::FaceFeatures, recognition::Recognition, tracking::TrackedObject};
+use opencv::core;
+use serde::{Deserialize, Serialize};
+
+/// A detected face in the frame coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaceRecord {
+    /// `None` if the face is not tracked
+    pub track_id: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Eyes, smiles and landmarks, only if the `features` config enables any of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<FeatureRecord>,
+    /// Who the face is, only if the `recognition` config is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recognition: Option<Recognition>,
+}
+
+impl FaceRecord {
+    /// Where this face is in the detected rects of the frame
+    fn index_in(&self, face_rects: &[core::Rect]) -> Option<usize> {
+        let face_rect = core::Rect::new(self.x, self.y, self.width, self.height);
+        face_rects.iter().position(|rect| *rect == face_rect)
+    }
+}
+
+/// What was found inside a face, in the frame coordinates. Rects are `[x, y, width, height]`,
+/// landmarks are `[x, y]` in the 68 points order of the Facemark LBF model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeatureRecord {
+    pub eyes: Vec<[i32; 4]>,
+    pub smiles: Vec<[i32; 4]>,
+    pub landmarks: Vec<[f32; 2]>,
+}
+
+/// All detections of one frame, this is what goes out as JSON
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DetectionReport {
+    pub frame_index: u64,
+    /// Local time when the frame was processed
+    pub timestamp: String,
+    /// Position in the video file, `0` for a live camera
+    pub video_position_ms: f64,
+    pub faces: Vec<FaceRecord>,
+}
+
+impl DetectionReport {
+    /// Only the objects matched in the current frame are reported
+    pub fn from_tracked_objects(
+        frame_index: u64,
+        video_position_ms: f64,
+        objects: &[TrackedObject],
+    ) -> Self {
+        DetectionReport {
+            frame_index,
+            timestamp: chrono::Local::now()
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string(),
+            video_position_ms,
+            faces: objects
+                .iter()
+                .filter(|object| object.missing_frames == 0)
+                .map(|object| FaceRecord {
+                    track_id: Some(object.id),
+                    x: object.rect.x,
+                    y: object.rect.y,
+                    width: object.rect.width,
+                    height: object.rect.height,
+                    features: None,
+                    recognition: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Attach the eyes, smiles and landmarks to the faces, `face_rects` are the detections
+    /// `features` were found in (same order), the tracker keeps the same rects
+    pub fn attach_features(&mut self, face_rects: &[core::Rect], features: &[FaceFeatures]) {
+        for face in self.faces.iter_mut() {
+            face.features = face
+                .index_in(face_rects)
+                .and_then(|index| features.get(index))
+                .map(FaceFeatures::to_record);
+        }
+    }
+
+    /// Same as `attach_features` for the recognized people
+    pub fn attach_recognitions(&mut self, face_rects: &[core::Rect], recognitions: &[Recognition]) {
+        for face in self.faces.iter_mut() {
+            face.recognition = face
+                .index_in(face_rects)
+                .and_then(|index| recognitions.get(index))
+                .cloned();
+        }
+    }
+}
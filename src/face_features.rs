@@ -0,0 +1,223 @@
+use crate::{
+    detection::{clamp_to_frame, load_cascade},
+    error::{Context, Error, Result},
+    export::FeatureRecord,
+};
+#[cfg(feature = "contrib")]
+use opencv::face;
+use opencv::{core, imgproc, objdetect, prelude::*, types};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The eye cascade shipped with OpenCV, works with and without glasses
+pub const EYE_CASCADE_FILE: &'static str = "haarcascades/haarcascade_eye_tree_eyeglasses.xml";
+/// The smile cascade shipped with OpenCV
+pub const SMILE_CASCADE_FILE: &'static str = "haarcascades/haarcascade_smile.xml";
+
+/// Secondary analysis inside every detected face, everything is off by default because every
+/// feature costs another detection per face
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaceFeatureSettings {
+    /// Detect eyes in the upper half of the face
+    pub eyes: bool,
+    /// Detect a smile in the lower half of the face
+    pub smile: bool,
+    /// Fit the 68 facial landmarks, needs the `contrib` feature and `landmark_model`
+    pub landmarks: bool,
+    /// The Facemark LBF model, e.g. `lbfmodel.yaml` from the OpenCV contrib samples
+    pub landmark_model: PathBuf,
+}
+
+impl Default for FaceFeatureSettings {
+    fn default() -> Self {
+        FaceFeatureSettings {
+            eyes: false,
+            smile: false,
+            landmarks: false,
+            landmark_model: PathBuf::from("lbfmodel.yaml"),
+        }
+    }
+}
+
+/// Eyes, smiles and landmarks of one face, in the frame coordinates
+#[derive(Debug, Clone, Default)]
+pub struct FaceFeatures {
+    pub eyes: Vec<core::Rect>,
+    pub smiles: Vec<core::Rect>,
+    pub landmarks: Vec<core::Point2f>,
+}
+
+impl FaceFeatures {
+    /// The export shape, see `FaceRecord::features`
+    pub fn to_record(&self) -> FeatureRecord {
+        let to_array = |rect: &core::Rect| [rect.x, rect.y, rect.width, rect.height];
+        FeatureRecord {
+            eyes: self.eyes.iter().map(to_array).collect(),
+            smiles: self.smiles.iter().map(to_array).collect(),
+            landmarks: self
+                .landmarks
+                .iter()
+                .map(|point| [point.x, point.y])
+                .collect(),
+        }
+    }
+}
+
+/// Run `cascade` inside `area` of the grayscale frame, the results are in the frame
+/// coordinates
+pub fn detect_in_area(
+    cascade: &mut objdetect::CascadeClassifier,
+    gray: &Mat,
+    area: core::Rect,
+    scale_factor: f64,
+    min_neighbors: i32,
+    min_size: i32,
+) -> Result<Vec<core::Rect>> {
+    if area.width <= 0 || area.height <= 0 {
+        return Ok(Vec::new());
+    }
+
+    let area_image = core::Mat::roi(gray, area).context("Create face feature ROI")?;
+    let mut found = types::VectorOfRect::new();
+    cascade
+        .detect_multi_scale(
+            &area_image,
+            &mut found,
+            scale_factor,
+            min_neighbors,
+            objdetect::CASCADE_SCALE_IMAGE,
+            core::Size::new(min_size, min_size),
+            core::Size::new(0, 0),
+        )
+        .context("Detect face features")?;
+
+    Ok(found
+        .iter()
+        .map(|rect| core::Rect {
+            x: rect.x + area.x,
+            y: rect.y + area.y,
+            ..rect
+        })
+        .collect())
+}
+
+/// Eye and smile cascades plus the Facemark LBF landmarks, run inside the detected faces on
+/// the full resolution frame (the face detection runs on the reduced one)
+pub struct FaceFeatureDetector {
+    eye: Option<objdetect::CascadeClassifier>,
+    smile: Option<objdetect::CascadeClassifier>,
+    #[cfg(feature = "contrib")]
+    facemark: Option<core::Ptr<dyn face::Facemark>>,
+}
+
+impl FaceFeatureDetector {
+    /// Only loads the cascades (and model) enabled in `settings`
+    pub fn new(settings: &FaceFeatureSettings) -> Result<Self> {
+        let eye = if settings.eyes {
+            Some(load_cascade(EYE_CASCADE_FILE)?)
+        } else {
+            None
+        };
+        let smile = if settings.smile {
+            Some(load_cascade(SMILE_CASCADE_FILE)?)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "contrib")]
+        let facemark = if settings.landmarks {
+            let model = settings.landmark_model.to_string_lossy().to_string();
+            if !settings.landmark_model.is_file() {
+                return Err(Error::CascadeLoad(format!(
+                    "Facemark model not found: {}",
+                    model
+                )));
+            }
+            let mut facemark = face::create_facemark_lbf().context("Create Facemark LBF")?;
+            facemark
+                .load_model(&model)
+                .map_err(|error| Error::CascadeLoad(format!("{}: {}", model, error)))?;
+            Some(facemark)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "contrib"))]
+        if settings.landmarks {
+            return Err(Error::Config(String::from(
+                "Facial landmarks need the `contrib` feature (OpenCV face module)",
+            )));
+        }
+
+        Ok(FaceFeatureDetector {
+            eye,
+            smile,
+            #[cfg(feature = "contrib")]
+            facemark,
+        })
+    }
+
+    /// `false` if nothing is enabled, `detect` can be skipped
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(feature = "contrib")]
+        let has_facemark = self.facemark.is_some();
+        #[cfg(not(feature = "contrib"))]
+        let has_facemark = false;
+
+        self.eye.is_some() || self.smile.is_some() || has_facemark
+    }
+
+    /// `faces` are in the frame coordinates, the result has the same order
+    pub fn detect(&mut self, frame: &Mat, faces: &[core::Rect]) -> Result<Vec<FaceFeatures>> {
+        let mut all_features = vec![FaceFeatures::default(); faces.len()];
+        if !self.is_enabled() || faces.is_empty() {
+            return Ok(all_features);
+        }
+
+        let mut gray = Mat::default()?;
+        imgproc::cvt_color(frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)
+            .context("Convert frame to grayscale for face features")?;
+        let frame_size = gray.size()?;
+        let faces: Vec<core::Rect> = faces
+            .iter()
+            .map(|face| clamp_to_frame(*face, frame_size))
+            .collect();
+
+        for (face, features) in faces.iter().zip(all_features.iter_mut()) {
+            let upper_half = core::Rect {
+                height: face.height / 2,
+                ..*face
+            };
+            let lower_half = core::Rect {
+                y: face.y + face.height / 2,
+                height: face.height - face.height / 2,
+                ..*face
+            };
+
+            if let Some(eye) = self.eye.as_mut() {
+                features.eyes = detect_in_area(eye, &gray, upper_half, 1.1, 3, face.width / 8)?;
+            }
+            if let Some(smile) = self.smile.as_mut() {
+                // The smile cascade finds a lot of false smiles with fewer neighbors
+                features.smiles =
+                    detect_in_area(smile, &gray, lower_half, 1.7, 20, face.width / 6)?;
+            }
+        }
+
+        #[cfg(feature = "contrib")]
+        if let Some(facemark) = self.facemark.as_mut() {
+            let face_vector: types::VectorOfRect = faces.iter().cloned().collect();
+            let mut landmarks = types::VectorOfVectorOfPoint2f::new();
+            if facemark
+                .fit(&gray, &face_vector, &mut landmarks)
+                .context("Fit facial landmarks")?
+            {
+                for (points, features) in landmarks.iter().zip(all_features.iter_mut()) {
+                    features.landmarks = points.to_vec();
+                }
+            }
+        }
+
+        Ok(all_features)
+    }
+}
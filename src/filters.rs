@@ -0,0 +1,63 @@
+use crate::error::Result;
+use opencv::{core, imgproc, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Image filters for the preview
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    None,
+    Blur,
+    Edges,
+    Invert,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::None
+    }
+}
+
+impl Filter {
+    /// For switching the filter with a single key
+    pub fn next(self) -> Filter {
+        match self {
+            Filter::None => Filter::Blur,
+            Filter::Blur => Filter::Edges,
+            Filter::Edges => Filter::Invert,
+            Filter::Invert => Filter::None,
+        }
+    }
+}
+
+/// Return the filtered copy, the result keeps the same channels as the source, so the colored
+/// overlays can still be drawn on top of it.
+pub fn apply_filter(frame: &Mat, filter: Filter) -> Result<Mat> {
+    let mut filtered_frame = Mat::default()?;
+
+    match filter {
+        Filter::None => frame.copy_to(&mut filtered_frame)?,
+        Filter::Blur => imgproc::gaussian_blur(
+            &frame,
+            &mut filtered_frame,
+            core::Size {
+                width: 15,
+                height: 15,
+            },
+            0.,
+            0.,
+            core::BORDER_DEFAULT,
+        )?,
+        Filter::Edges => {
+            let mut gray = Mat::default()?;
+            imgproc::cvt_color(&frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)?;
+
+            let mut edges = Mat::default()?;
+            imgproc::canny(&gray, &mut edges, 50., 150., 3, false)?;
+            imgproc::cvt_color(&edges, &mut filtered_frame, imgproc::COLOR_GRAY2BGR, 0)?;
+        }
+        Filter::Invert => core::bitwise_not(&frame, &mut filtered_frame, &Mat::default()?)?,
+    }
+
+    Ok(filtered_frame)
+}
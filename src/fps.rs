@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+/// Measure the real processing FPS, the value reported by `CAP_PROP_FPS` is only what the
+/// device claims (and it's `0` on some webcams).
+pub struct FpsCounter {
+    last_frame_time: Option<Instant>,
+    fps: f64,
+}
+
+impl Default for FpsCounter {
+    fn default() -> Self {
+        FpsCounter {
+            last_frame_time: None,
+            fps: 0.,
+        }
+    }
+}
+
+impl FpsCounter {
+    /// Call it once per frame, return the smoothed FPS
+    pub fn tick(&mut self) -> f64 {
+        let now = Instant::now();
+        if let Some(last_frame_time) = self.last_frame_time {
+            let elapsed = now.duration_since(last_frame_time).as_secs_f64();
+            if elapsed > 0. {
+                let current_fps = 1. / elapsed;
+                // Moving average, otherwise the number jumps all the time
+                self.fps = if self.fps == 0. {
+                    current_fps
+                } else {
+                    self.fps * 0.9 + current_fps * 0.1
+                };
+            }
+        }
+        self.last_frame_time = Some(now);
+        self.fps
+    }
+
+    ///
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+}
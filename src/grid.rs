@@ -0,0 +1,52 @@
+use crate::error::{Context, Result};
+use opencv::{core, imgproc, prelude::*};
+
+/// As square as possible, e.g. 2 columns for 3 or 4 tiles, 3 columns for 5 to 9 tiles
+pub fn grid_columns(tile_count: usize) -> i32 {
+    (tile_count.max(1) as f64).sqrt().ceil() as i32
+}
+
+/// Resize the frame into a grid cell, grayscale frames become BGR so all tiles match
+pub fn resize_to_tile(frame: &Mat, tile_size: core::Size) -> Result<Mat> {
+    let mut tile = Mat::default()?;
+    imgproc::resize(frame, &mut tile, tile_size, 0., 0., imgproc::INTER_AREA)
+        .context("Resize frame to tile")?;
+
+    if tile.channels()? == 1 {
+        let mut color_tile = Mat::default()?;
+        imgproc::cvt_color(&tile, &mut color_tile, imgproc::COLOR_GRAY2BGR, 0)
+            .context("Convert tile to BGR")?;
+        return Ok(color_tile);
+    }
+    Ok(tile)
+}
+
+/// Copy the tiles (already `tile_size`, see `resize_to_tile`) into one frame, row by row.
+/// The empty cells in the last row stay black.
+pub fn compose_grid(tiles: &[Mat], columns: i32, tile_size: core::Size) -> Result<Mat> {
+    let columns = columns.max(1);
+    let rows = ((tiles.len() as i32 + columns - 1) / columns).max(1);
+    let grid = Mat::new_rows_cols_with_default(
+        rows * tile_size.height,
+        columns * tile_size.width,
+        core::CV_8UC3,
+        core::Scalar::all(0.),
+    )
+    .context("Create grid frame")?;
+
+    for (index, tile) in tiles.iter().enumerate() {
+        let index = index as i32;
+        let cell = core::Rect {
+            x: (index % columns) * tile_size.width,
+            y: (index / columns) * tile_size.height,
+            width: tile_size.width,
+            height: tile_size.height,
+        };
+        // Same as the info panel: the ROI shares the data with `grid`
+        let mut cell_roi_ref = core::Mat::roi(&grid, cell).context("Create grid cell ROI")?;
+        tile.copy_to(&mut cell_roi_ref)
+            .context("Copy tile into grid")?;
+    }
+
+    Ok(grid)
+}
@@ -0,0 +1,52 @@
+//! Shared building blocks used by the examples.
+//!
+//! Every example is still a standalone program, the modules below only hold the
+//! pieces which are too big (or too useful) to copy around.
+
+#[cfg(not(any(feature = "opencv-32", feature = "opencv-34", feature = "opencv-4")))]
+compile_error!("Enable one of the `opencv-32`, `opencv-34` or `opencv-4` features");
+
+#[cfg(any(
+    all(feature = "opencv-32", feature = "opencv-34"),
+    all(feature = "opencv-32", feature = "opencv-4"),
+    all(feature = "opencv-34", feature = "opencv-4"),
+))]
+compile_error!(
+    "Only one of the `opencv-32`, `opencv-34` or `opencv-4` features can be enabled, use \
+     `--no-default-features` to replace the default `opencv-4`"
+);
+
+// The Facemark and `LBPHFaceRecognizer::create` APIs came with OpenCV 3.3/3.4
+#[cfg(all(feature = "opencv-32", feature = "contrib"))]
+compile_error!("The `contrib` feature needs OpenCV 3.4 or newer");
+
+pub mod alignment;
+pub mod camera;
+pub mod capture;
+pub mod compare;
+pub mod config;
+pub mod control;
+pub mod counting;
+pub mod dataset;
+pub mod detection;
+pub mod display;
+pub mod error;
+pub mod evaluation;
+pub mod events;
+pub mod export;
+pub mod face_features;
+pub mod filters;
+pub mod fps;
+pub mod grid;
+pub mod object_detection;
+pub mod overlay;
+pub mod playback;
+pub mod postprocess;
+pub mod recognition;
+pub mod recording;
+pub mod scheduler;
+pub mod streaming;
+pub mod sweep;
+pub mod tracking;
+pub mod transform;
+pub mod zones;
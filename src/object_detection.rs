@@ -0,0 +1,445 @@
+use crate::error::{Error, Result};
+#[cfg(not(feature = "opencv-32"))]
+use crate::{error::Context, transform::CoordinateTransform};
+use opencv::{core, prelude::*};
+#[cfg(not(feature = "opencv-32"))]
+use opencv::{dnn, types};
+use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "opencv-32"))]
+use std::fs;
+use std::path::PathBuf;
+
+/// The supported model families, they differ in the input blob and the output layout
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelKind {
+    /// Darknet (`.cfg` + `.weights`) or ONNX (e.g. YOLOv5), one row per candidate box:
+    /// `[center x, center y, width, height, objectness, class scores...]`
+    Yolo,
+    /// Caffe/TensorFlow MobileNet-SSD, one row per box:
+    /// `[image id, class id, confidence, left, top, right, bottom]`
+    Ssd,
+}
+
+impl ModelKind {
+    /// `"yolo"` or `"ssd"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "yolo" => Some(ModelKind::Yolo),
+            "ssd" => Some(ModelKind::Ssd),
+            _ => None,
+        }
+    }
+}
+
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectDetectorSettings {
+    pub kind: ModelKind,
+    /// `.weights`, `.onnx`, `.caffemodel` or `.pb`
+    pub model_file: PathBuf,
+    /// `.cfg` (Darknet), `.prototxt` (Caffe) or `.pbtxt` (TensorFlow), `None` for ONNX
+    pub model_config: Option<PathBuf>,
+    /// One class name per line, in the class id order of the model
+    pub classes_file: PathBuf,
+    /// The model input is a square of this size, e.g. 416 for YOLO, 300 for MobileNet-SSD
+    pub input_size: i32,
+    /// Keep the aspect ratio and pad the frame to the square input (e.g. YOLOv5), instead of
+    /// stretching it
+    pub letterbox: bool,
+    /// Boxes below this confidence are dropped
+    pub confidence_threshold: f32,
+    /// Boxes of the same class overlapping more than this (IoU) are merged by NMS
+    pub nms_threshold: f32,
+}
+
+impl ObjectDetectorSettings {
+    /// The defaults for the model kind, only the files are left to fill in
+    pub fn new(kind: ModelKind, model_file: PathBuf, classes_file: PathBuf) -> Self {
+        ObjectDetectorSettings {
+            kind,
+            model_file,
+            model_config: None,
+            classes_file,
+            input_size: match kind {
+                ModelKind::Yolo => 416,
+                ModelKind::Ssd => 300,
+            },
+            letterbox: false,
+            confidence_threshold: 0.5,
+            nms_threshold: 0.4,
+        }
+    }
+}
+
+/// One detected object in the frame coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectedObject {
+    pub class_id: i32,
+    /// The class name, or the class id if the classes file doesn't have it
+    pub label: String,
+    pub confidence: f32,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl DetectedObject {
+    ///
+    pub fn rect(&self) -> core::Rect {
+        core::Rect::new(self.x, self.y, self.width, self.height)
+    }
+}
+
+/// A YOLO or SSD model running on the CPU with `opencv::dnn` (OpenCV 3.4 or newer)
+pub struct ObjectDetector {
+    #[cfg(not(feature = "opencv-32"))]
+    net: dnn::Net,
+    settings: ObjectDetectorSettings,
+    #[cfg_attr(feature = "opencv-32", allow(dead_code))]
+    class_names: Vec<String>,
+    /// ONNX YOLO models output the boxes in the input pixels, Darknet relative to the input
+    #[cfg_attr(feature = "opencv-32", allow(dead_code))]
+    is_yolo_in_pixels: bool,
+    /// The class names are checked against the first model output
+    #[cfg_attr(feature = "opencv-32", allow(dead_code))]
+    is_class_count_checked: bool,
+}
+
+/// The `dnn` module is only in the OpenCV 3.2 contrib modules, without the YOLO/SSD support
+#[cfg(feature = "opencv-32")]
+fn dnn_required() -> Error {
+    Error::Config(String::from(
+        "Object detection needs OpenCV 3.4 or newer (the `dnn` module)",
+    ))
+}
+
+impl ObjectDetector {
+    /// Load the model and the class names
+    #[cfg(not(feature = "opencv-32"))]
+    pub fn new(settings: ObjectDetectorSettings) -> Result<Self> {
+        if settings.input_size <= 0 {
+            return Err(Error::Config(format!(
+                "input_size must be positive, got: {}",
+                settings.input_size
+            )));
+        }
+
+        let model_file = settings.model_file.to_string_lossy().to_string();
+        let model_config = settings
+            .model_config
+            .as_ref()
+            .map(|model_config| model_config.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut net = dnn::read_net(&model_file, &model_config, "")
+            .map_err(|error| Error::ModelLoad(format!("{}: {}", model_file, error)))?;
+        if net.empty()? {
+            return Err(Error::ModelLoad(model_file));
+        }
+        net.set_preferable_backend(dnn::DNN_BACKEND_OPENCV)
+            .context("Set DNN backend")?;
+        net.set_preferable_target(dnn::DNN_TARGET_CPU)
+            .context("Set DNN target")?;
+
+        let mut class_names: Vec<String> = fs::read_to_string(&settings.classes_file)
+            .map_err(|error| {
+                Error::io(
+                    format!(
+                        "Unable to read classes '{}'",
+                        settings.classes_file.display()
+                    ),
+                    error,
+                )
+            })?
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect();
+        // A blank line at the end isn't a class
+        while class_names.last().map(String::is_empty).unwrap_or(false) {
+            class_names.pop();
+        }
+
+        let is_yolo_in_pixels = settings.kind == ModelKind::Yolo
+            && settings
+                .model_file
+                .extension()
+                .map(|ext| ext == "onnx")
+                .unwrap_or(false);
+
+        Ok(ObjectDetector {
+            net,
+            settings,
+            class_names,
+            is_yolo_in_pixels,
+            is_class_count_checked: false,
+        })
+    }
+
+    ///
+    #[cfg(feature = "opencv-32")]
+    pub fn new(_settings: ObjectDetectorSettings) -> Result<Self> {
+        Err(dnn_required())
+    }
+
+    ///
+    pub fn settings(&self) -> &ObjectDetectorSettings {
+        &self.settings
+    }
+
+    ///
+    #[cfg(not(feature = "opencv-32"))]
+    fn label(&self, class_id: i32) -> String {
+        self.class_names
+            .get(class_id as usize)
+            .filter(|name| !name.is_empty())
+            .cloned()
+            .unwrap_or_else(|| class_id.to_string())
+    }
+
+    /// The classes file must have a name for every class score of the model, otherwise the
+    /// names don't belong to the classes. Then the labels fall back to the class ids.
+    #[cfg(not(feature = "opencv-32"))]
+    fn check_class_count(&mut self, class_count: usize) {
+        if self.is_class_count_checked {
+            return;
+        }
+        self.is_class_count_checked = true;
+        if class_count != self.class_names.len() {
+            println!(
+                "The model has {} classes, '{}' has {} names, showing the class ids instead",
+                class_count,
+                self.settings.classes_file.display(),
+                self.class_names.len()
+            );
+            self.class_names.clear();
+        }
+    }
+
+    /// Run the model on the frame, the boxes are in the frame coordinates, after NMS
+    #[cfg(not(feature = "opencv-32"))]
+    pub fn detect(&mut self, frame: &Mat) -> Result<Vec<DetectedObject>> {
+        let input_size = core::Size::new(self.settings.input_size, self.settings.input_size);
+        // The boxes come out in the model input coordinates, the transform maps them back
+        let transform = CoordinateTransform::new(frame.size()?);
+        let (transform, input) = if self.settings.letterbox {
+            let transform = transform.letterbox(input_size);
+            (transform, transform.warp(frame)?)
+        } else {
+            // `blob_from_image` stretches the frame to the input size
+            (transform.resize(input_size), frame.clone())
+        };
+
+        // YOLO wants RGB in 0..1, MobileNet-SSD BGR in -1..1
+        let blob = match self.settings.kind {
+            ModelKind::Yolo => dnn::blob_from_image(
+                &input,
+                1. / 255.,
+                input_size,
+                core::Scalar::default(),
+                true,
+                false,
+                core::CV_32F,
+            ),
+            ModelKind::Ssd => dnn::blob_from_image(
+                &input,
+                1. / 127.5,
+                input_size,
+                core::Scalar::all(127.5),
+                false,
+                false,
+                core::CV_32F,
+            ),
+        }
+        .context("Create DNN input blob")?;
+        self.net
+            .set_input(&blob, "", 1., core::Scalar::default())
+            .context("Set DNN input")?;
+
+        let mut outputs = types::VectorOfMat::new();
+        let output_names = self
+            .net
+            .get_unconnected_out_layers_names()
+            .context("Get DNN output layers")?;
+        self.net
+            .forward(&mut outputs, &output_names)
+            .context("Run DNN inference")?;
+
+        let candidates = match self.settings.kind {
+            ModelKind::Yolo => self.yolo_candidates(&outputs)?,
+            ModelKind::Ssd => self.ssd_candidates(&outputs)?,
+        };
+        let candidates = candidates
+            .into_iter()
+            .map(|(class_id, confidence, rect)| {
+                (class_id, confidence, transform.to_frame_rect(rect))
+            })
+            .collect();
+        self.non_maximum_suppression(candidates)
+    }
+
+    ///
+    #[cfg(feature = "opencv-32")]
+    pub fn detect(&mut self, _frame: &Mat) -> Result<Vec<DetectedObject>> {
+        Err(dnn_required())
+    }
+
+    /// `(class id, confidence, rect)` of every YOLO row above the confidence threshold, in the
+    /// model input coordinates
+    #[cfg(not(feature = "opencv-32"))]
+    fn yolo_candidates(
+        &mut self,
+        outputs: &types::VectorOfMat,
+    ) -> Result<Vec<(i32, f32, core::Rect)>> {
+        let scale = if self.is_yolo_in_pixels {
+            1.
+        } else {
+            self.settings.input_size as f32
+        };
+
+        let mut candidates = Vec::new();
+        for output in outputs.iter() {
+            // ONNX models have a batch dimension (1 x rows x columns), Darknet is 2D already.
+            // Either way a row is the box, the objectness and a score for every class.
+            let columns = output.mat_size().last().copied().unwrap_or(0);
+            if columns < 6 {
+                continue;
+            }
+            self.check_class_count(columns as usize - 5);
+            let rows = (output.total()? as i32) / columns;
+            let output = output.reshape(1, rows).context("Reshape YOLO output")?;
+
+            for row in 0..rows {
+                let values = output.at_row::<f32>(row)?;
+                if values.len() < 6 {
+                    continue;
+                }
+                let (class_id, class_score) =
+                    values[5..]
+                        .iter()
+                        .enumerate()
+                        .fold((0, 0f32), |best, (class_id, score)| {
+                            if *score > best.1 {
+                                (class_id, *score)
+                            } else {
+                                best
+                            }
+                        });
+                // Darknet class scores already include the objectness
+                let confidence = if self.is_yolo_in_pixels {
+                    class_score * values[4]
+                } else {
+                    class_score
+                };
+                if confidence < self.settings.confidence_threshold {
+                    continue;
+                }
+
+                let (width, height) = (values[2] * scale, values[3] * scale);
+                let rect = core::Rect::new(
+                    (values[0] * scale - width / 2.) as i32,
+                    (values[1] * scale - height / 2.) as i32,
+                    width as i32,
+                    height as i32,
+                );
+                candidates.push((class_id as i32, confidence, rect));
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// `(class id, confidence, rect)` of every SSD row above the confidence threshold, in the
+    /// model input coordinates
+    #[cfg(not(feature = "opencv-32"))]
+    fn ssd_candidates(&self, outputs: &types::VectorOfMat) -> Result<Vec<(i32, f32, core::Rect)>> {
+        let input_size = self.settings.input_size as f32;
+        let mut candidates = Vec::new();
+        for output in outputs.iter() {
+            // 1 x 1 x boxes x 7
+            let rows = output.total()? as i32 / 7;
+            let output = output.reshape(1, rows).context("Reshape SSD output")?;
+            for row in 0..rows {
+                let values = output.at_row::<f32>(row)?;
+                let confidence = values[2];
+                if confidence < self.settings.confidence_threshold {
+                    continue;
+                }
+                let (left, top) = (values[3] * input_size, values[4] * input_size);
+                let (right, bottom) = (values[5] * input_size, values[6] * input_size);
+                candidates.push((
+                    values[1] as i32,
+                    confidence,
+                    core::Rect::new(
+                        left as i32,
+                        top as i32,
+                        (right - left) as i32,
+                        (bottom - top) as i32,
+                    ),
+                ));
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// `dnn::nms_boxes` per class, so a person doesn't suppress the bicycle under them
+    #[cfg(not(feature = "opencv-32"))]
+    fn non_maximum_suppression(
+        &self,
+        candidates: Vec<(i32, f32, core::Rect)>,
+    ) -> Result<Vec<DetectedObject>> {
+        let mut class_ids: Vec<i32> = candidates
+            .iter()
+            .map(|(class_id, _, _)| *class_id)
+            .collect();
+        class_ids.sort();
+        class_ids.dedup();
+
+        let mut objects = Vec::new();
+        for class_id in class_ids {
+            let class_candidates: Vec<&(i32, f32, core::Rect)> = candidates
+                .iter()
+                .filter(|candidate| candidate.0 == class_id)
+                .collect();
+            let boxes: types::VectorOfRect = class_candidates
+                .iter()
+                .map(|candidate| candidate.2)
+                .collect();
+            let scores: types::VectorOff32 = class_candidates
+                .iter()
+                .map(|candidate| candidate.1)
+                .collect();
+
+            let mut kept = types::VectorOfi32::new();
+            dnn::nms_boxes(
+                &boxes,
+                &scores,
+                self.settings.confidence_threshold,
+                self.settings.nms_threshold,
+                &mut kept,
+                1.,
+                0,
+            )
+            .context("Run NMS")?;
+
+            for index in kept.iter() {
+                let (_, confidence, rect) = class_candidates[index as usize];
+                objects.push(DetectedObject {
+                    class_id,
+                    label: self.label(class_id),
+                    confidence: *confidence,
+                    x: rect.x,
+                    y: rect.y,
+                    width: rect.width,
+                    height: rect.height,
+                });
+            }
+        }
+
+        objects.sort_by(|a, b| {
+            b.confidence
+                .partial_cmp(&a.confidence)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        Ok(objects)
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    detection::FaceDetections,
+    error::{Context, Result},
+    face_features::FaceFeatures,
+    object_detection::DetectedObject,
+};
+use opencv::{core, imgproc, prelude::*};
+
+/// The size of `text` drawn with `draw_text_on_image`
+pub fn get_drawing_text_size<'a>(
+    text: &'a str,
+    font_scale: f64,
+    font_thickness: i32,
+) -> Result<core::Size> {
+    let mut base_line = 0;
+    imgproc::get_text_size(
+        text,
+        // FONT_HERSHEY_COMPLEX        - normal size serif font
+        // FONT_HERSHEY_COMPLEX_SMALL  - smaller version of FONT_HERSHEY_COMPLEX
+        // FONT_HERSHEY_DUPLEX         - normal size sans-serif font (more complex than FONT_HERSHEY_SIMPLEX)
+        // FONT_HERSHEY_PLAIN          - small size sans-serif font
+        // FONT_HERSHEY_SCRIPT_COMPLEX - more complex variant of FONT_HERSHEY_SCRIPT_SIMPLEX
+        // FONT_HERSHEY_SCRIPT_SIMPLEX - hand-writing style font
+        // FONT_HERSHEY_SIMPLEX        - normal size sans-serif font
+        // FONT_HERSHEY_TRIPLEX        - normal size serif font (more complex than FONT_HERSHEY_COMPLEX)
+        imgproc::FONT_HERSHEY_DUPLEX,
+        font_scale,
+        font_thickness,
+        &mut base_line,
+    )
+    .context("Get text size")
+}
+
+/// `put_text` with the font used by all overlays
+pub fn draw_text_on_image<'a>(
+    image: &mut Mat,
+    text: &'a str,
+    left_top_coord: core::Point,
+    font_scale: f64,
+    font_thickness: i32,
+    text_color: core::Scalar,
+) -> Result<()> {
+    imgproc::put_text(
+        image,
+        text,
+        left_top_coord,
+        imgproc::FONT_HERSHEY_DUPLEX,
+        font_scale,
+        text_color,
+        font_thickness,
+        imgproc::LINE_AA,
+        false,
+    )
+    .context("Draw text")
+}
+
+/// Detection runs on the reduced image, map the faces back to the frame coordinates with the
+/// transform of the detections (it doesn't depend on `frame`, e.g. the grayscale copy)
+pub fn draw_detected_faces(frame: &mut Mat, detections: &FaceDetections) -> Result<()> {
+    draw_face_rects(frame, &detections.frame_rects())
+}
+
+/// A rectangle for every face, `faces` are in the frame coordinates
+pub fn draw_face_rects(frame: &mut Mat, faces: &[core::Rect]) -> Result<()> {
+    for temp_face in faces.iter() {
+        imgproc::rectangle(
+            frame,                                        // Dest image
+            *temp_face,                                   // Rectangle to draw
+            core::Scalar::new(0f64, 0f64, 255f64, -1f64), // Border color (Blue, Green, Red, Alpha)
+            4,                                            // Boarder thickness
+            imgproc::LINE_AA,                             // Boarder line type
+            0,
+        )
+        .context("Draw face rectangle")?;
+    }
+
+    Ok(())
+}
+
+/// Eyes in blue, smiles in green and the landmarks as small yellow dots (frame coordinates)
+pub fn draw_face_features(frame: &mut Mat, all_features: &[FaceFeatures]) -> Result<()> {
+    for features in all_features {
+        for eye in features.eyes.iter() {
+            imgproc::rectangle(
+                frame,
+                *eye,
+                core::Scalar::new(255f64, 0f64, 0f64, -1f64), // (Blue, Green, Red, Alpha)
+                2,
+                imgproc::LINE_AA,
+                0,
+            )
+            .context("Draw eye rectangle")?;
+        }
+        for smile in features.smiles.iter() {
+            imgproc::rectangle(
+                frame,
+                *smile,
+                core::Scalar::new(0f64, 255f64, 0f64, -1f64),
+                2,
+                imgproc::LINE_AA,
+                0,
+            )
+            .context("Draw smile rectangle")?;
+        }
+        for point in features.landmarks.iter() {
+            imgproc::circle(
+                frame,
+                core::Point::new(point.x.round() as i32, point.y.round() as i32),
+                2,
+                core::Scalar::new(0f64, 255f64, 255f64, -1f64),
+                -1, // Filled
+                imgproc::LINE_AA,
+                0,
+            )
+            .context("Draw landmark")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A label above every face (e.g. the recognized name), `faces` are in the frame coordinates
+pub fn draw_face_labels(frame: &mut Mat, faces: &[core::Rect], labels: &[String]) -> Result<()> {
+    for (face, label) in faces.iter().zip(labels.iter()) {
+        draw_text_on_image(
+            frame,
+            label,
+            core::Point::new(face.x, (face.y - 10).max(20)),
+            0.8,
+            1,
+            core::Scalar::new(0f64, 255f64, 255f64, -1f64), // (Blue, Green, Red, Alpha)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// One box per object with `label 0.87` on a filled background above it, the color depends
+/// on the class so neighbouring objects of different classes are easy to tell apart
+pub fn draw_detected_objects(frame: &mut Mat, objects: &[DetectedObject]) -> Result<()> {
+    for object in objects {
+        let hue = (object.class_id.max(0) as u32).wrapping_mul(2654435761) as u64;
+        let color = core::Scalar::new(
+            (hue & 0xff) as f64,
+            ((hue >> 8) & 0xff) as f64,
+            ((hue >> 16) & 0xff) as f64,
+            -1f64,
+        );
+        imgproc::rectangle(frame, object.rect(), color, 2, imgproc::LINE_AA, 0)
+            .context("Draw object rectangle")?;
+
+        let text = format!("{} {:.2}", object.label, object.confidence);
+        let text_size = get_drawing_text_size(&text, 0.6, 1)?;
+        let text_top = (object.y - text_size.height - 8).max(0);
+        imgproc::rectangle(
+            frame,
+            core::Rect::new(
+                object.x,
+                text_top,
+                text_size.width + 8,
+                text_size.height + 8,
+            ),
+            color,
+            imgproc::FILLED,
+            imgproc::LINE_8,
+            0,
+        )
+        .context("Draw object label background")?;
+        draw_text_on_image(
+            frame,
+            &text,
+            core::Point::new(object.x + 4, text_top + text_size.height + 4),
+            0.6,
+            1,
+            core::Scalar::new(255f64, 255f64, 255f64, -1f64), // (Blue, Green, Red, Alpha)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Semi-transparent panel in the top right corner: resolution, FPS, detected faces, then one
+/// line per `extra_info`
+pub fn draw_info_panel(
+    frame: &mut Mat,
+    frame_width: i32,
+    frame_height: i32,
+    fps: i32,
+    detected_face_amount: u8,
+    extra_info: &[String],
+) -> Result<()> {
+    let mut formatted_panel_info = format!(
+        "Resolution: {} x {}\nFPS: {}\nDetected Faces: {}",
+        frame_width, frame_height, fps, detected_face_amount
+    );
+    for temp_info in extra_info {
+        formatted_panel_info.push_str("\n");
+        formatted_panel_info.push_str(temp_info);
+    }
+    let text_list = formatted_panel_info.split("\n");
+    let text_vertical_space = 10;
+    let font_scale = 0.7;
+    let font_thickness = 1;
+    let font_color = core::Scalar::new(251., 235., 220., -1.); // (Blue, Green, Red, Alpha)
+    let info_panel_background_color = core::Scalar::new(15., 6., 3., -1.); // (Blue, Green, Red, Alpha)
+    let info_panel_width = 280;
+    // Every extra line takes the same height as the default 3 lines
+    let info_line_height = get_drawing_text_size("Resolution", font_scale, font_thickness)?.height
+        + text_vertical_space;
+    let info_panel_height = 88 + extra_info.len() as i32 * info_line_height;
+    let info_panel_margin = 2;
+
+    // For getting the better performance, we create a `ROI`(Region Of Interest) from the origin
+    // frame. This won't copy any data, as it's just a mut reference which will be affected if
+    // we modify it!!!
+    //
+    // The panel is clipped to the frame, e.g. a small grid tile is narrower than the panel.
+    let left = (frame_width - info_panel_width - info_panel_margin).max(0);
+    let right = (frame_width - info_panel_margin).min(frame.cols());
+    let bottom = (info_panel_margin + info_panel_height).min(frame.rows());
+    if right <= left || bottom <= info_panel_margin {
+        return Ok(());
+    }
+    let roi = core::Rect {
+        x: left,
+        y: info_panel_margin,
+        width: right - left,
+        height: bottom - info_panel_margin,
+    };
+    let mut panel_roi_ref = core::Mat::roi(frame, roi).context("Create info panel ROI")?;
+
+    // Create a temp draw area with the same size of `roi`
+    let mut panel_background = core::Mat::new_size_with_default(
+        core::Size {
+            width: panel_roi_ref.cols(),
+            height: panel_roi_ref.rows(),
+        },
+        panel_roi_ref.typ()?,
+        core::Scalar::new(0., 0., 0., -1.),
+    )
+    .context("Create info panel background")?;
+    let panel_background_area = core::Rect {
+        x: 0,
+        y: 0,
+        width: roi.width,
+        height: roi.height,
+    };
+    // println!("roi: {:#?}", roi);
+    // println!("panel_roi_ref: {:#?}", panel_roi_ref);
+    // println!("panel_background_area: {:#?}", panel_background_area);
+    // println!("panel_background: {:#?}", panel_background);
+
+    // Fill the color
+    imgproc::rectangle(
+        &mut panel_background,       // Dest image
+        panel_background_area,       // Rectangle to draw
+        info_panel_background_color, // Boarder color
+        imgproc::FILLED,             // Boarder thickness: Fill the entire area
+        imgproc::LINE_AA,            // Boarder line type
+        0,
+    )
+    .context("Fill info panel background")?;
+
+    // Merge `panel_roi_ref` and `panel_background` together with the particular alpha(transparent)
+    // settings. So, we finished drawing a transparent background on top of the original frame:)
+    //
+    // `src image alpha` + `copy image alpha` should equal `1.0`. Just like a transparent percentage.
+    core::add_weighted(
+        &panel_roi_ref.clone(), // Src image
+        0.3,                    // Src image alpha
+        &panel_background,      // Copy image
+        0.7,                    // Copy image alpha
+        0.,                     // Gamma
+        &mut panel_roi_ref,     // The merge dest image
+        -1,
+    )
+    .context("Blend info panel background")?;
+
+    // Draw all split text
+    let text_coord = (roi.x + 6, roi.y + 25); // X, Y
+    let mut text_drawing_coord_y = text_coord.1;
+    for (index, temp_text) in text_list.enumerate() {
+        let text_size = get_drawing_text_size(temp_text, font_scale, font_thickness)?;
+
+        let drawing_coord = if index == 0 {
+            core::Point::new(text_coord.0, text_drawing_coord_y)
+        } else {
+            text_drawing_coord_y += text_size.height + text_vertical_space;
+            core::Point::new(text_coord.0, text_drawing_coord_y)
+        };
+
+        draw_text_on_image(
+            frame,
+            temp_text,
+            drawing_coord,
+            font_scale,
+            font_thickness,
+            font_color,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::{capture::ReconnectingCapture, error::Result};
+use opencv::videoio;
+
+/// The speeds `+`/`-` go through
+const PLAYBACK_SPEEDS: &[f64] = &[0.25, 0.5, 1., 2., 4.];
+/// How far `[` and `]` seek
+const SEEK_STEP_MS: f64 = 5_000.;
+
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PendingSeek {
+    /// Go to this frame index (trackbar, step back)
+    Frame(i32),
+    /// Move by this many milliseconds from the current position
+    Milliseconds(f64),
+}
+
+/// Pause, single-step, seek and speed control for video file playback.
+///
+/// Keys:
+///
+/// - `space`: pause/resume
+/// - `.`/`,`: one frame forward/back (pauses the playback)
+/// - `]`/`[`: seek 5 seconds forward/back
+/// - `+`/`-`: faster/slower (0.25x to 4x)
+pub struct PlaybackController {
+    frame_count: i32,
+    fps: f64,
+    speed_index: usize,
+    is_paused: bool,
+    is_step_pending: bool,
+    pending_seek: Option<PendingSeek>,
+    /// The index of the frame on screen, `-1` before the first frame
+    current_frame: i32,
+    current_position_ms: f64,
+}
+
+impl PlaybackController {
+    /// `frame_count` and `fps` come from `CAP_PROP_FRAME_COUNT` and `CAP_PROP_FPS`
+    pub fn new(frame_count: i32, fps: f64) -> Self {
+        PlaybackController {
+            frame_count: frame_count.max(1),
+            fps: if fps > 0. { fps } else { 30. },
+            speed_index: 2,
+            is_paused: false,
+            is_step_pending: false,
+            pending_seek: None,
+            current_frame: -1,
+            current_position_ms: 0.,
+        }
+    }
+
+    ///
+    pub fn frame_count(&self) -> i32 {
+        self.frame_count
+    }
+
+    ///
+    pub fn current_frame(&self) -> i32 {
+        self.current_frame.max(0)
+    }
+
+    ///
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    ///
+    pub fn speed(&self) -> f64 {
+        PLAYBACK_SPEEDS[self.speed_index]
+    }
+
+    /// Return `true` if it's a playback key
+    pub fn on_key(&mut self, key: i32) -> bool {
+        match key {
+            // space
+            32 => self.is_paused = !self.is_paused,
+            // `.`
+            46 => {
+                self.is_paused = true;
+                self.is_step_pending = true;
+            }
+            // `,`: after reading, the capture points to the frame after the current one
+            44 => {
+                self.is_paused = true;
+                self.pending_seek = Some(PendingSeek::Frame((self.current_frame - 1).max(0)));
+            }
+            // `]`
+            93 => self.pending_seek = Some(PendingSeek::Milliseconds(SEEK_STEP_MS)),
+            // `[`
+            91 => self.pending_seek = Some(PendingSeek::Milliseconds(-SEEK_STEP_MS)),
+            // `+` or `=` (same key without shift)
+            43 | 61 => self.speed_index = (self.speed_index + 1).min(PLAYBACK_SPEEDS.len() - 1),
+            // `-`
+            45 => self.speed_index = self.speed_index.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The trackbar callback, it also fires when the position is set by `update_position`,
+    /// that's why the current frame is ignored.
+    pub fn on_trackbar(&mut self, position: i32) {
+        if position != self.current_frame {
+            self.pending_seek = Some(PendingSeek::Frame(position));
+        }
+    }
+
+    /// `false` while paused without a pending step or seek
+    pub fn should_read_frame(&self) -> bool {
+        !self.is_paused || self.is_step_pending || self.pending_seek.is_some()
+    }
+
+    /// Call it right before reading the next frame
+    pub fn apply_pending_seek(&mut self, cam: &mut ReconnectingCapture) -> Result<()> {
+        self.is_step_pending = false;
+        match self.pending_seek.take() {
+            Some(PendingSeek::Frame(frame)) => {
+                let frame = frame.max(0).min(self.frame_count - 1);
+                cam.set(videoio::CAP_PROP_POS_FRAMES, frame as f64)?;
+            }
+            Some(PendingSeek::Milliseconds(offset_ms)) => {
+                let duration_ms = self.frame_count as f64 / self.fps * 1000.;
+                let position_ms = (self.current_position_ms + offset_ms)
+                    .max(0.)
+                    .min(duration_ms);
+                cam.set(videoio::CAP_PROP_POS_MSEC, position_ms)?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Call it right after reading a frame
+    pub fn update_position(&mut self, cam: &ReconnectingCapture) -> Result<()> {
+        self.current_frame = cam.get(videoio::CAP_PROP_POS_FRAMES)? as i32 - 1;
+        self.current_position_ms = cam.get(videoio::CAP_PROP_POS_MSEC)?;
+        Ok(())
+    }
+
+    /// How long to wait for the next frame at the current speed, `processing_ms` is the time
+    /// already spent on the current frame
+    pub fn frame_delay_ms(&self, processing_ms: f64) -> i32 {
+        let frame_interval_ms = 1000. / (self.fps * self.speed());
+        (frame_interval_ms - processing_ms).max(1.) as i32
+    }
+
+    /// Lines for the info panel, e.g. `Time: 00:01:05.200 x2 PAUSED` and `Frame: 1956 / 5400`
+    pub fn summary(&self) -> Vec<String> {
+        let mut time_info = format!("Time: {}", format_timestamp(self.current_position_ms));
+        if self.speed() != 1. {
+            time_info.push_str(&format!(" x{}", self.speed()));
+        }
+        if self.is_paused() {
+            time_info.push_str(" PAUSED");
+        }
+        vec![
+            time_info,
+            format!("Frame: {} / {}", self.current_frame(), self.frame_count),
+        ]
+    }
+}
+
+/// `65200.` -> `00:01:05.200`
+pub fn format_timestamp(position_ms: f64) -> String {
+    let total_ms = position_ms.max(0.) as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        total_ms / 3_600_000,
+        total_ms / 60_000 % 60,
+        total_ms / 1000 % 60,
+        total_ms % 1000
+    )
+}
@@ -0,0 +1,405 @@
+use crate::dataset::BoundingBox;
+use opencv::core;
+use serde::{Deserialize, Serialize};
+
+/// How overlapping detections of the same face are merged into one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeMethod {
+    /// Keep every detection
+    None,
+    /// Non-maximum suppression: keep the best scored detection of every overlapping group
+    Nms,
+    /// Weighted box fusion: average every overlapping group, weighted by the scores. Better
+    /// than NMS when several detectors (cascades) find the same face slightly differently.
+    WeightedBoxFusion,
+}
+
+/// Clean up the raw detections before they are drawn, tracked or exported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessSettings {
+    pub merge: MergeMethod,
+    /// Detections overlapping more than this (IoU) are the same face
+    pub iou_threshold: f64,
+    /// Smaller detections (width or height, in frame pixels) are dropped, `0` means no limit
+    pub min_size: i32,
+    /// Bigger detections (width or height, in frame pixels) are dropped, `0` means no limit
+    pub max_size: i32,
+    /// Detections with `width / height` below this are dropped, `0.0` means no limit
+    pub min_aspect_ratio: f64,
+    /// Detections with `width / height` above this are dropped, `0.0` means no limit
+    pub max_aspect_ratio: f64,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        PostProcessSettings {
+            merge: MergeMethod::Nms,
+            iou_threshold: 0.3,
+            min_size: 0,
+            max_size: 0,
+            min_aspect_ratio: 0.,
+            max_aspect_ratio: 0.,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    ///
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.iou_threshold <= 0. || self.iou_threshold > 1. {
+            return Err(format!(
+                "iou_threshold must be in (0, 1], got: {}",
+                self.iou_threshold
+            ));
+        }
+        if self.min_size < 0 || self.max_size < 0 {
+            return Err(format!(
+                "min_size and max_size can't be negative, got: {} and {}",
+                self.min_size, self.max_size
+            ));
+        }
+        if self.max_size > 0 && self.max_size < self.min_size {
+            return Err(format!(
+                "max_size must be at least min_size, got: {} < {}",
+                self.max_size, self.min_size
+            ));
+        }
+        if self.min_aspect_ratio < 0. || self.max_aspect_ratio < 0. {
+            return Err(format!(
+                "Aspect ratios can't be negative, got: {} and {}",
+                self.min_aspect_ratio, self.max_aspect_ratio
+            ));
+        }
+        if self.max_aspect_ratio > 0. && self.max_aspect_ratio < self.min_aspect_ratio {
+            return Err(format!(
+                "max_aspect_ratio must be at least min_aspect_ratio, got: {} < {}",
+                self.max_aspect_ratio, self.min_aspect_ratio
+            ));
+        }
+        Ok(())
+    }
+
+    /// The size and aspect ratio filters, then the merge
+    pub fn apply(&self, detections: Vec<ScoredRect>) -> Vec<ScoredRect> {
+        let detections = filter_by_size(detections, self.min_size, self.max_size);
+        let detections =
+            filter_by_aspect_ratio(detections, self.min_aspect_ratio, self.max_aspect_ratio);
+        match self.merge {
+            MergeMethod::None => detections,
+            MergeMethod::Nms => non_maximum_suppression(detections, self.iou_threshold),
+            MergeMethod::WeightedBoxFusion => weighted_box_fusion(detections, self.iou_threshold),
+        }
+    }
+}
+
+/// A detection and how confident the detector is, e.g. the neighbor count of a cascade
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoredRect {
+    pub rect: core::Rect,
+    pub score: f64,
+}
+
+///
+fn to_bounding_box(rect: &core::Rect) -> BoundingBox {
+    BoundingBox {
+        x: rect.x as f64,
+        y: rect.y as f64,
+        width: rect.width as f64,
+        height: rect.height as f64,
+    }
+}
+
+///
+pub fn intersection_over_union(a: &core::Rect, b: &core::Rect) -> f64 {
+    to_bounding_box(a).intersection_over_union(&to_bounding_box(b))
+}
+
+/// Best scored first
+fn sort_by_score(detections: &mut [ScoredRect]) {
+    detections.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+/// Drop the detections with the width or height outside of `[min_size, max_size]`, `0` means
+/// no limit
+pub fn filter_by_size(
+    detections: Vec<ScoredRect>,
+    min_size: i32,
+    max_size: i32,
+) -> Vec<ScoredRect> {
+    detections
+        .into_iter()
+        .filter(|detection| {
+            let rect = &detection.rect;
+            rect.width.min(rect.height) >= min_size
+                && (max_size == 0 || rect.width.max(rect.height) <= max_size)
+        })
+        .collect()
+}
+
+/// Drop the detections with `width / height` outside of `[min_ratio, max_ratio]`, `0.0` means
+/// no limit
+pub fn filter_by_aspect_ratio(
+    detections: Vec<ScoredRect>,
+    min_ratio: f64,
+    max_ratio: f64,
+) -> Vec<ScoredRect> {
+    detections
+        .into_iter()
+        .filter(|detection| {
+            if detection.rect.height <= 0 {
+                return false;
+            }
+            let ratio = detection.rect.width as f64 / detection.rect.height as f64;
+            ratio >= min_ratio && (max_ratio == 0. || ratio <= max_ratio)
+        })
+        .collect()
+}
+
+/// Keep the best scored detection, drop everything overlapping it more than `iou_threshold`,
+/// repeat with the rest. The result is best scored first.
+pub fn non_maximum_suppression(
+    mut detections: Vec<ScoredRect>,
+    iou_threshold: f64,
+) -> Vec<ScoredRect> {
+    sort_by_score(&mut detections);
+
+    let mut kept: Vec<ScoredRect> = Vec::with_capacity(detections.len());
+    for detection in detections {
+        let is_suppressed = kept.iter().any(|kept_detection| {
+            intersection_over_union(&kept_detection.rect, &detection.rect) > iou_threshold
+        });
+        if !is_suppressed {
+            kept.push(detection);
+        }
+    }
+    kept
+}
+
+/// Group the detections overlapping the fused box of a group more than `iou_threshold` (best
+/// scored first), then replace every group by the score weighted average of its boxes. The
+/// fused score is the mean score of the group. The result is best scored first.
+pub fn weighted_box_fusion(mut detections: Vec<ScoredRect>, iou_threshold: f64) -> Vec<ScoredRect> {
+    sort_by_score(&mut detections);
+
+    // (fused box, the boxes of the group)
+    let mut groups: Vec<(ScoredRect, Vec<ScoredRect>)> = Vec::new();
+    for detection in detections {
+        let group = groups.iter_mut().find(|(fused, _)| {
+            intersection_over_union(&fused.rect, &detection.rect) > iou_threshold
+        });
+        match group {
+            Some((fused, members)) => {
+                members.push(detection);
+                *fused = fuse(members);
+            }
+            None => groups.push((detection, vec![detection])),
+        }
+    }
+
+    let mut fused: Vec<ScoredRect> = groups.into_iter().map(|(fused, _)| fused).collect();
+    sort_by_score(&mut fused);
+    fused
+}
+
+/// The score weighted average box, zero (or negative) scores count as equal weights
+fn fuse(members: &[ScoredRect]) -> ScoredRect {
+    let weights: Vec<f64> = if members.iter().all(|member| member.score > 0.) {
+        members.iter().map(|member| member.score).collect()
+    } else {
+        vec![1.; members.len()]
+    };
+    let total_weight: f64 = weights.iter().sum();
+
+    let (mut left, mut top, mut right, mut bottom) = (0., 0., 0., 0.);
+    for (member, weight) in members.iter().zip(weights.iter()) {
+        let rect = &member.rect;
+        left += rect.x as f64 * weight;
+        top += rect.y as f64 * weight;
+        right += (rect.x + rect.width) as f64 * weight;
+        bottom += (rect.y + rect.height) as f64 * weight;
+    }
+    let (left, top) = (left / total_weight, top / total_weight);
+    let (right, bottom) = (right / total_weight, bottom / total_weight);
+
+    ScoredRect {
+        rect: core::Rect::new(
+            left.round() as i32,
+            top.round() as i32,
+            (right - left).round() as i32,
+            (bottom - top).round() as i32,
+        ),
+        score: members.iter().map(|member| member.score).sum::<f64>() / members.len() as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scored(x: i32, y: i32, width: i32, height: i32, score: f64) -> ScoredRect {
+        ScoredRect {
+            rect: core::Rect::new(x, y, width, height),
+            score,
+        }
+    }
+
+    fn rects(detections: &[ScoredRect]) -> Vec<core::Rect> {
+        detections.iter().map(|detection| detection.rect).collect()
+    }
+
+    fn scores(detections: &[ScoredRect]) -> Vec<f64> {
+        detections.iter().map(|detection| detection.score).collect()
+    }
+
+    #[test]
+    fn nms_only_suppresses_above_the_threshold() {
+        // The second box is the top half of the first one: IoU exactly 0.5
+        let detections = vec![scored(0, 0, 10, 10, 2.), scored(0, 0, 10, 5, 1.)];
+        assert_eq!(
+            intersection_over_union(&detections[0].rect, &detections[1].rect),
+            0.5
+        );
+
+        assert_eq!(non_maximum_suppression(detections.clone(), 0.5).len(), 2);
+        let kept = non_maximum_suppression(detections, 0.49);
+        assert_eq!(kept, vec![scored(0, 0, 10, 10, 2.)]);
+    }
+
+    #[test]
+    fn nms_keeps_the_best_scored_and_sorts_by_score() {
+        let detections = vec![
+            scored(100, 100, 10, 10, 1.),
+            scored(1, 1, 10, 10, 2.),
+            scored(0, 0, 10, 10, 5.),
+            scored(50, 50, 10, 10, 3.),
+        ];
+        let kept = non_maximum_suppression(detections, 0.3);
+
+        assert_eq!(scores(&kept), vec![5., 3., 1.]);
+        assert_eq!(kept[0].rect, core::Rect::new(0, 0, 10, 10));
+    }
+
+    #[test]
+    fn wbf_fuses_by_score_weight() {
+        // IoU 60 / 140, fused left (0 * 3 + 4 * 1) / 4, right (10 * 3 + 14 * 1) / 4
+        let detections = vec![scored(4, 0, 10, 10, 1.), scored(0, 0, 10, 10, 3.)];
+        let fused = weighted_box_fusion(detections, 0.3);
+
+        assert_eq!(fused, vec![scored(1, 0, 10, 10, 2.)]);
+    }
+
+    #[test]
+    fn wbf_zero_scores_weigh_the_same() {
+        let detections = vec![scored(0, 0, 10, 10, 0.), scored(2, 2, 10, 10, 0.)];
+        let fused = weighted_box_fusion(detections, 0.3);
+
+        assert_eq!(fused, vec![scored(1, 1, 10, 10, 0.)]);
+    }
+
+    #[test]
+    fn wbf_keeps_separate_faces_sorted_by_score() {
+        let detections = vec![
+            scored(0, 0, 10, 10, 1.),
+            scored(100, 0, 10, 10, 4.),
+            scored(0, 1, 10, 10, 3.),
+        ];
+        let fused = weighted_box_fusion(detections, 0.3);
+
+        assert_eq!(scores(&fused), vec![4., 2.]);
+        assert_eq!(fused[0].rect, core::Rect::new(100, 0, 10, 10));
+        // (0 * 1 + 1 * 3) / 4 = 0.75 rounds up
+        assert_eq!(fused[1].rect, core::Rect::new(0, 1, 10, 10));
+    }
+
+    #[test]
+    fn size_filter_zero_means_no_limit() {
+        let detections = vec![
+            scored(0, 0, 10, 30, 1.),
+            scored(0, 0, 20, 20, 1.),
+            scored(0, 0, 50, 40, 1.),
+        ];
+
+        assert_eq!(filter_by_size(detections.clone(), 0, 0).len(), 3);
+        // The smaller side must reach `min_size`
+        assert_eq!(
+            rects(&filter_by_size(detections.clone(), 20, 0)),
+            vec![core::Rect::new(0, 0, 20, 20), core::Rect::new(0, 0, 50, 40)]
+        );
+        // The bigger side must stay within `max_size`
+        assert_eq!(
+            rects(&filter_by_size(detections.clone(), 0, 30)),
+            vec![core::Rect::new(0, 0, 10, 30), core::Rect::new(0, 0, 20, 20)]
+        );
+        assert_eq!(
+            rects(&filter_by_size(detections, 20, 30)),
+            vec![core::Rect::new(0, 0, 20, 20)]
+        );
+    }
+
+    #[test]
+    fn aspect_ratio_filter_zero_means_no_limit() {
+        let detections = vec![
+            scored(0, 0, 10, 20, 1.),
+            scored(0, 0, 20, 20, 1.),
+            scored(0, 0, 30, 10, 1.),
+            scored(0, 0, 10, 0, 1.),
+        ];
+
+        // Only the zero height one is dropped, it has no ratio
+        assert_eq!(filter_by_aspect_ratio(detections.clone(), 0., 0.).len(), 3);
+        assert_eq!(
+            rects(&filter_by_aspect_ratio(detections.clone(), 0.8, 0.)),
+            vec![core::Rect::new(0, 0, 20, 20), core::Rect::new(0, 0, 30, 10)]
+        );
+        assert_eq!(
+            rects(&filter_by_aspect_ratio(detections.clone(), 0., 1.5)),
+            vec![core::Rect::new(0, 0, 10, 20), core::Rect::new(0, 0, 20, 20)]
+        );
+        // The limits are inclusive
+        assert_eq!(
+            rects(&filter_by_aspect_ratio(detections, 0.5, 1.)),
+            vec![core::Rect::new(0, 0, 10, 20), core::Rect::new(0, 0, 20, 20)]
+        );
+    }
+
+    #[test]
+    fn apply_filters_before_merging() {
+        let settings = PostProcessSettings {
+            min_size: 15,
+            ..PostProcessSettings::default()
+        };
+        // The small one is dropped first, then the overlapping pair becomes one
+        let detections = vec![
+            scored(0, 0, 10, 10, 9.),
+            scored(100, 100, 40, 40, 2.),
+            scored(102, 100, 40, 40, 1.),
+        ];
+
+        assert_eq!(
+            settings.apply(detections),
+            vec![scored(100, 100, 40, 40, 2.)]
+        );
+    }
+
+    #[test]
+    fn validate_rejects_inverted_limits() {
+        assert!(PostProcessSettings::default().validate().is_ok());
+        let inverted_sizes = PostProcessSettings {
+            min_size: 50,
+            max_size: 40,
+            ..PostProcessSettings::default()
+        };
+        assert!(inverted_sizes.validate().is_err());
+        let zero_iou = PostProcessSettings {
+            iou_threshold: 0.,
+            ..PostProcessSettings::default()
+        };
+        assert!(zero_iou.validate().is_err());
+    }
+}
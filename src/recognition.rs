@@ -0,0 +1,313 @@
+use crate::{
+    alignment::{convert_to_gray, AlignmentSettings, FaceAligner},
+    detection::{detect_faces, DetectorSettings},
+    error::{Context, Error, Result},
+    postprocess::PostProcessSettings,
+};
+#[cfg(feature = "contrib")]
+use opencv::face;
+#[cfg(feature = "contrib")]
+use opencv::types;
+use opencv::{core, imgcodecs, objdetect, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the faces which don't match anyone enrolled
+pub const UNKNOWN_PERSON: &'static str = "unknown";
+
+/// Label the detected faces with the enrolled people (LBPH model created by `enroll-faces`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecognitionSettings {
+    pub enabled: bool,
+    /// The model saved by `enroll-faces`
+    pub model_file: PathBuf,
+    /// LBPH distance above this is `unknown`, lower is stricter
+    pub max_distance: f64,
+}
+
+impl Default for RecognitionSettings {
+    fn default() -> Self {
+        RecognitionSettings {
+            enabled: false,
+            model_file: PathBuf::from("faces.lbph.yml"),
+            max_distance: 80.,
+        }
+    }
+}
+
+/// Who a face is, `distance` is the LBPH histogram distance (`0` is a perfect match)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recognition {
+    pub name: String,
+    pub distance: f64,
+}
+
+impl Recognition {
+    ///
+    pub fn is_known(&self) -> bool {
+        self.name != UNKNOWN_PERSON
+    }
+
+    /// `Alice (42.3)` or `unknown`
+    pub fn label(&self) -> String {
+        if self.is_known() {
+            format!("{} ({:.1})", self.name, self.distance)
+        } else {
+            String::from(UNKNOWN_PERSON)
+        }
+    }
+}
+
+/// The samples found by `collect_enrollment_samples`
+#[derive(Default)]
+pub struct EnrollmentSamples {
+    /// `(name, face sample)`
+    pub samples: Vec<(String, Mat)>,
+    /// Images which can't be read or have no face
+    pub skipped: Vec<PathBuf>,
+}
+
+/// `people_dir` has one folder per person, named after the person, with their photos in it.
+/// The biggest face of every photo is aligned into a sample. The faces are detected like in the
+/// capture loop (with `postprocess`), so the samples come from the same boxes.
+pub fn collect_enrollment_samples(
+    face_classifier: &mut objdetect::CascadeClassifier,
+    people_dir: &Path,
+    detector_settings: &DetectorSettings,
+    postprocess: &PostProcessSettings,
+    alignment_settings: &AlignmentSettings,
+) -> Result<EnrollmentSamples> {
+    let mut aligner = FaceAligner::new(alignment_settings)?;
+    let read_dir = |dir: &Path| -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|error| {
+                Error::io(format!("Unable to read folder '{}'", dir.display()), error)
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        paths.sort();
+        Ok(paths)
+    };
+
+    let mut enrollment = EnrollmentSamples::default();
+    for person_dir in read_dir(people_dir)?
+        .into_iter()
+        .filter(|path| path.is_dir())
+    {
+        let name = person_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        for image_path in read_dir(&person_dir)? {
+            let image = imgcodecs::imread(&image_path.to_string_lossy(), imgcodecs::IMREAD_COLOR)
+                .context("Read enrollment image")?;
+            if image.cols() <= 0 {
+                enrollment.skipped.push(image_path);
+                continue;
+            }
+
+            let biggest_face =
+                detect_faces(face_classifier, &image, detector_settings, postprocess)?
+                    .into_iter()
+                    .map(|face| face.rect)
+                    .max_by_key(|face| face.area());
+            match biggest_face {
+                Some(face) => {
+                    enrollment
+                        .samples
+                        .push((name.clone(), aligner.align(&image, face)?));
+                }
+                None => enrollment.skipped.push(image_path),
+            }
+        }
+    }
+
+    Ok(enrollment)
+}
+
+/// LBPH face recognizer, every enrolled person gets a label with their name as label info
+pub struct PersonRecognizer {
+    #[cfg(feature = "contrib")]
+    model: core::Ptr<dyn face::LBPHFaceRecognizer>,
+    #[cfg_attr(not(feature = "contrib"), allow(dead_code))]
+    max_distance: f64,
+    /// Training and recognition must align the faces the same way
+    aligner: FaceAligner,
+}
+
+///
+#[cfg(not(feature = "contrib"))]
+fn contrib_required() -> Error {
+    Error::Config(String::from(
+        "Face recognition needs the `contrib` feature (OpenCV face module)",
+    ))
+}
+
+impl PersonRecognizer {
+    ///
+    #[cfg(feature = "contrib")]
+    fn create_model() -> Result<core::Ptr<dyn face::LBPHFaceRecognizer>> {
+        // radius 1, 8 neighbors, 8x8 grid, no threshold (`max_distance` is applied later)
+        <dyn face::LBPHFaceRecognizer>::create(1, 8, 8, 8, f64::MAX)
+            .context("Create LBPH face recognizer")
+    }
+
+    /// Train a new model, `samples` are `(name, aligned face)` from
+    /// `collect_enrollment_samples`
+    #[cfg(feature = "contrib")]
+    pub fn train(
+        samples: &[(String, Mat)],
+        max_distance: f64,
+        alignment_settings: &AlignmentSettings,
+    ) -> Result<Self> {
+        if samples.is_empty() {
+            return Err(Error::Config(String::from("No face samples to train")));
+        }
+
+        let mut names: Vec<&str> = samples.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort();
+        names.dedup();
+
+        let mut images = types::VectorOfMat::new();
+        let mut labels = types::VectorOfi32::new();
+        for (name, sample) in samples {
+            images.push(sample.clone());
+            labels.push(
+                names
+                    .iter()
+                    .position(|known| *known == name.as_str())
+                    .unwrap_or(0) as i32,
+            );
+        }
+
+        let mut model = Self::create_model()?;
+        model
+            .train(&images, &labels)
+            .context("Train face recognizer")?;
+        for (label, name) in names.iter().enumerate() {
+            model
+                .set_label_info(label as i32, name)
+                .context("Set person name")?;
+        }
+
+        Ok(PersonRecognizer {
+            model,
+            max_distance,
+            aligner: FaceAligner::new(alignment_settings)?,
+        })
+    }
+
+    ///
+    #[cfg(not(feature = "contrib"))]
+    pub fn train(
+        _samples: &[(String, Mat)],
+        _max_distance: f64,
+        _alignment_settings: &AlignmentSettings,
+    ) -> Result<Self> {
+        Err(contrib_required())
+    }
+
+    /// Load the model saved by `save`
+    #[cfg(feature = "contrib")]
+    pub fn load(
+        settings: &RecognitionSettings,
+        alignment_settings: &AlignmentSettings,
+    ) -> Result<Self> {
+        if !settings.model_file.is_file() {
+            return Err(Error::CascadeLoad(format!(
+                "Face recognition model not found: {} (create it with `enroll-faces`)",
+                settings.model_file.display()
+            )));
+        }
+        let mut model = Self::create_model()?;
+        model
+            .read(&settings.model_file.to_string_lossy())
+            .map_err(|error| {
+                Error::CascadeLoad(format!("{}: {}", settings.model_file.display(), error))
+            })?;
+
+        Ok(PersonRecognizer {
+            model,
+            max_distance: settings.max_distance,
+            aligner: FaceAligner::new(alignment_settings)?,
+        })
+    }
+
+    ///
+    #[cfg(not(feature = "contrib"))]
+    pub fn load(
+        _settings: &RecognitionSettings,
+        _alignment_settings: &AlignmentSettings,
+    ) -> Result<Self> {
+        Err(contrib_required())
+    }
+
+    ///
+    #[cfg(feature = "contrib")]
+    pub fn save(&self, model_file: &Path) -> Result<()> {
+        self.model
+            .write(&model_file.to_string_lossy())
+            .context("Save face recognition model")?;
+        Ok(())
+    }
+
+    ///
+    #[cfg(not(feature = "contrib"))]
+    pub fn save(&self, _model_file: &Path) -> Result<()> {
+        Err(contrib_required())
+    }
+
+    /// Recognize one aligned face
+    #[cfg(feature = "contrib")]
+    pub fn recognize_sample(&self, sample: &Mat) -> Result<Recognition> {
+        let mut label = -1;
+        let mut distance = f64::MAX;
+        self.model
+            .predict(sample, &mut label, &mut distance)
+            .context("Recognize face")?;
+
+        let name = self.model.get_label_info(label).unwrap_or_default();
+        if label < 0 || name.is_empty() || distance > self.max_distance {
+            return Ok(Recognition {
+                name: String::from(UNKNOWN_PERSON),
+                distance,
+            });
+        }
+        Ok(Recognition { name, distance })
+    }
+
+    ///
+    #[cfg(not(feature = "contrib"))]
+    pub fn recognize_sample(&self, _sample: &Mat) -> Result<Recognition> {
+        Err(contrib_required())
+    }
+
+    /// Label every face, `faces` are in the frame coordinates, the result has the same order.
+    /// A face which can't be aligned or recognized is `unknown`, it doesn't stop the others.
+    pub fn recognize(&mut self, frame: &Mat, faces: &[core::Rect]) -> Result<Vec<Recognition>> {
+        // Once per frame rather than once per face
+        let converted = convert_to_gray(frame)?;
+        let gray = converted.as_ref().unwrap_or(frame);
+
+        let mut recognitions = Vec::with_capacity(faces.len());
+        for face in faces {
+            let recognition = self
+                .aligner
+                .align(gray, *face)
+                .and_then(|aligned_face| self.recognize_sample(&aligned_face));
+            recognitions.push(recognition.unwrap_or_else(|error| {
+                println!("Recognize face {:?} failed: {}", face, error);
+                Recognition {
+                    name: String::from(UNKNOWN_PERSON),
+                    distance: f64::MAX,
+                }
+            }));
+        }
+        Ok(recognitions)
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::{Context, Error, Result};
+use opencv::{core, imgproc, prelude::*, videoio};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+/// Fallback FPS when the capture device reports `0` (happens a lot on MacOSX webcams)
+const DEFAULT_RECORDING_FPS: f64 = 30.0;
+
+///
+#[derive(Debug, Clone)]
+pub struct RecordingSettings {
+    /// Where to save the clips, will be created if not exists
+    pub output_dir: PathBuf,
+    /// How many seconds before the event should be kept in the clip
+    pub pre_roll_seconds: f64,
+    /// How many seconds after the last event should be kept in the clip
+    pub post_roll_seconds: f64,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        RecordingSettings {
+            output_dir: PathBuf::from("recordings"),
+            pre_roll_seconds: 3.0,
+            post_roll_seconds: 5.0,
+        }
+    }
+}
+
+/// Only write the frames to disk when something happened.
+///
+/// The recorder keeps the latest `pre_roll_seconds` frames in memory (ring buffer). When an
+/// event is triggered, a new clip will be opened and the buffered frames will be written first,
+/// then every frame goes to the clip until no event happens for `post_roll_seconds`.
+///
+/// If another event is triggered during the post-roll, the same clip keeps going, that's how
+/// overlapping events get merged into one clip.
+pub struct EventRecorder {
+    settings: RecordingSettings,
+    fps: f64,
+    pre_roll_capacity: usize,
+    post_roll_frames: usize,
+    pre_roll_buffer: VecDeque<Mat>,
+    writer: Option<videoio::VideoWriter>,
+    current_clip: Option<PathBuf>,
+    post_roll_frames_left: usize,
+}
+
+impl EventRecorder {
+    ///
+    pub fn new(settings: RecordingSettings, fps: f64) -> Result<Self> {
+        let fps = if fps > 0. { fps } else { DEFAULT_RECORDING_FPS };
+
+        std::fs::create_dir_all(&settings.output_dir).map_err(|error| {
+            Error::io(
+                format!(
+                    "Unable to create recording folder '{}'",
+                    settings.output_dir.display()
+                ),
+                error,
+            )
+        })?;
+
+        Ok(EventRecorder {
+            pre_roll_capacity: (settings.pre_roll_seconds * fps).round() as usize,
+            post_roll_frames: (settings.post_roll_seconds * fps).round() as usize,
+            settings,
+            fps,
+            pre_roll_buffer: VecDeque::new(),
+            writer: None,
+            current_clip: None,
+            post_roll_frames_left: 0,
+        })
+    }
+
+    ///
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Feed every captured frame into the recorder, `event_triggered` means something
+    /// interesting (face or motion) happened on this frame.
+    pub fn push_frame(&mut self, frame: &Mat, event_triggered: bool) -> Result<()> {
+        if event_triggered {
+            if self.writer.is_none() {
+                self.start_clip(frame)?;
+            }
+            self.post_roll_frames_left = self.post_roll_frames;
+        }
+
+        match self.writer.as_mut() {
+            Some(writer) => {
+                writer.write(frame).context("Write frame into clip")?;
+
+                if !event_triggered {
+                    self.post_roll_frames_left = self.post_roll_frames_left.saturating_sub(1);
+                    if self.post_roll_frames_left == 0 {
+                        self.finish_clip()?;
+                    }
+                }
+            }
+            None => {
+                // Frames which already went into a clip never come back to the buffer, so the
+                // pre-roll of the next clip won't duplicate anything.
+                if self.pre_roll_capacity > 0 {
+                    self.pre_roll_buffer.push_back(frame.clone());
+                    if self.pre_roll_buffer.len() > self.pre_roll_capacity {
+                        self.pre_roll_buffer.pop_front();
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close the current clip (if any), should be called before the capture stops.
+    pub fn finish_clip(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.release().context("Close clip")?;
+        }
+
+        if let Some(clip) = self.current_clip.take() {
+            println!("Event clip saved: {}", clip.display());
+        }
+
+        self.post_roll_frames_left = 0;
+        Ok(())
+    }
+
+    ///
+    fn start_clip(&mut self, frame: &Mat) -> Result<()> {
+        let clip_path = clip_file_name(&self.settings.output_dir);
+        let frame_size = frame.size()?;
+        let fourcc = videoio::VideoWriter::fourcc('m' as i8, 'p' as i8, '4' as i8, 'v' as i8)?;
+        let mut writer = videoio::VideoWriter::new(
+            &clip_path.to_string_lossy(),
+            fourcc,
+            self.fps,
+            frame_size,
+            frame.channels()? > 1,
+        )?;
+
+        if !writer.is_opened()? {
+            return Err(Error::Codec(format!(
+                "Unable to open clip for writing: {}",
+                clip_path.display()
+            )));
+        }
+
+        println!("Event triggered, recording into: {}", clip_path.display());
+
+        // Pre-roll goes first
+        while let Some(buffered_frame) = self.pre_roll_buffer.pop_front() {
+            writer
+                .write(&buffered_frame)
+                .context("Write pre-roll frame into clip")?;
+        }
+
+        self.writer = Some(writer);
+        self.current_clip = Some(clip_path);
+        Ok(())
+    }
+}
+
+/// Clips are named by the local time when the event started, e.g. `2020-11-08_21-30-05.mp4`.
+/// If two clips start in the same second, the later one gets a `_1`, `_2` suffix.
+fn clip_file_name(output_dir: &Path) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let mut clip_path = output_dir.join(format!("{}.mp4", timestamp));
+    let mut suffix = 1;
+    while clip_path.exists() {
+        clip_path = output_dir.join(format!("{}_{}.mp4", timestamp, suffix));
+        suffix += 1;
+    }
+
+    clip_path
+}
+
+/// Super simple motion detection by diffing the current frame with the previous one.
+pub struct MotionDetector {
+    previous_frame: Option<Mat>,
+    /// Pixel difference (0 ~ 255) to treat a pixel as "changed"
+    pixel_threshold: f64,
+    /// How many percent (0.0 ~ 1.0) of the pixels should be changed to treat it as motion
+    min_changed_ratio: f64,
+}
+
+impl Default for MotionDetector {
+    fn default() -> Self {
+        MotionDetector::new(25., 0.01)
+    }
+}
+
+impl MotionDetector {
+    ///
+    pub fn new(pixel_threshold: f64, min_changed_ratio: f64) -> Self {
+        MotionDetector {
+            previous_frame: None,
+            pixel_threshold,
+            min_changed_ratio,
+        }
+    }
+
+    /// Return `true` if the frame has enough changes compared to the previous one
+    pub fn detect(&mut self, frame: &Mat) -> Result<bool> {
+        // Same as the face detection, work on a reduced gray image for speed
+        let mut gray = Mat::default()?;
+        imgproc::cvt_color(&frame, &mut gray, imgproc::COLOR_BGR2GRAY, 0)?;
+
+        let mut reduced = Mat::default()?;
+        imgproc::resize(
+            &gray,
+            &mut reduced,
+            core::Size {
+                width: 0,
+                height: 0,
+            },
+            0.25f64,
+            0.25f64,
+            imgproc::INTER_LINEAR,
+        )?;
+
+        // Blur to get rid of the camera noise
+        let mut blurred = Mat::default()?;
+        imgproc::gaussian_blur(
+            &reduced,
+            &mut blurred,
+            core::Size {
+                width: 21,
+                height: 21,
+            },
+            0.,
+            0.,
+            core::BORDER_DEFAULT,
+        )?;
+
+        let previous_frame = match self.previous_frame.replace(blurred) {
+            Some(previous_frame) => previous_frame,
+            None => return Ok(false),
+        };
+        let current_frame = self.previous_frame.as_ref().unwrap();
+
+        // Size changed (e.g. camera reopened), nothing to compare with
+        if previous_frame.size()? != current_frame.size()? {
+            return Ok(false);
+        }
+
+        let mut diff = Mat::default()?;
+        core::absdiff(&previous_frame, current_frame, &mut diff)?;
+
+        let mut changed_mask = Mat::default()?;
+        imgproc::threshold(
+            &diff,
+            &mut changed_mask,
+            self.pixel_threshold,
+            255.,
+            imgproc::THRESH_BINARY,
+        )?;
+
+        let changed_pixels = core::count_non_zero(&changed_mask)? as f64;
+        let total_pixels = (changed_mask.rows() * changed_mask.cols()) as f64;
+
+        Ok(total_pixels > 0. && changed_pixels / total_pixels >= self.min_changed_ratio)
+    }
+}
@@ -0,0 +1,422 @@
+use crate::{detection::clamp_to_frame, postprocess::ScoredRect};
+use opencv::core;
+use serde::{Deserialize, Serialize};
+
+/// When the (expensive) face detection runs
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleMode {
+    /// Detect on every frame
+    EveryFrame,
+    /// Detect every `every_n_frames` frames
+    EveryNthFrame,
+    /// Detect as often as `target_fps` allows, measured from the real processing times
+    Adaptive,
+}
+
+/// Skip the detection on some frames, the faces are moved along their last motion in between
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerSettings {
+    pub mode: ScheduleMode,
+    /// For `every_nth_frame`, `1` is the same as `every_frame`
+    pub every_n_frames: u32,
+    /// For `adaptive`, the output FPS to keep
+    pub target_fps: f64,
+    /// For `adaptive`, never skip more frames than this in a row, the predicted faces drift
+    /// away from the real ones after a while
+    pub max_skipped_frames: u32,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        SchedulerSettings {
+            mode: ScheduleMode::EveryFrame,
+            every_n_frames: 3,
+            target_fps: 25.,
+            max_skipped_frames: 10,
+        }
+    }
+}
+
+impl SchedulerSettings {
+    ///
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.every_n_frames == 0 {
+            return Err(String::from("every_n_frames must be at least 1"));
+        }
+        if self.target_fps <= 0. {
+            return Err(format!(
+                "target_fps must be positive, got: {}",
+                self.target_fps
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Moving average, the measured times jump from frame to frame
+fn smooth(average: f64, value: f64) -> f64 {
+    if average == 0. {
+        value
+    } else {
+        average * 0.9 + value * 0.1
+    }
+}
+
+/// Decide on every frame whether to run the detection, and predict the faces on the skipped
+/// frames from the last two detections.
+///
+/// Call `should_detect` once per frame, then either `on_detection` with the detected faces or
+/// `predicted_faces`, then `on_frame_processed` with the processing time of the whole frame.
+pub struct DetectionScheduler {
+    settings: SchedulerSettings,
+    /// Frames since the last detection, `None` before the first one
+    frames_since_detection: Option<u32>,
+    /// The current detection interval, `1` means every frame
+    interval: u32,
+    is_detection_frame: bool,
+    /// Smoothed time (ms) of the detection, and of the rest of the frame processing
+    detection_ms: f64,
+    other_ms: f64,
+    /// Faces of the last detection, with their motion per frame (x, y, width, height)
+    faces: Vec<(ScoredRect, [f64; 4])>,
+}
+
+impl DetectionScheduler {
+    ///
+    pub fn new(settings: &SchedulerSettings) -> Self {
+        DetectionScheduler {
+            settings: settings.clone(),
+            frames_since_detection: None,
+            interval: match settings.mode {
+                ScheduleMode::EveryNthFrame => settings.every_n_frames.max(1),
+                _ => 1,
+            },
+            is_detection_frame: false,
+            detection_ms: 0.,
+            other_ms: 0.,
+            faces: Vec::new(),
+        }
+    }
+
+    /// The current detection interval, `1` means every frame
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// `true` if the detection should run on this frame
+    pub fn should_detect(&mut self) -> bool {
+        self.is_detection_frame = match self.frames_since_detection {
+            Some(frames_since_detection) => frames_since_detection + 1 >= self.interval,
+            None => true,
+        };
+        if !self.is_detection_frame {
+            self.frames_since_detection = self.frames_since_detection.map(|frames| frames + 1);
+        }
+        self.is_detection_frame
+    }
+
+    /// The faces detected on this frame (frame coordinates) and how long the detection took
+    pub fn on_detection(&mut self, detected_faces: &[ScoredRect], detection_ms: f64) {
+        let frames_between = self.frames_since_detection.map(|frames| frames + 1);
+        let faces = detected_faces
+            .iter()
+            .map(|face| {
+                let motion = match frames_between {
+                    Some(frames_between) => self.motion_per_frame(face, frames_between),
+                    None => [0.; 4],
+                };
+                (*face, motion)
+            })
+            .collect();
+
+        self.faces = faces;
+        self.frames_since_detection = Some(0);
+        self.detection_ms = smooth(self.detection_ms, detection_ms);
+    }
+
+    /// The motion of `face` since the closest face of the previous detection (if it's close
+    /// enough to be the same face)
+    fn motion_per_frame(&self, face: &ScoredRect, frames_between: u32) -> [f64; 4] {
+        let center = |rect: &core::Rect| {
+            (
+                rect.x as f64 + rect.width as f64 / 2.,
+                rect.y as f64 + rect.height as f64 / 2.,
+            )
+        };
+        let (x, y) = center(&face.rect);
+        let distance = |previous: &ScoredRect| {
+            let (previous_x, previous_y) = center(&previous.rect);
+            ((x - previous_x).powi(2) + (y - previous_y).powi(2)).sqrt()
+        };
+
+        let previous_face = self
+            .faces
+            .iter()
+            .map(|(previous_face, _)| previous_face)
+            .filter(|previous_face| distance(previous_face) <= face.rect.width as f64)
+            .min_by(|a, b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        match previous_face {
+            Some(previous_face) => {
+                let frames = frames_between.max(1) as f64;
+                [
+                    (face.rect.x - previous_face.rect.x) as f64 / frames,
+                    (face.rect.y - previous_face.rect.y) as f64 / frames,
+                    (face.rect.width - previous_face.rect.width) as f64 / frames,
+                    (face.rect.height - previous_face.rect.height) as f64 / frames,
+                ]
+            }
+            None => [0.; 4],
+        }
+    }
+
+    /// The faces of the last detection moved along their motion, for the skipped frames. The
+    /// faces are clamped to the frame, the ones which moved out of it are dropped.
+    pub fn predicted_faces(&self, frame_size: core::Size) -> Vec<ScoredRect> {
+        let frames = self.frames_since_detection.unwrap_or(0) as f64;
+        let moved = |value: i32, motion: f64| (value as f64 + motion * frames).round() as i32;
+        self.faces
+            .iter()
+            .map(|(face, motion)| {
+                let rect = core::Rect::new(
+                    moved(face.rect.x, motion[0]),
+                    moved(face.rect.y, motion[1]),
+                    moved(face.rect.width, motion[2]).max(1),
+                    moved(face.rect.height, motion[3]).max(1),
+                );
+                ScoredRect {
+                    rect: clamp_to_frame(rect, frame_size),
+                    score: face.score,
+                }
+            })
+            .filter(|face| face.rect.width > 0 && face.rect.height > 0)
+            .collect()
+    }
+
+    /// The processing time of the whole frame (including the detection), the adaptive mode
+    /// picks the next interval from it
+    pub fn on_frame_processed(&mut self, frame_ms: f64) {
+        if self.is_detection_frame {
+            self.other_ms = smooth(self.other_ms, (frame_ms - self.detection_ms).max(0.));
+        } else {
+            self.other_ms = smooth(self.other_ms, frame_ms);
+        }
+
+        if self.settings.mode == ScheduleMode::Adaptive {
+            // Every frame costs `other_ms`, one in `interval` frames costs `detection_ms` more
+            let frame_budget_ms = 1000. / self.settings.target_fps;
+            let spare_ms = frame_budget_ms - self.other_ms;
+            let interval = if spare_ms <= 0. {
+                u32::MAX
+            } else {
+                (self.detection_ms / spare_ms).ceil() as u32
+            };
+            self.interval = interval
+                .max(1)
+                .min(self.settings.max_skipped_frames.saturating_add(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_SIZE: core::Size = core::Size {
+        width: 640,
+        height: 480,
+    };
+
+    fn scored(x: i32, y: i32, width: i32, height: i32) -> ScoredRect {
+        ScoredRect {
+            rect: core::Rect::new(x, y, width, height),
+            score: 1.,
+        }
+    }
+
+    fn scheduler(mode: ScheduleMode) -> DetectionScheduler {
+        DetectionScheduler::new(&SchedulerSettings {
+            mode,
+            ..SchedulerSettings::default()
+        })
+    }
+
+    /// `should_detect` for `frames` frames, with an (empty) detection whenever it says so
+    fn detect_sequence(scheduler: &mut DetectionScheduler, frames: usize) -> Vec<bool> {
+        (0..frames)
+            .map(|_| {
+                let should_detect = scheduler.should_detect();
+                if should_detect {
+                    scheduler.on_detection(&[], 10.);
+                }
+                should_detect
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_frame_always_detects() {
+        let mut scheduler = scheduler(ScheduleMode::EveryFrame);
+
+        assert_eq!(scheduler.interval(), 1);
+        assert_eq!(detect_sequence(&mut scheduler, 4), vec![true; 4]);
+    }
+
+    #[test]
+    fn every_nth_frame_detects_on_the_first_and_every_nth_frame() {
+        let mut scheduler = scheduler(ScheduleMode::EveryNthFrame);
+
+        assert_eq!(scheduler.interval(), 3);
+        assert_eq!(
+            detect_sequence(&mut scheduler, 7),
+            vec![true, false, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn adaptive_interval_fits_the_detection_into_the_spare_time() {
+        let mut scheduler = scheduler(ScheduleMode::Adaptive);
+        assert_eq!(scheduler.interval(), 1);
+
+        // 25 FPS is 40 ms a frame, the rest takes 50 - 40 = 10 ms: 30 ms spare for a 40 ms
+        // detection
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[], 40.);
+        scheduler.on_frame_processed(50.);
+        assert_eq!(scheduler.interval(), 2);
+        assert_eq!(detect_sequence(&mut scheduler, 3), vec![false, true, false]);
+    }
+
+    #[test]
+    fn adaptive_interval_is_one_for_a_cheap_detection() {
+        let mut scheduler = scheduler(ScheduleMode::Adaptive);
+
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[], 5.);
+        scheduler.on_frame_processed(15.);
+        assert_eq!(scheduler.interval(), 1);
+    }
+
+    #[test]
+    fn adaptive_interval_is_capped_by_max_skipped_frames() {
+        let mut scheduler = DetectionScheduler::new(&SchedulerSettings {
+            mode: ScheduleMode::Adaptive,
+            max_skipped_frames: 4,
+            ..SchedulerSettings::default()
+        });
+
+        // The rest of the frame alone (50 ms) is over the 40 ms budget
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[], 10.);
+        scheduler.on_frame_processed(60.);
+        assert_eq!(scheduler.interval(), 5);
+    }
+
+    #[test]
+    fn predicted_faces_follow_the_linear_motion() {
+        let mut scheduler = scheduler(ScheduleMode::EveryNthFrame);
+
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(100, 100, 50, 50)], 10.);
+        assert_eq!(detect_sequence(&mut scheduler, 2), vec![false, false]);
+        // 3 frames later: 4 px a frame to the right, 2 px down
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(112, 106, 50, 50)], 10.);
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(112, 106, 50, 50)]
+        );
+
+        assert!(!scheduler.should_detect());
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(116, 108, 50, 50)]
+        );
+        assert!(!scheduler.should_detect());
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(120, 110, 50, 50)]
+        );
+    }
+
+    #[test]
+    fn predicted_faces_without_a_close_previous_face_stay_put() {
+        let mut scheduler = scheduler(ScheduleMode::EveryNthFrame);
+
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(0, 0, 50, 50)], 10.);
+        assert_eq!(detect_sequence(&mut scheduler, 2), vec![false, false]);
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(300, 300, 50, 50)], 10.);
+
+        assert!(!scheduler.should_detect());
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(300, 300, 50, 50)]
+        );
+    }
+
+    #[test]
+    fn predicted_faces_are_clamped_to_the_frame() {
+        let mut scheduler = scheduler(ScheduleMode::EveryNthFrame);
+
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(600, 450, 50, 50)], 10.);
+
+        assert!(!scheduler.should_detect());
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(600, 450, 40, 30)]
+        );
+    }
+
+    #[test]
+    fn predicted_faces_outside_of_the_frame_are_dropped() {
+        let mut scheduler = scheduler(ScheduleMode::Adaptive);
+
+        // Two detections in a row: 40 px a frame to the left
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(100, 100, 50, 50)], 10.);
+        scheduler.on_frame_processed(15.);
+        assert!(scheduler.should_detect());
+        scheduler.on_detection(&[scored(60, 100, 50, 50)], 10.);
+        // Then the frame gets slow, the next detection is up to 10 frames away
+        scheduler.on_frame_processed(1000.);
+        assert_eq!(scheduler.interval(), 11);
+
+        assert!(!scheduler.should_detect());
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(20, 100, 50, 50)]
+        );
+        assert!(!scheduler.should_detect());
+        assert_eq!(
+            scheduler.predicted_faces(FRAME_SIZE),
+            vec![scored(0, 100, 30, 50)]
+        );
+        assert!(!scheduler.should_detect());
+        assert!(scheduler.predicted_faces(FRAME_SIZE).is_empty());
+    }
+
+    #[test]
+    fn validate_rejects_bad_settings() {
+        assert!(SchedulerSettings::default().validate().is_ok());
+
+        let settings = SchedulerSettings {
+            every_n_frames: 0,
+            ..SchedulerSettings::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = SchedulerSettings {
+            target_fps: 0.,
+            ..SchedulerSettings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
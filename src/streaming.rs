@@ -0,0 +1,214 @@
+use crate::{
+    error::{Context, Error, Result},
+    export::DetectionReport,
+};
+use opencv::{imgcodecs, prelude::*, types};
+use std::{
+    io::{self, Read},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+const MJPEG_BOUNDARY: &'static str = "frame";
+const JPEG_QUALITY: i32 = 80;
+
+/// The latest annotated frame shared with all HTTP clients
+#[derive(Default)]
+struct LatestFrame {
+    /// Increase by 1 for every published frame, `0` means nothing published yet
+    frame_id: u64,
+    jpeg: Vec<u8>,
+    detections_json: String,
+    closed: bool,
+}
+
+type SharedFrame = Arc<(Mutex<LatestFrame>, Condvar)>;
+
+/// The capture loop publishes frames through this, the HTTP server threads read them.
+///
+/// Endpoints:
+///
+/// - `/` or `/stream`: `multipart/x-mixed-replace` MJPEG stream, open it in a browser
+/// - `/snapshot.jpg` : the latest frame
+/// - `/detections`   : the latest detections as JSON
+#[derive(Clone)]
+pub struct StreamPublisher {
+    shared: SharedFrame,
+}
+
+impl StreamPublisher {
+    /// Start the HTTP server in the background, e.g. `start("0.0.0.0:8080")`
+    pub fn start(address: &str) -> Result<StreamPublisher> {
+        let server = Server::http(address).map_err(|error| {
+            Error::Server(format!(
+                "Unable to start stream server on '{}': {}",
+                address, error
+            ))
+        })?;
+        println!("MJPEG stream server listening on: http://{}", address);
+
+        let shared: SharedFrame = Arc::new((Mutex::new(LatestFrame::default()), Condvar::new()));
+        let server_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let request_shared = Arc::clone(&server_shared);
+                // The MJPEG stream never ends, every client needs its own thread
+                thread::spawn(move || handle_request(request, request_shared));
+            }
+        });
+
+        Ok(StreamPublisher { shared })
+    }
+
+    /// Encode the annotated frame as JPEG and wake up all stream clients
+    pub fn publish(&self, frame: &Mat, detections: &DetectionReport) -> Result<()> {
+        let mut jpeg = types::VectorOfu8::new();
+        let mut encode_params = types::VectorOfi32::new();
+        encode_params.push(imgcodecs::IMWRITE_JPEG_QUALITY);
+        encode_params.push(JPEG_QUALITY);
+        let encoded = imgcodecs::imencode(".jpg", frame, &mut jpeg, &encode_params)
+            .context("Encode stream frame")?;
+        if !encoded {
+            return Err(Error::Codec(String::from(
+                "Unable to encode stream frame as JPEG",
+            )));
+        }
+
+        let detections_json = serde_json::to_string(detections).unwrap_or_default();
+
+        let (lock, frame_published) = &*self.shared;
+        let mut latest = lock.lock().unwrap();
+        latest.frame_id += 1;
+        latest.jpeg = jpeg.to_vec();
+        latest.detections_json = detections_json;
+        frame_published.notify_all();
+        Ok(())
+    }
+
+    /// End all MJPEG streams, call it before the capture stops
+    pub fn close(&self) {
+        let (lock, frame_published) = &*self.shared;
+        lock.lock().unwrap().closed = true;
+        frame_published.notify_all();
+    }
+}
+
+///
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+///
+fn handle_request(request: Request, shared: SharedFrame) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+
+    let result = match path.as_str() {
+        "/" | "/stream" => {
+            let content_type = format!("multipart/x-mixed-replace; boundary={}", MJPEG_BOUNDARY);
+            request.respond(Response::new(
+                StatusCode(200),
+                vec![
+                    header("Content-Type", &content_type),
+                    header("Cache-Control", "no-cache"),
+                ],
+                MjpegReader::new(shared),
+                None,
+                None,
+            ))
+        }
+        "/snapshot.jpg" => {
+            let latest = shared.0.lock().unwrap();
+            if latest.frame_id == 0 {
+                request
+                    .respond(Response::from_string("No frame captured yet").with_status_code(503))
+            } else {
+                let jpeg = latest.jpeg.clone();
+                drop(latest);
+                request.respond(
+                    Response::from_data(jpeg).with_header(header("Content-Type", "image/jpeg")),
+                )
+            }
+        }
+        "/detections" => {
+            let detections_json = shared.0.lock().unwrap().detections_json.clone();
+            let detections_json = if detections_json.is_empty() {
+                String::from("{}")
+            } else {
+                detections_json
+            };
+            request.respond(
+                Response::from_string(detections_json)
+                    .with_header(header("Content-Type", "application/json")),
+            )
+        }
+        _ => request.respond(Response::from_string("Not found").with_status_code(404)),
+    };
+
+    // Most of the time it's just the client closed the connection
+    if let Err(error) = result {
+        println!("Stream client '{}' disconnected: {}", path, error);
+    }
+}
+
+/// Endless response body: block until the next frame is published, then hand out one
+/// multipart part (header + JPEG) at a time.
+struct MjpegReader {
+    shared: SharedFrame,
+    last_frame_id: u64,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl MjpegReader {
+    ///
+    fn new(shared: SharedFrame) -> Self {
+        MjpegReader {
+            shared,
+            last_frame_id: 0,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Return `false` if the publisher has been closed
+    fn wait_for_next_part(&mut self) -> bool {
+        let (lock, frame_published) = &*self.shared;
+        let mut latest = lock.lock().unwrap();
+        while !latest.closed && latest.frame_id == self.last_frame_id {
+            latest = frame_published.wait(latest).unwrap();
+        }
+        if latest.closed {
+            return false;
+        }
+
+        self.last_frame_id = latest.frame_id;
+        self.buffer.clear();
+        self.buffer.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                MJPEG_BOUNDARY,
+                latest.jpeg.len()
+            )
+            .as_bytes(),
+        );
+        self.buffer.extend_from_slice(&latest.jpeg);
+        self.buffer.extend_from_slice(b"\r\n");
+        self.position = 0;
+        true
+    }
+}
+
+impl Read for MjpegReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.buffer.len() && !self.wait_for_next_part() {
+            return Ok(0);
+        }
+
+        let remaining = &self.buffer[self.position..];
+        let size = remaining.len().min(buf.len());
+        buf[..size].copy_from_slice(&remaining[..size]);
+        self.position += size;
+        Ok(size)
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    detection::DetectorSettings,
+    error::{Error, Result},
+    evaluation::{self, DatasetImage, EvaluationReport},
+    postprocess::PostProcessSettings,
+};
+use opencv::objdetect;
+use serde::{Deserialize, Serialize};
+
+/// The values tried for every `DetectorSettings` field, the sweep runs every combination
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SweepGrid {
+    pub scale_factors: Vec<f64>,
+    pub min_neighbors: Vec<i32>,
+    pub min_face_sizes: Vec<i32>,
+    pub downscales: Vec<f64>,
+}
+
+impl Default for SweepGrid {
+    fn default() -> Self {
+        SweepGrid {
+            scale_factors: vec![1.05, 1.1, 1.2],
+            min_neighbors: vec![1, 2, 3, 5],
+            min_face_sizes: vec![10, 20, 30],
+            downscales: vec![0.25, 0.5],
+        }
+    }
+}
+
+impl SweepGrid {
+    /// Every combination of the grid values, invalid ones are rejected up front instead of
+    /// failing halfway through the sweep
+    pub fn settings(&self) -> Result<Vec<DetectorSettings>> {
+        let mut all_settings = Vec::new();
+        for scale_factor in self.scale_factors.iter() {
+            for min_neighbors in self.min_neighbors.iter() {
+                for min_face_size in self.min_face_sizes.iter() {
+                    for downscale in self.downscales.iter() {
+                        let settings = DetectorSettings {
+                            scale_factor: *scale_factor,
+                            min_neighbors: *min_neighbors,
+                            min_face_size: *min_face_size,
+                            downscale: *downscale,
+                        };
+                        settings.validate().map_err(Error::Config)?;
+                        all_settings.push(settings);
+                    }
+                }
+            }
+        }
+
+        if all_settings.is_empty() {
+            return Err(Error::Config(String::from(
+                "The sweep grid needs at least one value for every setting",
+            )));
+        }
+        Ok(all_settings)
+    }
+}
+
+/// The speed and accuracy of one grid point
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepResult {
+    pub settings: DetectorSettings,
+    pub mean_detection_ms: f64,
+    /// Average precision at the first IoU threshold
+    pub average_precision: f64,
+    pub precision: f64,
+    pub recall: f64,
+    /// No other grid point is both faster and more accurate
+    pub is_pareto_optimal: bool,
+}
+
+impl SweepResult {
+    ///
+    fn from_report(settings: DetectorSettings, report: &EvaluationReport) -> Self {
+        let first_threshold = report.thresholds.first();
+        SweepResult {
+            settings,
+            mean_detection_ms: report.mean_detection_ms,
+            average_precision: first_threshold
+                .map(|metrics| metrics.average_precision)
+                .unwrap_or(0.),
+            precision: first_threshold
+                .map(|metrics| metrics.precision)
+                .unwrap_or(0.),
+            recall: first_threshold.map(|metrics| metrics.recall).unwrap_or(0.),
+            is_pareto_optimal: false,
+        }
+    }
+
+    /// At least as fast and as accurate as `other`, and strictly better in one of them
+    fn dominates(&self, other: &SweepResult) -> bool {
+        self.mean_detection_ms <= other.mean_detection_ms
+            && self.average_precision >= other.average_precision
+            && (self.mean_detection_ms < other.mean_detection_ms
+                || self.average_precision > other.average_precision)
+    }
+}
+
+/// Mark the Pareto-optimal results, then sort them fastest first (the front comes first)
+pub fn mark_pareto_front(results: &mut [SweepResult]) {
+    let is_pareto_optimal: Vec<bool> = results
+        .iter()
+        .map(|result| !results.iter().any(|other| other.dominates(result)))
+        .collect();
+    for (result, is_pareto_optimal) in results.iter_mut().zip(is_pareto_optimal) {
+        result.is_pareto_optimal = is_pareto_optimal;
+    }
+
+    results.sort_by(|a, b| {
+        b.is_pareto_optimal.cmp(&a.is_pareto_optimal).then(
+            a.mean_detection_ms
+                .partial_cmp(&b.mean_detection_ms)
+                .unwrap_or(std::cmp::Ordering::Equal),
+        )
+    });
+}
+
+/// Evaluate every grid point on the dataset (`evaluation::load_dataset`), with the same
+/// `postprocess` settings for all of them. `on_progress` is called after every grid point with
+/// `(done, total, result)`, a full sweep over a big dataset takes a while.
+pub fn run_sweep<F>(
+    face: &mut objdetect::CascadeClassifier,
+    images: &[DatasetImage],
+    grid: &SweepGrid,
+    postprocess: &PostProcessSettings,
+    iou_thresholds: &[f64],
+    mut on_progress: F,
+) -> Result<Vec<SweepResult>>
+where
+    F: FnMut(usize, usize, &SweepResult),
+{
+    let all_settings = grid.settings()?;
+    let total = all_settings.len();
+
+    let mut results = Vec::with_capacity(total);
+    for settings in all_settings {
+        let detections = evaluation::detect_dataset(face, images, &settings, postprocess)?;
+        let report = evaluation::evaluate(&detections, iou_thresholds);
+        let result = SweepResult::from_report(settings, &report);
+        on_progress(results.len() + 1, total, &result);
+        results.push(result);
+    }
+
+    mark_pareto_front(&mut results);
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(mean_detection_ms: f64, average_precision: f64) -> SweepResult {
+        SweepResult {
+            settings: DetectorSettings::default(),
+            mean_detection_ms,
+            average_precision,
+            precision: 0.,
+            recall: 0.,
+            is_pareto_optimal: false,
+        }
+    }
+
+    /// `(ms, AP)` of the front, fastest first
+    fn front(results: &[SweepResult]) -> Vec<(f64, f64)> {
+        results
+            .iter()
+            .filter(|result| result.is_pareto_optimal)
+            .map(|result| (result.mean_detection_ms, result.average_precision))
+            .collect()
+    }
+
+    #[test]
+    fn dominated_points_are_not_on_the_front() {
+        let mut results = vec![
+            result(30., 0.9),
+            // Slower and less accurate than the first one
+            result(40., 0.8),
+            result(10., 0.5),
+            // Same speed as the one above, less accurate
+            result(10., 0.4),
+            result(20., 0.7),
+        ];
+        mark_pareto_front(&mut results);
+
+        assert_eq!(front(&results), vec![(10., 0.5), (20., 0.7), (30., 0.9)]);
+        // The front comes first, then the rest fastest first
+        assert!(!results[3].is_pareto_optimal && !results[4].is_pareto_optimal);
+        assert_eq!(results[3].mean_detection_ms, 10.);
+        assert_eq!(results[4].mean_detection_ms, 40.);
+    }
+
+    #[test]
+    fn ties_dont_dominate_each_other() {
+        let mut results = vec![result(10., 0.5), result(10., 0.5), result(20., 0.5)];
+        mark_pareto_front(&mut results);
+
+        // Equal points are both optimal, the slower one with the same AP isn't
+        assert_eq!(front(&results), vec![(10., 0.5), (10., 0.5)]);
+        assert!(!results[2].is_pareto_optimal);
+    }
+
+    #[test]
+    fn single_point_is_the_front() {
+        let mut results = vec![result(15., 0.)];
+        mark_pareto_front(&mut results);
+        assert!(results[0].is_pareto_optimal);
+
+        let mut results: Vec<SweepResult> = Vec::new();
+        mark_pareto_front(&mut results);
+        assert!(results.is_empty());
+    }
+}
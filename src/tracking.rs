@@ -0,0 +1,134 @@
+use opencv::core;
+
+/// An object (face) which has been seen in multiple frames
+#[derive(Debug, Clone)]
+pub struct TrackedObject {
+    pub id: u32,
+    /// Rect in the frame coordinates
+    pub rect: core::Rect,
+    pub centroid: core::Point,
+    /// Centroid in the previous matched frame, `None` for a new object
+    pub previous_centroid: Option<core::Point>,
+    /// How many frames in a row this object hasn't been matched
+    pub missing_frames: u32,
+}
+
+/// What changed after `CentroidTracker::update`
+#[derive(Debug, Default)]
+pub struct TrackUpdate {
+    /// IDs of the new objects
+    pub appeared: Vec<u32>,
+    /// Objects which have been missing for too long and have been removed
+    pub left: Vec<TrackedObject>,
+}
+
+/// Match the detections across frames by the distance between the centroids, every object
+/// keeps the same ID as long as it's matched (or only missing for a few frames).
+pub struct CentroidTracker {
+    next_id: u32,
+    objects: Vec<TrackedObject>,
+    /// Max centroid distance (in pixels) between 2 frames to treat as the same object
+    max_distance: f64,
+    /// How many frames an object can be missing before removing it
+    max_missing_frames: u32,
+}
+
+impl Default for CentroidTracker {
+    fn default() -> Self {
+        CentroidTracker::new(100., 10)
+    }
+}
+
+///
+fn rect_centroid(rect: &core::Rect) -> core::Point {
+    core::Point::new(rect.x + rect.width / 2, rect.y + rect.height / 2)
+}
+
+///
+fn distance(a: core::Point, b: core::Point) -> f64 {
+    (((a.x - b.x) as f64).powi(2) + ((a.y - b.y) as f64).powi(2)).sqrt()
+}
+
+impl CentroidTracker {
+    ///
+    pub fn new(max_distance: f64, max_missing_frames: u32) -> Self {
+        CentroidTracker {
+            next_id: 1,
+            objects: Vec::new(),
+            max_distance,
+            max_missing_frames,
+        }
+    }
+
+    /// All objects currently tracked, include the ones missing for a few frames
+    pub fn objects(&self) -> &[TrackedObject] {
+        &self.objects
+    }
+
+    /// Feed the detected rects (in frame coordinates) of the current frame
+    pub fn update(&mut self, detected_rects: &[core::Rect]) -> TrackUpdate {
+        let mut update = TrackUpdate::default();
+
+        // Greedy matching: the closest pairs go first
+        let mut candidate_pairs = Vec::new();
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (rect_index, rect) in detected_rects.iter().enumerate() {
+                let pair_distance = distance(object.centroid, rect_centroid(rect));
+                if pair_distance <= self.max_distance {
+                    candidate_pairs.push((pair_distance, object_index, rect_index));
+                }
+            }
+        }
+        candidate_pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut object_matched = vec![false; self.objects.len()];
+        let mut rect_matched = vec![false; detected_rects.len()];
+        for (_, object_index, rect_index) in candidate_pairs {
+            if object_matched[object_index] || rect_matched[rect_index] {
+                continue;
+            }
+            object_matched[object_index] = true;
+            rect_matched[rect_index] = true;
+
+            let object = &mut self.objects[object_index];
+            let rect = detected_rects[rect_index];
+            object.previous_centroid = Some(object.centroid);
+            object.centroid = rect_centroid(&rect);
+            object.rect = rect;
+            object.missing_frames = 0;
+        }
+
+        // Unmatched objects: missing in this frame
+        for (object, matched) in self.objects.iter_mut().zip(object_matched) {
+            if !matched {
+                object.missing_frames += 1;
+            }
+        }
+        let max_missing_frames = self.max_missing_frames;
+        let (left, kept): (Vec<_>, Vec<_>) = self
+            .objects
+            .drain(..)
+            .partition(|object| object.missing_frames > max_missing_frames);
+        self.objects = kept;
+        update.left = left;
+
+        // Unmatched rects: new objects
+        for (rect, matched) in detected_rects.iter().zip(rect_matched) {
+            if matched {
+                continue;
+            }
+            let id = self.next_id;
+            self.next_id += 1;
+            self.objects.push(TrackedObject {
+                id,
+                rect: *rect,
+                centroid: rect_centroid(rect),
+                previous_centroid: None,
+                missing_frames: 0,
+            });
+            update.appeared.push(id);
+        }
+
+        update
+    }
+}
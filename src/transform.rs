@@ -0,0 +1,332 @@
+use crate::error::{Context, Result};
+use opencv::{core, imgproc, prelude::*};
+
+/// How the detection image was made from the frame: any chain of crops, resizes, letterboxes
+/// and rotations, kept as one affine transform. Detections are in the detection image
+/// coordinates, `to_frame_rect` maps them back to the frame for drawing and export, e.g.
+/// `CoordinateTransform::new(frame_size).crop(roi).scale(0.25)` for the region of interest
+/// reduced to a quarter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoordinateTransform {
+    /// Frame to detection image, row major 2x3 affine matrix
+    matrix: [f64; 6],
+    /// The detection image size after all the steps
+    image_size: core::Size,
+}
+
+impl CoordinateTransform {
+    /// No step yet, the detection image is the frame
+    pub fn new(frame_size: core::Size) -> Self {
+        CoordinateTransform {
+            matrix: [1., 0., 0., 0., 1., 0.],
+            image_size: frame_size,
+        }
+    }
+
+    /// Apply `step` (a frame to detection image affine matrix) after the current ones
+    fn then(self, step: [f64; 6], image_size: core::Size) -> Self {
+        let [a, b, c, d, e, f] = self.matrix;
+        let [sa, sb, sc, sd, se, sf] = step;
+        CoordinateTransform {
+            matrix: [
+                sa * a + sb * d,
+                sa * b + sb * e,
+                sa * c + sb * f + sc,
+                sd * a + se * d,
+                sd * b + se * e,
+                sd * c + se * f + sf,
+            ],
+            image_size,
+        }
+    }
+
+    /// Detect inside `roi` only (in the current detection image coordinates)
+    pub fn crop(self, roi: core::Rect) -> Self {
+        self.then(
+            [1., 0., -roi.x as f64, 0., 1., -roi.y as f64],
+            core::Size::new(roi.width, roi.height),
+        )
+    }
+
+    /// Resize by `factor`, same as `imgproc::resize` with `fx` and `fy`
+    pub fn scale(self, factor: f64) -> Self {
+        let image_size = core::Size::new(
+            (self.image_size.width as f64 * factor).round() as i32,
+            (self.image_size.height as f64 * factor).round() as i32,
+        );
+        self.resize(image_size)
+    }
+
+    /// Stretch to `image_size`, the aspect ratio can change
+    pub fn resize(self, image_size: core::Size) -> Self {
+        let x_scale = image_size.width as f64 / self.image_size.width.max(1) as f64;
+        let y_scale = image_size.height as f64 / self.image_size.height.max(1) as f64;
+        self.then([x_scale, 0., 0., 0., y_scale, 0.], image_size)
+    }
+
+    /// Resize to fit into `image_size` keeping the aspect ratio, centered, the rest is padding
+    pub fn letterbox(self, image_size: core::Size) -> Self {
+        let scale = (image_size.width as f64 / self.image_size.width.max(1) as f64)
+            .min(image_size.height as f64 / self.image_size.height.max(1) as f64);
+        let x_padding = (image_size.width as f64 - self.image_size.width as f64 * scale) / 2.;
+        let y_padding = (image_size.height as f64 - self.image_size.height as f64 * scale) / 2.;
+        self.then([scale, 0., x_padding, 0., scale, y_padding], image_size)
+    }
+
+    /// Rotate counterclockwise by `angle` degrees around the image center, the image grows to
+    /// fit the whole rotated image (90 degrees is the same as `core::rotate`)
+    pub fn rotate(self, angle: f64) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (width, height) = (self.image_size.width as f64, self.image_size.height as f64);
+        let image_size = core::Size::new(
+            (width * cos.abs() + height * sin.abs()).round() as i32,
+            (width * sin.abs() + height * cos.abs()).round() as i32,
+        );
+        // Same matrix as `imgproc::get_rotation_matrix_2d`, moved into the grown image
+        let (center_x, center_y) = (width / 2., height / 2.);
+        self.then(
+            [
+                cos,
+                sin,
+                (1. - cos) * center_x - sin * center_y + (image_size.width as f64 - width) / 2.,
+                -sin,
+                cos,
+                sin * center_x + (1. - cos) * center_y + (image_size.height as f64 - height) / 2.,
+            ],
+            image_size,
+        )
+    }
+
+    /// The detection image size
+    pub fn image_size(&self) -> core::Size {
+        self.image_size
+    }
+
+    /// Detection image to frame
+    fn inverse_matrix(&self) -> [f64; 6] {
+        let [a, b, c, d, e, f] = self.matrix;
+        let determinant = a * e - b * d;
+        if determinant == 0. {
+            return [1., 0., 0., 0., 1., 0.];
+        }
+        let (ia, ib, id, ie) = (
+            e / determinant,
+            -b / determinant,
+            -d / determinant,
+            a / determinant,
+        );
+        [ia, ib, -(ia * c + ib * f), id, ie, -(id * c + ie * f)]
+    }
+
+    ///
+    fn map_rect(matrix: &[f64; 6], rect: core::Rect) -> core::Rect {
+        let [a, b, c, d, e, f] = *matrix;
+        let corners = [
+            (rect.x as f64, rect.y as f64),
+            ((rect.x + rect.width) as f64, rect.y as f64),
+            (rect.x as f64, (rect.y + rect.height) as f64),
+            ((rect.x + rect.width) as f64, (rect.y + rect.height) as f64),
+        ];
+        // The bounding box of the mapped corners, a rotated rect isn't axis-aligned anymore
+        let (mut left, mut top) = (f64::MAX, f64::MAX);
+        let (mut right, mut bottom) = (f64::MIN, f64::MIN);
+        for (x, y) in corners.iter() {
+            let (mapped_x, mapped_y) = (a * x + b * y + c, d * x + e * y + f);
+            left = left.min(mapped_x);
+            top = top.min(mapped_y);
+            right = right.max(mapped_x);
+            bottom = bottom.max(mapped_y);
+        }
+        let (left, top) = (left.round() as i32, top.round() as i32);
+        core::Rect::new(
+            left,
+            top,
+            right.round() as i32 - left,
+            bottom.round() as i32 - top,
+        )
+    }
+
+    /// Map a detection back to the frame coordinates
+    pub fn to_frame_rect(&self, rect: core::Rect) -> core::Rect {
+        Self::map_rect(&self.inverse_matrix(), rect)
+    }
+
+    /// Map a detection image point back to the frame coordinates
+    pub fn to_frame_point(&self, point: core::Point2f) -> core::Point2f {
+        let [a, b, c, d, e, f] = self.inverse_matrix();
+        let (x, y) = (point.x as f64, point.y as f64);
+        core::Point2f::new((a * x + b * y + c) as f32, (d * x + e * y + f) as f32)
+    }
+
+    /// Map a frame rect (e.g. a zone) into the detection image coordinates
+    pub fn to_detection_rect(&self, rect: core::Rect) -> core::Rect {
+        Self::map_rect(&self.matrix, rect)
+    }
+
+    /// Make the detection image from the frame, the area outside of the frame (letterbox
+    /// padding, rotation corners) is black
+    pub fn warp(&self, frame: &Mat) -> Result<Mat> {
+        let mut matrix =
+            Mat::new_rows_cols_with_default(2, 3, core::CV_64F, core::Scalar::all(0.))?;
+        for (index, value) in self.matrix.iter().enumerate() {
+            *matrix.at_2d_mut::<f64>(index as i32 / 3, index as i32 % 3)? = *value;
+        }
+
+        let mut detection_image = Mat::default()?;
+        imgproc::warp_affine(
+            frame,
+            &mut detection_image,
+            &matrix,
+            self.image_size,
+            imgproc::INTER_LINEAR,
+            core::BORDER_CONSTANT,
+            core::Scalar::default(),
+        )
+        .context("Warp frame into detection image")?;
+        Ok(detection_image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_size() -> core::Size {
+        core::Size::new(640, 480)
+    }
+
+    /// Frame to detection image and back gives the same rect
+    fn assert_round_trip(transform: &CoordinateTransform, rect: core::Rect) {
+        let detection_rect = transform.to_detection_rect(rect);
+        assert_eq!(
+            transform.to_frame_rect(detection_rect),
+            rect,
+            "{:?} -> {:?}",
+            rect,
+            detection_rect
+        );
+    }
+
+    #[test]
+    fn new_is_the_identity() {
+        let transform = CoordinateTransform::new(frame_size());
+        let rect = core::Rect::new(12, 34, 56, 78);
+
+        assert_eq!(transform.image_size(), frame_size());
+        assert_eq!(transform.to_detection_rect(rect), rect);
+        assert_eq!(transform.to_frame_rect(rect), rect);
+    }
+
+    #[test]
+    fn crop_moves_by_the_roi_origin() {
+        let transform =
+            CoordinateTransform::new(frame_size()).crop(core::Rect::new(100, 50, 200, 100));
+
+        assert_eq!(transform.image_size(), core::Size::new(200, 100));
+        assert_eq!(
+            transform.to_detection_rect(core::Rect::new(150, 60, 20, 30)),
+            core::Rect::new(50, 10, 20, 30)
+        );
+        assert_round_trip(&transform, core::Rect::new(150, 60, 20, 30));
+        assert_round_trip(&transform, core::Rect::new(0, 0, 640, 480));
+    }
+
+    #[test]
+    fn quarter_scale_is_the_old_times_four_mapping() {
+        // What the detection path did by hand before: every coordinate of the reduced image
+        // multiplied by 4
+        let scaled = CoordinateTransform::new(frame_size()).scale(0.25);
+        // What `reduce_for_detection` builds from the real sizes
+        let resized = CoordinateTransform::new(frame_size()).resize(core::Size::new(160, 120));
+        assert_eq!(scaled.image_size(), core::Size::new(160, 120));
+
+        for x in (0..160).step_by(7) {
+            for y in (0..120).step_by(5) {
+                for size in [1, 8, 13, 30, 64].iter() {
+                    let rect = core::Rect::new(x, y, *size, *size + 3);
+                    let old_mapping = core::Rect::new(x * 4, y * 4, *size * 4, (*size + 3) * 4);
+                    assert_eq!(scaled.to_frame_rect(rect), old_mapping);
+                    assert_eq!(resized.to_frame_rect(rect), old_mapping);
+                }
+            }
+        }
+        assert_eq!(
+            scaled.to_frame_point(core::Point2f::new(10.5, 20.)),
+            core::Point2f::new(42., 80.)
+        );
+    }
+
+    #[test]
+    fn resize_can_change_the_aspect_ratio() {
+        let transform = CoordinateTransform::new(frame_size()).resize(core::Size::new(320, 120));
+
+        assert_eq!(
+            transform.to_detection_rect(core::Rect::new(40, 80, 20, 40)),
+            core::Rect::new(20, 20, 10, 10)
+        );
+        assert_round_trip(&transform, core::Rect::new(40, 80, 20, 40));
+        assert_round_trip(&transform, core::Rect::new(0, 0, 640, 480));
+    }
+
+    #[test]
+    fn letterbox_keeps_the_aspect_ratio_and_pads() {
+        // Scale 416 / 640 = 0.65, the 312 pixel high image is centered: 52 pixels padding
+        let transform = CoordinateTransform::new(frame_size()).letterbox(core::Size::new(416, 416));
+
+        assert_eq!(transform.image_size(), core::Size::new(416, 416));
+        assert_eq!(
+            transform.to_detection_rect(core::Rect::new(0, 0, 640, 480)),
+            core::Rect::new(0, 52, 416, 312)
+        );
+        assert_eq!(
+            transform.to_detection_rect(core::Rect::new(100, 200, 200, 100)),
+            core::Rect::new(65, 182, 130, 65)
+        );
+        assert_round_trip(&transform, core::Rect::new(100, 200, 200, 100));
+        assert_round_trip(&transform, core::Rect::new(0, 0, 640, 480));
+    }
+
+    #[test]
+    fn rotate_grows_the_image_and_maps_rects() {
+        let transform = CoordinateTransform::new(frame_size()).rotate(90.);
+
+        // Counterclockwise: the top left corner goes to the bottom left
+        assert_eq!(transform.image_size(), core::Size::new(480, 640));
+        assert_eq!(
+            transform.to_detection_rect(core::Rect::new(0, 0, 10, 20)),
+            core::Rect::new(0, 630, 20, 10)
+        );
+        assert_round_trip(&transform, core::Rect::new(0, 0, 10, 20));
+        assert_round_trip(&transform, core::Rect::new(300, 200, 40, 60));
+
+        let transform = CoordinateTransform::new(frame_size()).rotate(180.);
+        assert_eq!(transform.image_size(), frame_size());
+        assert_eq!(
+            transform.to_detection_rect(core::Rect::new(0, 0, 10, 20)),
+            core::Rect::new(630, 460, 10, 20)
+        );
+    }
+
+    #[test]
+    fn combined_steps_round_trip() {
+        let transform = CoordinateTransform::new(frame_size())
+            .crop(core::Rect::new(80, 60, 480, 360))
+            .scale(0.5)
+            .rotate(90.)
+            .letterbox(core::Size::new(360, 360));
+
+        for rect in [
+            core::Rect::new(120, 100, 40, 60),
+            core::Rect::new(80, 60, 480, 360),
+            core::Rect::new(300, 240, 100, 80),
+        ]
+        .iter()
+        {
+            assert_round_trip(&transform, *rect);
+        }
+        // The crop origin goes to the bottom left of the rotated image, centered with 45 pixels
+        // padding on both sides
+        let point = transform.to_frame_point(core::Point2f::new(45., 360.));
+        assert!((point.x - 80.).abs() < 1e-3 && (point.y - 60.).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::error::Result;
+use opencv::{core, highgui, imgproc, prelude::*, types};
+use serde::{Deserialize, Serialize};
+
+/// How the zones affect the face detection
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneMode {
+    /// Everything outside the zones is blacked out before running the detection
+    DetectInside,
+    /// Detection runs on the whole frame, but only the faces inside the zones are reported
+    ReportInside,
+}
+
+impl Default for ZoneMode {
+    fn default() -> Self {
+        ZoneMode::ReportInside
+    }
+}
+
+/// A polygon in the original frame coordinates (not the reduced detection image)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Zone {
+    pub name: String,
+    pub points: Vec<(i32, i32)>,
+}
+
+impl Zone {
+    ///
+    fn to_vector_of_point(&self) -> types::VectorOfPoint {
+        self.points
+            .iter()
+            .map(|&(x, y)| core::Point::new(x, y))
+            .collect()
+    }
+}
+
+/// The `zones` section of the config file, e.g.:
+///
+/// ```json
+/// "zones": {
+///     "mode": "detect_inside",
+///     "zones": [
+///         { "name": "door", "points": [[100, 80], [400, 80], [400, 600], [100, 600]] }
+///     ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZoneSettings {
+    pub mode: ZoneMode,
+    pub zones: Vec<Zone>,
+}
+
+///
+fn zones_to_polygons(zones: &[Zone]) -> types::VectorOfVectorOfPoint {
+    zones
+        .iter()
+        .filter(|zone| zone.points.len() >= 3)
+        .map(|zone| zone.to_vector_of_point())
+        .collect()
+}
+
+/// Black out everything outside the zones, so the detection only "sees" the inside.
+pub fn mask_frame_outside_zones(frame: &Mat, zones: &[Zone]) -> Result<Mat> {
+    let mut mask = Mat::new_size_with_default(
+        frame.size()?,
+        core::CV_8UC1,
+        core::Scalar::new(0., 0., 0., 0.),
+    )?;
+    imgproc::fill_poly(
+        &mut mask,
+        &zones_to_polygons(zones),
+        core::Scalar::new(255., 255., 255., 0.),
+        imgproc::LINE_8,
+        0,
+        core::Point::new(0, 0),
+    )?;
+
+    // The newly allocated dest will be filled with zero (black) before copying
+    let mut masked_frame = Mat::default()?;
+    frame.copy_to_masked(&mut masked_frame, &mask)?;
+    Ok(masked_frame)
+}
+
+/// Return `true` if the point is inside (or on the edge of) any zone
+pub fn is_point_inside_zones(point: core::Point, zones: &[Zone]) -> Result<bool> {
+    for polygon in zones_to_polygons(zones) {
+        let distance = imgproc::point_polygon_test(
+            &polygon,
+            core::Point2f::new(point.x as f32, point.y as f32),
+            false,
+        )?;
+        if distance >= 0. {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// `Mat::roi` fails if the rect goes out of the frame
+fn intersect_rect(a: core::Rect, b: core::Rect) -> core::Rect {
+    let x = a.x.max(b.x);
+    let y = a.y.max(b.y);
+    core::Rect {
+        x,
+        y,
+        width: (a.x + a.width).min(b.x + b.width) - x,
+        height: (a.y + a.height).min(b.y + b.height) - y,
+    }
+}
+
+/// Draw the zones with a transparent fill, same blending technique as the info panel
+pub fn draw_zones_on_frame(frame: &mut Mat, zones: &[Zone]) -> Result<()> {
+    let zone_color = core::Scalar::new(255., 191., 0., -1.); // (Blue, Green, Red, Alpha)
+    let frame_area = core::Rect {
+        x: 0,
+        y: 0,
+        width: frame.cols(),
+        height: frame.rows(),
+    };
+
+    for zone in zones.iter().filter(|zone| zone.points.len() >= 3) {
+        let polygon = zone.to_vector_of_point();
+
+        // Only blend the area covered by the zone: `ROI`(Region Of Interest) is just a mut
+        // reference to the original frame, no data copied.
+        let roi = intersect_rect(imgproc::bounding_rect(&polygon)?, frame_area);
+        if roi.width <= 0 || roi.height <= 0 {
+            continue;
+        }
+        let mut zone_roi_ref = core::Mat::roi(frame, roi)?;
+
+        // The background starts as a copy of the ROI, so the pixels outside the polygon stay
+        // the same after blending.
+        let mut zone_background = zone_roi_ref.clone();
+        let mut polygons = types::VectorOfVectorOfPoint::new();
+        polygons.push(polygon.clone());
+        imgproc::fill_poly(
+            &mut zone_background,
+            &polygons,
+            zone_color,
+            imgproc::LINE_AA,
+            0,
+            core::Point::new(-roi.x, -roi.y), // Move the polygon into ROI coordinates
+        )?;
+
+        core::add_weighted(
+            &zone_roi_ref.clone(), // Src image
+            0.7,                   // Src image alpha
+            &zone_background,      // Copy image
+            0.3,                   // Copy image alpha
+            0.,                    // Gamma
+            &mut zone_roi_ref,     // The merge dest image
+            -1,
+        )?;
+
+        // Solid border and the zone name
+        imgproc::polylines(frame, &polygons, true, zone_color, 2, imgproc::LINE_AA, 0)?;
+        imgproc::put_text(
+            frame,
+            &zone.name,
+            core::Point::new(roi.x + 4, roi.y + 20),
+            imgproc::FONT_HERSHEY_DUPLEX,
+            0.6,
+            zone_color,
+            1,
+            imgproc::LINE_AA,
+            false,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Draw zones with the mouse in the preview window:
+///
+/// - Left click  : add a point to the zone in progress
+/// - Right click : close the zone in progress (needs 3 points at least)
+#[derive(Debug, Default)]
+pub struct ZoneEditor {
+    pub zones: Vec<Zone>,
+    drawing_points: Vec<(i32, i32)>,
+    cursor: Option<(i32, i32)>,
+}
+
+impl ZoneEditor {
+    ///
+    pub fn new(zones: Vec<Zone>) -> Self {
+        ZoneEditor {
+            zones,
+            ..ZoneEditor::default()
+        }
+    }
+
+    /// Call it from the `highgui::set_mouse_callback` closure
+    pub fn on_mouse_event(&mut self, event: i32, x: i32, y: i32) {
+        match event {
+            highgui::EVENT_LBUTTONDOWN => self.drawing_points.push((x, y)),
+            highgui::EVENT_RBUTTONDOWN => {
+                if self.drawing_points.len() >= 3 {
+                    let name = format!("zone-{}", self.zones.len() + 1);
+                    println!("Zone added: {}", name);
+                    self.zones.push(Zone {
+                        name,
+                        points: self.drawing_points.drain(..).collect(),
+                    });
+                }
+            }
+            highgui::EVENT_MOUSEMOVE => self.cursor = Some((x, y)),
+            _ => {}
+        }
+    }
+
+    /// Remove all zones, include the one in progress
+    pub fn clear(&mut self) {
+        self.zones.clear();
+        self.drawing_points.clear();
+    }
+
+    /// Draw the finished zones and the one in progress (follow the mouse cursor)
+    pub fn draw_on_frame(&self, frame: &mut Mat) -> Result<()> {
+        draw_zones_on_frame(frame, &self.zones)?;
+
+        if self.drawing_points.is_empty() {
+            return Ok(());
+        }
+
+        let mut points: types::VectorOfPoint = self
+            .drawing_points
+            .iter()
+            .map(|&(x, y)| core::Point::new(x, y))
+            .collect();
+        if let Some((x, y)) = self.cursor {
+            points.push(core::Point::new(x, y));
+        }
+        let mut polygons = types::VectorOfVectorOfPoint::new();
+        polygons.push(points);
+
+        imgproc::polylines(
+            frame,
+            &polygons,
+            false,
+            core::Scalar::new(0., 255., 255., -1.),
+            1,
+            imgproc::LINE_AA,
+            0,
+        )?;
+
+        Ok(())
+    }
+}
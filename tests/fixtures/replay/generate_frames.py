@@ -0,0 +1,70 @@
+#!/usr/bin/env python3
+"""Generate the replay fixture frames: a simple cartoon face moving across a gradient.
+
+Only the standard library is used, so the fixture can be re-created anywhere:
+
+    python3 tests/fixtures/replay/generate_frames.py
+"""
+import os
+import struct
+import zlib
+
+WIDTH, HEIGHT = 640, 480
+FRAME_COUNT = 8
+OUTPUT_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "frames")
+
+
+def write_png(path, pixels):
+    """`pixels` is a list of rows, every row a bytearray of RGB values."""
+    raw = b"".join(b"\x00" + bytes(row) for row in pixels)
+
+    def chunk(tag, data):
+        body = tag + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body) & 0xFFFFFFFF)
+
+    with open(path, "wb") as png:
+        png.write(b"\x89PNG\r\n\x1a\n")
+        png.write(chunk(b"IHDR", struct.pack(">IIBBBBB", WIDTH, HEIGHT, 8, 2, 0, 0, 0)))
+        png.write(chunk(b"IDAT", zlib.compress(raw, 9)))
+        png.write(chunk(b"IEND", b""))
+
+
+def inside_ellipse(x, y, cx, cy, rx, ry):
+    return ((x - cx) / rx) ** 2 + ((y - cy) / ry) ** 2 <= 1.0
+
+
+def draw_frame(index):
+    cx = 180 + index * 40
+    cy = 240
+    pixels = []
+    for y in range(HEIGHT):
+        row = bytearray()
+        for x in range(WIDTH):
+            shade = 40 + y * 60 // HEIGHT
+            color = (shade, shade, shade + 20)
+            if inside_ellipse(x, y, cx, cy, 90, 115):
+                color = (224, 172, 140)
+                # Eyes, eyebrows, nose and mouth
+                if inside_ellipse(x, y, cx - 35, cy - 30, 14, 9) or inside_ellipse(
+                    x, y, cx + 35, cy - 30, 14, 9
+                ):
+                    color = (40, 30, 30)
+                elif cy - 55 <= y <= cy - 48 and 15 <= abs(x - cx) <= 55:
+                    color = (70, 50, 40)
+                elif abs(x - cx) <= 6 and cy - 10 <= y <= cy + 25:
+                    color = (200, 140, 110)
+                elif inside_ellipse(x, y, cx, cy + 60, 35, 10):
+                    color = (150, 60, 60)
+            row.extend(color)
+        pixels.append(row)
+    return pixels
+
+
+def main():
+    os.makedirs(OUTPUT_DIR, exist_ok=True)
+    for index in range(FRAME_COUNT):
+        write_png(os.path.join(OUTPUT_DIR, "frame_{:03}.png".format(index)), draw_frame(index))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,337 @@
+//! Replay the committed fixture frames through face detection and the overlays headlessly,
+//! then compare with the golden files:
+//!
+//! - `tests/fixtures/replay/golden/detections.json`: the faces of every frame, matched by IoU
+//! - `tests/fixtures/replay/golden/*.png`: the annotated frames, compared by PSNR and SSIM
+//!
+//! PSNR catches any pixel noise but barely moves for a small shifted box (a few hundred pixels
+//! out of 300k), SSIM compares the local structure, so it also fails on a moved or resized box.
+//!
+//! After an intended change (e.g. to `detect_faces` or the info panel), review the differences
+//! and re-create the golden files with:
+//!
+//! ```bash
+//! UPDATE_GOLDEN=1 cargo test --test replay
+//! ```
+
+use opencv::{core, imgcodecs, imgproc, prelude::*, types};
+use rust_opencv::{
+    detection::{self, DetectorSettings},
+    export::FaceRecord,
+    overlay,
+    postprocess::PostProcessSettings,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Two detections of the same face must overlap at least this much
+const MIN_FACE_IOU: f64 = 0.8;
+/// The annotated frames must be at least this close to the golden ones (identical gives 361)
+const MIN_FRAME_PSNR: f64 = 40.;
+/// Mean SSIM of the annotated frames against the golden ones (identical gives 1.0)
+const MIN_FRAME_SSIM: f64 = 0.98;
+/// The info panel shows a fixed FPS, the measured one would differ on every run
+const REPLAY_FPS: i32 = 30;
+
+///
+#[derive(Debug, Serialize, Deserialize)]
+struct FrameDetections {
+    frame: String,
+    faces: Vec<FaceRecord>,
+}
+
+///
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay")
+}
+
+///
+fn is_updating_golden() -> bool {
+    env::var("UPDATE_GOLDEN")
+        .map(|value| value == "1")
+        .unwrap_or(false)
+}
+
+/// The fixture frames sorted by file name
+fn fixture_frames() -> Vec<PathBuf> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(fixture_dir().join("frames"))
+        .expect("Fixture frames folder is missing")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().map(|ext| ext == "png").unwrap_or(false))
+        .collect();
+    frames.sort();
+    assert!(!frames.is_empty(), "No fixture frames found");
+    frames
+}
+
+/// Same steps as the capture loop: detect (with the post-processing), draw the faces, then the
+/// info panel
+fn replay_frame(
+    face: &mut opencv::objdetect::CascadeClassifier,
+    frame_path: &Path,
+) -> (Mat, Vec<FaceRecord>) {
+    let mut frame = imgcodecs::imread(&frame_path.to_string_lossy(), imgcodecs::IMREAD_COLOR)
+        .expect("Unable to read fixture frame");
+    assert!(
+        frame.cols() > 0,
+        "Empty fixture frame: {}",
+        frame_path.display()
+    );
+
+    let detected_faces = detection::detect_faces(
+        face,
+        &frame,
+        &DetectorSettings::default(),
+        &PostProcessSettings::default(),
+    )
+    .unwrap();
+    let face_rects: Vec<core::Rect> = detected_faces
+        .iter()
+        .map(|detected_face| detected_face.rect)
+        .collect();
+    overlay::draw_face_rects(&mut frame, &face_rects).unwrap();
+    let (frame_width, frame_height) = (frame.cols(), frame.rows());
+    overlay::draw_info_panel(
+        &mut frame,
+        frame_width,
+        frame_height,
+        REPLAY_FPS,
+        detected_faces.len() as u8,
+        &[],
+    )
+    .unwrap();
+
+    let faces = face_rects
+        .into_iter()
+        .map(|rect| FaceRecord {
+            track_id: None,
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+            features: None,
+            recognition: None,
+        })
+        .collect();
+    (frame, faces)
+}
+
+///
+fn intersection_over_union(a: &FaceRecord, b: &FaceRecord) -> f64 {
+    let left = a.x.max(b.x);
+    let top = a.y.max(b.y);
+    let right = (a.x + a.width).min(b.x + b.width);
+    let bottom = (a.y + a.height).min(b.y + b.height);
+    let intersection = ((right - left).max(0) * (bottom - top).max(0)) as f64;
+    let union = (a.width * a.height + b.width * b.height) as f64 - intersection;
+    if union > 0. {
+        intersection / union
+    } else {
+        0.
+    }
+}
+
+/// Mean SSIM of the grayscale images, the usual 11x11 Gaussian window with sigma 1.5
+fn mean_ssim(a: &Mat, b: &Mat) -> f64 {
+    let to_float_gray = |image: &Mat| {
+        let mut gray = Mat::default().unwrap();
+        imgproc::cvt_color(image, &mut gray, imgproc::COLOR_BGR2GRAY, 0).unwrap();
+        let mut float_gray = Mat::default().unwrap();
+        gray.convert_to(&mut float_gray, core::CV_64F, 1., 0.)
+            .unwrap();
+        float_gray
+    };
+    let blur = |image: &Mat| {
+        let mut blurred = Mat::default().unwrap();
+        imgproc::gaussian_blur(
+            image,
+            &mut blurred,
+            core::Size::new(11, 11),
+            1.5,
+            0.,
+            core::BORDER_DEFAULT,
+        )
+        .unwrap();
+        blurred
+    };
+    let multiply = |a: &Mat, b: &Mat| {
+        let mut product = Mat::default().unwrap();
+        core::multiply(a, b, &mut product, 1., -1).unwrap();
+        product
+    };
+    let subtract = |a: &Mat, b: &Mat| {
+        let mut difference = Mat::default().unwrap();
+        core::subtract(a, b, &mut difference, &Mat::default().unwrap(), -1).unwrap();
+        difference
+    };
+    let add = |a: &Mat, b: &Mat| {
+        let mut sum = Mat::default().unwrap();
+        core::add(a, b, &mut sum, &Mat::default().unwrap(), -1).unwrap();
+        sum
+    };
+    // `alpha * image + beta`
+    let scale = |image: &Mat, alpha: f64, beta: f64| {
+        let mut scaled = Mat::default().unwrap();
+        image.convert_to(&mut scaled, -1, alpha, beta).unwrap();
+        scaled
+    };
+
+    // The stabilizing constants for 8 bit images: (0.01 * 255)^2 and (0.03 * 255)^2
+    let (c1, c2) = (6.5025, 58.5225);
+    let (x, y) = (to_float_gray(a), to_float_gray(b));
+    let (mu_x, mu_y) = (blur(&x), blur(&y));
+    let (mu_x2, mu_y2, mu_xy) = (
+        multiply(&mu_x, &mu_x),
+        multiply(&mu_y, &mu_y),
+        multiply(&mu_x, &mu_y),
+    );
+    let sigma_x2 = subtract(&blur(&multiply(&x, &x)), &mu_x2);
+    let sigma_y2 = subtract(&blur(&multiply(&y, &y)), &mu_y2);
+    let sigma_xy = subtract(&blur(&multiply(&x, &y)), &mu_xy);
+
+    let numerator = multiply(&scale(&mu_xy, 2., c1), &scale(&sigma_xy, 2., c2));
+    let denominator = multiply(
+        &scale(&add(&mu_x2, &mu_y2), 1., c1),
+        &scale(&add(&sigma_x2, &sigma_y2), 1., c2),
+    );
+    let mut ssim_map = Mat::default().unwrap();
+    core::divide2(&numerator, &denominator, &mut ssim_map, 1., -1).unwrap();
+    core::mean(&ssim_map, &Mat::default().unwrap()).unwrap().0[0]
+}
+
+/// Every golden face needs a detected face overlapping it, and nothing extra
+fn assert_faces_match(frame: &str, golden: &[FaceRecord], detected: &[FaceRecord]) {
+    assert_eq!(
+        golden.len(),
+        detected.len(),
+        "{}: expected {} faces, detected {}: {:?}",
+        frame,
+        golden.len(),
+        detected.len(),
+        detected
+    );
+    for golden_face in golden {
+        let best_iou = detected
+            .iter()
+            .map(|face| intersection_over_union(golden_face, face))
+            .fold(0., f64::max);
+        assert!(
+            best_iou >= MIN_FACE_IOU,
+            "{}: golden face {:?} only matched with IoU {:.2}",
+            frame,
+            golden_face,
+            best_iou
+        );
+    }
+}
+
+#[test]
+fn replay_matches_golden_detections_and_frames() {
+    let golden_dir = fixture_dir().join("golden");
+    let golden_detections_file = golden_dir.join("detections.json");
+    let mut face = detection::load_face_classifier().expect("Unable to load face classifier");
+
+    let mut replayed = Vec::new();
+    for frame_path in fixture_frames() {
+        let frame_name = frame_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let (annotated_frame, faces) = replay_frame(&mut face, &frame_path);
+        replayed.push((frame_name, annotated_frame, faces));
+    }
+    // An empty golden file would match a detector that finds nothing at all
+    let replayed_face_count: usize = replayed.iter().map(|(_, _, faces)| faces.len()).sum();
+    assert!(
+        replayed_face_count > 0,
+        "No face detected in any fixture frame, the replay would guard nothing"
+    );
+
+    // Updating still checks everything below against the files just written, e.g. a PNG that
+    // doesn't read back the same fails right away rather than on the next run
+    if is_updating_golden() {
+        fs::create_dir_all(&golden_dir).unwrap();
+        for (frame_name, annotated_frame, _) in replayed.iter() {
+            let saved = imgcodecs::imwrite(
+                &golden_dir.join(frame_name).to_string_lossy(),
+                annotated_frame,
+                &types::VectorOfi32::new(),
+            )
+            .unwrap();
+            assert!(saved, "Unable to write golden frame: {}", frame_name);
+        }
+        let detections: Vec<FrameDetections> = replayed
+            .iter()
+            .map(|(frame, _, faces)| FrameDetections {
+                frame: frame.clone(),
+                faces: faces.clone(),
+            })
+            .collect();
+        fs::write(
+            &golden_detections_file,
+            serde_json::to_string_pretty(&detections).unwrap(),
+        )
+        .unwrap();
+        println!("Golden files updated: {}", golden_dir.display());
+    }
+
+    let golden_detections: Vec<FrameDetections> = serde_json::from_str(
+        &fs::read_to_string(&golden_detections_file).unwrap_or_else(|_| {
+            panic!(
+                "Missing {}, create it with `UPDATE_GOLDEN=1 cargo test --test replay`",
+                golden_detections_file.display()
+            )
+        }),
+    )
+    .expect("Invalid golden detections");
+    assert_eq!(
+        golden_detections.len(),
+        replayed.len(),
+        "The fixture frames changed, update the golden files"
+    );
+    assert!(
+        golden_detections
+            .iter()
+            .any(|detections| !detections.faces.is_empty()),
+        "The golden detections have no face at all"
+    );
+
+    for ((frame_name, annotated_frame, faces), golden) in
+        replayed.iter().zip(golden_detections.iter())
+    {
+        assert_eq!(frame_name, &golden.frame);
+        assert_faces_match(frame_name, &golden.faces, faces);
+
+        let golden_frame = imgcodecs::imread(
+            &golden_dir.join(frame_name).to_string_lossy(),
+            imgcodecs::IMREAD_COLOR,
+        )
+        .unwrap();
+        assert_eq!(
+            golden_frame.size().unwrap(),
+            annotated_frame.size().unwrap(),
+            "{}: golden frame size differs",
+            frame_name
+        );
+        let psnr = core::psnr(annotated_frame, &golden_frame, 255.).unwrap();
+        assert!(
+            psnr >= MIN_FRAME_PSNR,
+            "{}: annotated frame differs from golden, PSNR {:.1} dB < {} dB",
+            frame_name,
+            psnr,
+            MIN_FRAME_PSNR
+        );
+        let ssim = mean_ssim(annotated_frame, &golden_frame);
+        assert!(
+            ssim >= MIN_FRAME_SSIM,
+            "{}: annotated frame differs from golden, SSIM {:.3} < {}",
+            frame_name,
+            ssim,
+            MIN_FRAME_SSIM
+        );
+    }
+}